@@ -4,4 +4,12 @@ define_client_error!(InvalidRequestError, "Request is invalid: {details}.", { de
 define_client_error!(InvalidRouteError, "Route '{route:?}' does not exist.", { route: Option<String> });
 define_sensitive_error!(UnauthorizedError, "Not authorized to access this resource.");
 define_internal_error!(EncodingError, "Failed to encode payload data (failed at: '{component}').", { component: &str });
+define_internal_error!(DecodingError, "Failed to decode payload data (failed at: '{component}').", { component: &str });
 define_client_error!(InvalidCrudRequestParameters, "Invalid CRUD request parameters: {details}.", { details: &str });
+define_internal_error!(InvalidRoutingConfig, "Invalid routing configuration: {details}.", { details: &str });
+define_internal_error!(InvalidCorsConfig, "Invalid CORS configuration: {details}.", { details: &str });
+define_internal_error!(InvalidJwtConfig, "Invalid JWT verification configuration: {details}.", { details: &str });
+define_client_error!(InvalidWebSocketRouteError, "WebSocket route '{route_key:?}' does not exist.", { route_key: Option<String> });
+define_internal_error!(WebSocketPostError, "Failed to send message to WebSocket connection '{connection_id}'.", { connection_id: &str });
+define_internal_error!(ApiKeyStoreError, "Failed to look up API key in '{store}'.", { store: &str });
+define_internal_error!(ApiRequestError, "Request to '{url}' failed.", { url: &str });
@@ -1,6 +1,11 @@
 #[macro_export]
 macro_rules! aws_lambda_handle_raw {
     ($handler:expr) => {
+        $crate::aws_lambda_handle_raw!($handler, async {});
+    };
+    // Optionally, an async block can be given to run once per cold start,
+    // before any events are handled.
+    ($handler:expr, $init:expr) => {
         #[::tokio::main]
         async fn main() -> Result<(), ::lambda_runtime::Error> {
             ::tracing_subscriber::fmt()
@@ -11,6 +16,8 @@ macro_rules! aws_lambda_handle_raw {
                 .without_time()
                 .init();
 
+            $init.await;
+
             ::lambda_runtime::run(::lambda_runtime::service_fn($handler)).await
         }
     };
@@ -1,16 +1,34 @@
 #[macro_export]
 macro_rules! aws_lambda_handle_with_function {
     ($validator:path, $func:path, $request_data_type:ty) => {
-        async fn __handler(
-            event: ::lambda_runtime::LambdaEvent<
-                ::aws_lambda_events::apigw::ApiGatewayProxyRequest,
-            >,
+        $crate::aws_lambda_handle_with_function!(
+            event = ::aws_lambda_events::apigw::ApiGatewayProxyRequest;
+            $validator, $func, $request_data_type
+        );
+    };
+    ($validator:path, $func:path) => {
+        $crate::aws_lambda_handle_with_function!(
+            event = ::aws_lambda_events::apigw::ApiGatewayProxyRequest;
+            $validator, $func
+        );
+    };
+    (RAW $func:path) => {
+        $crate::aws_lambda_handle_with_function!(
+            event = ::aws_lambda_events::apigw::ApiGatewayProxyRequest;
+            RAW $func
+        );
+    };
+    // The event type can be any `ApiEvent` (e.g.
+    // `::aws_lambda_events::apigw::ApiGatewayV2httpRequest` for HTTP APIs).
+    (event = $event:ty; $validator:path, $func:path, $request_data_type:ty) => {
+        async fn __handle(
+            request: &::aws_lambda_events::apigw::ApiGatewayProxyRequest,
         ) -> Result<::aws_lambda_events::apigw::ApiGatewayProxyResponse, ::lambda_runtime::Error> {
-            let metadata = match $crate::parse_request_metadata(&event.payload) {
+            let metadata = match $crate::parse_request_metadata(request) {
                 Ok(m) => m,
                 e @ Err(_) => return $crate::build_result(e),
             };
-            match $crate::parse_request_data::<$request_data_type>(&event.payload) {
+            match $crate::parse_request_data::<$request_data_type>(request) {
                 Ok(obj) => match $validator(&obj, metadata) {
                     Ok(_) => $crate::build_result($func(obj).await),
                     e @ Err(_) => $crate::build_result(e),
@@ -18,15 +36,13 @@ macro_rules! aws_lambda_handle_with_function {
                 e @ Err(_) => $crate::build_result(e),
             }
         }
-        $crate::aws_lambda_handle_raw!(__handler);
+        $crate::aws_lambda_handle_with_function!(@handler $event);
     };
-    ($validator:path, $func:path) => {
-        async fn __handler(
-            event: ::lambda_runtime::LambdaEvent<
-                ::aws_lambda_events::apigw::ApiGatewayProxyRequest,
-            >,
+    (event = $event:ty; $validator:path, $func:path) => {
+        async fn __handle(
+            request: &::aws_lambda_events::apigw::ApiGatewayProxyRequest,
         ) -> Result<::aws_lambda_events::apigw::ApiGatewayProxyResponse, ::lambda_runtime::Error> {
-            let metadata = match $crate::parse_request_metadata(&event.payload) {
+            let metadata = match $crate::parse_request_metadata(request) {
                 Ok(m) => m,
                 e @ Err(_) => return $crate::build_result(e),
             };
@@ -35,19 +51,31 @@ macro_rules! aws_lambda_handle_with_function {
                 e @ Err(_) => $crate::build_result(e),
             }
         }
-        $crate::aws_lambda_handle_raw!(__handler);
+        $crate::aws_lambda_handle_with_function!(@handler $event);
     };
-    (RAW $func:path) => {
-        async fn __handler(
-            event: ::lambda_runtime::LambdaEvent<
-                ::aws_lambda_events::apigw::ApiGatewayProxyRequest,
-            >,
+    (event = $event:ty; RAW $func:path) => {
+        async fn __handle(
+            request: &::aws_lambda_events::apigw::ApiGatewayProxyRequest,
         ) -> Result<::aws_lambda_events::apigw::ApiGatewayProxyResponse, ::lambda_runtime::Error> {
-            match $func(event.payload.headers, event.payload.body).await {
+            match $func(request.headers.clone(), request.body.clone()).await {
                 Ok(result) => Ok($crate::build_simple(result)),
                 e @ Err(_) => $crate::build_result(e),
             }
         }
+        $crate::aws_lambda_handle_with_function!(@handler $event);
+    };
+    // Internal: converts the event into a REST API request, and the response
+    // back (see `ApiEvent`).
+    (@handler $event:ty) => {
+        async fn __handler(
+            event: ::lambda_runtime::LambdaEvent<$event>,
+        ) -> Result<<$event as $crate::ApiEvent>::Response, ::lambda_runtime::Error> {
+            let request = $crate::ApiEvent::into_proxy_request(event.payload);
+            let response = __handle(&request).await?;
+            Ok(<$event as $crate::ApiEvent>::from_proxy_response(
+                response, &request,
+            ))
+        }
         $crate::aws_lambda_handle_raw!(__handler);
     };
 }
@@ -0,0 +1,369 @@
+use std::marker::PhantomData;
+
+use fractic_aws_dynamo::schema::{DynamoObject, PkSk};
+use fractic_server_error::ServerError;
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    Method, Url,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{
+    errors::{ApiRequestError, EncodingError, InvalidRequestError},
+    handle_with_router::{
+        path_templates::fill_template,
+        routes::{CrudRoute, FunctionRoute},
+        routing_config::split_route_key,
+        std::crud_specs::{ItemRef, ItemRefs},
+    },
+    shared::response_decoding::{decode_response, ClientError},
+};
+
+// API client.
+// --------------------------------------------------
+//
+// Calls a deployed `RoutingConfig` over HTTP, for service-to-service calls and
+// integration tests. Routes are identified by their shared definitions (see
+// `FunctionRoute` and `CrudRoute`), and responses are decoded from the
+// envelope, with errors forwarded by the API (`ok == false`, problem details,
+// or non-200 responses) returned as `ClientError::Api`.
+
+pub struct ApiClient {
+    /// Base URL of the API stage (e.g. "https://api.example.com/prod").
+    pub base_url: String,
+    /// Sent as the Authorization header (e.g. a Cognito ID token).
+    pub token: Option<String>,
+    http: reqwest::Client,
+}
+
+impl ApiClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.to_string(),
+            token: None,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Uses a custom HTTP client (e.g. with timeouts or proxies configured).
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    pub async fn call<R>(&self, input: &R::Input) -> Result<R::Output, ClientError>
+    where
+        R: FunctionRoute<Path = ()>,
+    {
+        self.call_with_path::<R>(&(), input).await
+    }
+
+    /// Like `call`, for templated routes (e.g. "users/{user_id}"). Each
+    /// `{param}` segment is replaced by the field of the same name in `path`.
+    pub async fn call_with_path<R: FunctionRoute>(
+        &self,
+        path: &R::Path,
+        input: &R::Input,
+    ) -> Result<R::Output, ClientError> {
+        let (method, template) = split_route_key(R::KEY);
+        let path_params = string_pairs(path, "path")?;
+        let path_params: Vec<(&str, &str)> = path_params
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let segments = fill_template(template, &path_params).map_err(|name| {
+            InvalidRequestError::new(&format!("missing path parameter '{name}'"))
+        })?;
+        // GET functions read their input from the query string (see
+        // `parse_request_input`).
+        if method == Method::GET {
+            let query = string_pairs(input, "query")?;
+            self.send(method, &segments, &query, None).await
+        } else {
+            let body = to_json(input)?;
+            self.send(method, &segments, &[] as &[(&str, String)], Some(body))
+                .await
+        }
+    }
+
+    pub fn crud<R: CrudRoute>(&self) -> CrudClient<'_, R> {
+        CrudClient {
+            client: self,
+            route: PhantomData,
+        }
+    }
+
+    async fn send<T: DeserializeOwned, Q: Serialize + ?Sized>(
+        &self,
+        method: Method,
+        segments: &[&str],
+        query: &Q,
+        body: Option<String>,
+    ) -> Result<T, ClientError> {
+        let mut url = Url::parse(&self.base_url)
+            .map_err(|e| ApiRequestError::with_debug(&self.base_url, &e))?;
+        url.path_segments_mut()
+            .map_err(|_| ApiRequestError::new(&self.base_url))?
+            .pop_if_empty()
+            .extend(segments);
+        let mut request = self.http.request(method, url.clone()).query(query);
+        if let Some(token) = &self.token {
+            request = request.header(AUTHORIZATION, token);
+        }
+        if let Some(body) = body {
+            request = request
+                .header(CONTENT_TYPE, "application/json; charset=utf-8")
+                .body(body);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| ApiRequestError::with_debug(url.as_str(), &e))?;
+        let status_code = i64::from(response.status().as_u16());
+        let headers = response.headers().clone();
+        let text = response
+            .text()
+            .await
+            .map_err(|e| ApiRequestError::with_debug(url.as_str(), &e))?;
+        decode_response(status_code, &headers, &text)
+    }
+}
+
+/// Typed helpers for the operations of a CRUD route. Operations not allowed by
+/// the route's access configuration return the API's unauthorized error.
+pub struct CrudClient<'a, R: CrudRoute> {
+    client: &'a ApiClient,
+    route: PhantomData<R>,
+}
+
+impl<R: CrudRoute> CrudClient<'_, R>
+where
+    <R::Item as DynamoObject>::Data: Serialize,
+{
+    pub async fn list(&self, parent_id: Option<&PkSk>) -> Result<R::Output, ClientError> {
+        let mut query = vec![("all", "true".to_string())];
+        push_id(&mut query, "parent_id", parent_id);
+        self.send(Method::GET, &query, None).await
+    }
+
+    pub async fn read(&self, item_ref: &ItemRef) -> Result<R::Output, ClientError> {
+        self.send(Method::GET, &item_ref_query(item_ref), None)
+            .await
+    }
+
+    pub async fn read_many(&self, item_refs: &ItemRefs) -> Result<R::Output, ClientError> {
+        self.send(Method::GET, &item_refs_query(item_refs), None)
+            .await
+    }
+
+    pub async fn create(
+        &self,
+        parent_id: Option<&PkSk>,
+        after: Option<&PkSk>,
+        data: &<R::Item as DynamoObject>::Data,
+    ) -> Result<R::Output, ClientError> {
+        let mut query = Vec::new();
+        push_id(&mut query, "parent_id", parent_id);
+        push_id(&mut query, "after", after);
+        self.send(Method::POST, &query, Some(to_json(data)?)).await
+    }
+
+    pub async fn create_many(
+        &self,
+        parent_id: Option<&PkSk>,
+        after: Option<&PkSk>,
+        data: &[<R::Item as DynamoObject>::Data],
+    ) -> Result<R::Output, ClientError> {
+        let mut query = Vec::new();
+        push_id(&mut query, "parent_id", parent_id);
+        push_id(&mut query, "after", after);
+        self.send(Method::POST, &query, Some(to_json(data)?)).await
+    }
+
+    pub async fn update(&self, item: &R::Item) -> Result<R::Output, ClientError> {
+        self.send(Method::PUT, &[], Some(to_json(item)?)).await
+    }
+
+    pub async fn delete(
+        &self,
+        item_ref: &ItemRef,
+        non_recursive: bool,
+    ) -> Result<R::Output, ClientError> {
+        let mut query = item_ref_query(item_ref);
+        push_flag(&mut query, "non_recursive", non_recursive);
+        self.send(Method::DELETE, &query, None).await
+    }
+
+    pub async fn delete_many(
+        &self,
+        item_refs: &ItemRefs,
+        non_recursive: bool,
+    ) -> Result<R::Output, ClientError> {
+        let mut query = item_refs_query(item_refs);
+        push_flag(&mut query, "non_recursive", non_recursive);
+        self.send(Method::DELETE, &query, None).await
+    }
+
+    pub async fn delete_all(
+        &self,
+        parent_id: Option<&PkSk>,
+        non_recursive: bool,
+    ) -> Result<R::Output, ClientError> {
+        let mut query = vec![("all", "true".to_string())];
+        push_id(&mut query, "parent_id", parent_id);
+        push_flag(&mut query, "non_recursive", non_recursive);
+        self.send(Method::DELETE, &query, None).await
+    }
+
+    pub async fn replace_all(
+        &self,
+        parent_id: Option<&PkSk>,
+        data: &[<R::Item as DynamoObject>::Data],
+    ) -> Result<R::Output, ClientError> {
+        let mut query = vec![("replace_all", "true".to_string())];
+        push_id(&mut query, "parent_id", parent_id);
+        self.send(Method::POST, &query, Some(to_json(data)?)).await
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        query: &[(&str, String)],
+        body: Option<String>,
+    ) -> Result<R::Output, ClientError> {
+        let segments: Vec<&str> = R::KEY.trim_matches('/').split('/').collect();
+        self.client.send(method, &segments, query, body).await
+    }
+}
+
+// Helper functions.
+// --------------------------------------------------
+
+fn to_json<T: Serialize + ?Sized>(data: &T) -> Result<String, ServerError> {
+    serde_json::to_string(data).map_err(|e| EncodingError::with_debug("request body", &e))
+}
+
+/// Flattens path parameters, or the input of a GET function, into string
+/// pairs, the inverse of how the server parses them (see
+/// `parse_request_input`). `data` must serialize to an object, or to null
+/// (e.g. `()`) if there is nothing to send.
+fn string_pairs<T: Serialize>(
+    data: &T,
+    component: &str,
+) -> Result<Vec<(String, String)>, ServerError> {
+    let value = serde_json::to_value(data).map_err(|e| EncodingError::with_debug(component, &e))?;
+    let object = match value {
+        Value::Object(object) => object,
+        Value::Null => return Ok(Vec::new()),
+        other => {
+            return Err(EncodingError::with_debug(
+                component,
+                &format!("expected an object, got {other}"),
+            ))
+        }
+    };
+    Ok(object
+        .iter()
+        .filter(|(_, v)| !v.is_null())
+        .map(|(k, v)| match v.as_str() {
+            Some(s) => (k.clone(), s.to_string()),
+            None => (k.clone(), v.to_string()),
+        })
+        .collect())
+}
+
+fn item_ref_query(item_ref: &ItemRef) -> Vec<(&'static str, String)> {
+    let mut query = Vec::new();
+    match item_ref {
+        ItemRef::Id(id) => push_id(&mut query, "id", Some(id)),
+        ItemRef::Key { parent_id, key } => {
+            query.push(("key", key.clone()));
+            push_id(&mut query, "parent_id", parent_id.as_ref());
+        }
+        ItemRef::None { parent_id } => push_id(&mut query, "parent_id", parent_id.as_ref()),
+    }
+    query
+}
+
+fn item_refs_query(item_refs: &ItemRefs) -> Vec<(&'static str, String)> {
+    let mut query = Vec::new();
+    match item_refs {
+        ItemRefs::Id(ids) => {
+            let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+            query.push(("ids", ids.join(",")));
+        }
+        ItemRefs::Key { parent_id, keys } => {
+            query.push(("keys", keys.join(",")));
+            push_id(&mut query, "parent_id", parent_id.as_ref());
+        }
+    }
+    query
+}
+
+fn push_id(query: &mut Vec<(&'static str, String)>, key: &'static str, id: Option<&PkSk>) {
+    if let Some(id) = id {
+        query.push((key, id.to_string()));
+    }
+}
+
+/// Flags are only checked for presence, so unset flags are left out.
+fn push_flag(query: &mut Vec<(&'static str, String)>, key: &'static str, set: bool) {
+    if set {
+        query.push((key, "true".to_string()));
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize)]
+    struct Search {
+        query: String,
+        limit: Option<u32>,
+        include_archived: bool,
+    }
+
+    #[test]
+    fn test_string_pairs() {
+        let mut pairs = string_pairs(
+            &Search {
+                query: "a b".to_string(),
+                limit: None,
+                include_archived: true,
+            },
+            "query",
+        )
+        .unwrap();
+        pairs.sort();
+        assert_eq!(
+            pairs,
+            vec![
+                ("include_archived".to_string(), "true".to_string()),
+                ("query".to_string(), "a b".to_string()),
+            ]
+        );
+        assert!(string_pairs(&(), "query").unwrap().is_empty());
+        assert!(string_pairs(&"text", "query").is_err());
+        assert!(string_pairs(&[1, 2], "query").is_err());
+    }
+
+    #[test]
+    fn test_item_refs_query() {
+        let query = item_refs_query(&ItemRefs::Key {
+            parent_id: None,
+            keys: vec!["a".to_string(), "b".to_string()],
+        });
+        assert_eq!(query, vec![("keys", "a,b".to_string())]);
+    }
+}
@@ -0,0 +1,98 @@
+use std::{future::Future, pin::Pin};
+
+use fractic_server_error::ServerError;
+
+use crate::{
+    handle_with_router::std::extractors::Json, shared::request_processing::RequestContext,
+};
+
+// Extractor-based handlers.
+// --------------------------------------------------
+
+/// Types that can be extracted from an incoming request, and can therefore be
+/// used as arguments of handlers passed to `ExtractorFunction`.
+pub trait FromRequest: Sized + Send + 'static {
+    fn from_request(ctx: &RequestContext) -> Result<Self, ServerError>;
+}
+
+pub type HandlerFuture<O> = Pin<Box<dyn Future<Output = Result<O, ServerError>> + Send>>;
+
+/// Implemented for async functions whose arguments all implement
+/// `FromRequest` (up to 8 arguments). `Args` is only used to distinguish the
+/// implementations, and is always inferred.
+pub trait Handler<Args, O>: Send + Sync + 'static {
+    fn call(&self, ctx: &RequestContext) -> HandlerFuture<O>;
+}
+
+macro_rules! impl_handler {
+    ($($ty:ident),*) => {
+        impl<F, Fut, O, $($ty,)*> Handler<($($ty,)*), O> for F
+        where
+            F: Fn($($ty),*) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Result<O, ServerError>> + Send + 'static,
+            O: Send + 'static,
+            $($ty: FromRequest,)*
+        {
+            #[allow(non_snake_case, unused_variables)]
+            fn call(&self, ctx: &RequestContext) -> HandlerFuture<O> {
+                $(
+                    let $ty = match $ty::from_request(ctx) {
+                        Ok(v) => v,
+                        Err(e) => return Box::pin(async move { Err(e) }),
+                    };
+                )*
+                Box::pin((self)($($ty),*))
+            }
+        }
+    };
+}
+
+/// Like `Handler`, for async functions taking the request input as their
+/// first argument (`Json<I>`), followed by up to 7 `FromRequest` arguments.
+/// The input is parsed (and validated) by the spec before the handler is
+/// called (see `ExtractorFunction::with_json`).
+pub trait JsonHandler<I, Args, O>: Send + Sync + 'static {
+    fn call(&self, input: I, ctx: &RequestContext) -> HandlerFuture<O>;
+}
+
+macro_rules! impl_json_handler {
+    ($($ty:ident),*) => {
+        impl<F, Fut, I, O, $($ty,)*> JsonHandler<I, ($($ty,)*), O> for F
+        where
+            F: Fn(Json<I>, $($ty),*) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Result<O, ServerError>> + Send + 'static,
+            O: Send + 'static,
+            $($ty: FromRequest,)*
+        {
+            #[allow(non_snake_case, unused_variables)]
+            fn call(&self, input: I, ctx: &RequestContext) -> HandlerFuture<O> {
+                $(
+                    let $ty = match $ty::from_request(ctx) {
+                        Ok(v) => v,
+                        Err(e) => return Box::pin(async move { Err(e) }),
+                    };
+                )*
+                Box::pin((self)(Json(input), $($ty),*))
+            }
+        }
+    };
+}
+
+impl_handler!();
+impl_handler!(T1);
+impl_handler!(T1, T2);
+impl_handler!(T1, T2, T3);
+impl_handler!(T1, T2, T3, T4);
+impl_handler!(T1, T2, T3, T4, T5);
+impl_handler!(T1, T2, T3, T4, T5, T6);
+impl_handler!(T1, T2, T3, T4, T5, T6, T7);
+impl_handler!(T1, T2, T3, T4, T5, T6, T7, T8);
+
+impl_json_handler!();
+impl_json_handler!(T1);
+impl_json_handler!(T1, T2);
+impl_json_handler!(T1, T2, T3);
+impl_json_handler!(T1, T2, T3, T4);
+impl_json_handler!(T1, T2, T3, T4, T5);
+impl_json_handler!(T1, T2, T3, T4, T5, T6);
+impl_json_handler!(T1, T2, T3, T4, T5, T6, T7);
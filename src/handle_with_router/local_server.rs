@@ -0,0 +1,256 @@
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc};
+
+use aws_lambda_events::{
+    apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse},
+    encodings::Body,
+    http::request::Parts,
+};
+use base64::Engine as _;
+use http_body_util::{BodyExt, Full};
+use hyper::{body::Bytes, server::conn::http1, service::service_fn, Request, Response};
+use hyper_util::rt::TokioIo;
+use lambda_runtime::{Context, LambdaEvent};
+use tokio::net::TcpListener;
+
+use crate::{
+    handle_with_router::routing_config::RoutingConfig,
+    shared::{api_events::percent_decode, fake_claims::FakeClaims},
+};
+
+// Local development server.
+// --------------------------------------------------
+//
+// Serves a `RoutingConfig` over plain HTTP, so that front-end developers can
+// point at localhost instead of `sam local` or a deployed stage. Requests are
+// converted into the same events API Gateway would send (with a '{proxy+}'
+// resource), including fake Cognito authorizer claims.
+//
+// Only for local development: there is no authentication, and any request
+// can impersonate any user through the override headers below.
+
+/// Overrides the sub of the simulated user for a single request.
+pub const FAKE_SUB_HEADER: &str = "x-fake-sub";
+
+/// Overrides the (comma-separated) groups of the simulated user for a single
+/// request. Only used together with `FAKE_SUB_HEADER`.
+pub const FAKE_GROUPS_HEADER: &str = "x-fake-groups";
+
+pub struct LocalServer {
+    pub config: RoutingConfig,
+    /// Claims of the simulated user, unless overridden by `FAKE_SUB_HEADER`.
+    /// If `None`, requests are unauthenticated.
+    pub claims: Option<FakeClaims>,
+}
+
+impl LocalServer {
+    pub fn new(config: RoutingConfig) -> Self {
+        Self {
+            config,
+            claims: None,
+        }
+    }
+
+    /// Serves requests until an error occurs accepting connections.
+    pub async fn serve(self, addr: SocketAddr) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        println!("Serving RoutingConfig on http://{addr}");
+        let server = Arc::new(self);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = server.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.respond(request).await) }
+                });
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    eprintln!("ERROR\nLocal server connection failed: {e}");
+                }
+            });
+        }
+    }
+
+    async fn respond(&self, request: Request<hyper::body::Incoming>) -> Response<Full<Bytes>> {
+        let (parts, body) = request.into_parts();
+        let body = match body.collect().await {
+            Ok(collected) => collected.to_bytes().to_vec(),
+            Err(e) => return plain_response(400, format!("Failed to read body: {e}")),
+        };
+        let request = to_proxy_request(&parts, body, self.claims.as_ref());
+        let event = LambdaEvent::new(request, Context::default());
+        match self.config.handle(event).await {
+            Ok(response) => to_http_response(response),
+            Err(e) => plain_response(500, format!("Handler failed: {e}")),
+        }
+    }
+}
+
+// Helper functions.
+// --------------------------------------------------
+
+fn to_proxy_request(
+    parts: &Parts,
+    body: Vec<u8>,
+    claims: Option<&FakeClaims>,
+) -> ApiGatewayProxyRequest {
+    let mut request = ApiGatewayProxyRequest::default();
+    request.http_method = parts.method.clone();
+    let path = parts.uri.path();
+    request.path_parameters.insert(
+        "proxy".to_string(),
+        path.trim_start_matches('/').to_string(),
+    );
+    request.path = Some(path.to_string());
+    request.resource = Some("/{proxy+}".to_string());
+    request.headers = parts.headers.clone();
+    request.query_string_parameters =
+        parse_query_string(parts.uri.query().unwrap_or_default()).into();
+    if !body.is_empty() {
+        match String::from_utf8(body) {
+            Ok(text) => request.body = Some(text),
+            Err(e) => {
+                request.body =
+                    Some(base64::engine::general_purpose::STANDARD.encode(e.into_bytes()));
+                request.is_base64_encoded = true;
+            }
+        }
+    }
+    let header = |name: &str| parts.headers.get(name).and_then(|v| v.to_str().ok());
+    let claims = match header(FAKE_SUB_HEADER) {
+        Some(sub) => Some(FakeClaims {
+            groups: header(FAKE_GROUPS_HEADER)
+                .map(|g| {
+                    g.split(',')
+                        .map(str::trim)
+                        .filter(|g| !g.is_empty())
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            ..FakeClaims::user(sub)
+        }),
+        None => claims.cloned(),
+    };
+    if let Some(claims) = claims {
+        request.request_context.authorizer = claims.authorizer();
+    }
+    request
+}
+
+fn parse_query_string(query: &str) -> HashMap<String, Vec<String>> {
+    let mut params: HashMap<String, Vec<String>> = HashMap::new();
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        params
+            .entry(percent_decode(key))
+            .or_default()
+            .push(percent_decode(value));
+    }
+    params
+}
+
+fn to_http_response(response: ApiGatewayProxyResponse) -> Response<Full<Bytes>> {
+    let body = match response.body {
+        Some(Body::Text(text)) if response.is_base64_encoded => {
+            base64::engine::general_purpose::STANDARD
+                .decode(&text)
+                .unwrap_or_else(|_| text.into_bytes())
+        }
+        Some(Body::Text(text)) => text.into_bytes(),
+        Some(Body::Binary(bytes)) => bytes,
+        _ => Vec::new(),
+    };
+    let mut http_response = Response::new(Full::new(Bytes::from(body)));
+    *http_response.status_mut() = u16::try_from(response.status_code)
+        .ok()
+        .and_then(|s| s.try_into().ok())
+        .unwrap_or_default();
+    *http_response.headers_mut() = response.headers;
+    http_response
+        .headers_mut()
+        .extend(response.multi_value_headers);
+    http_response
+}
+
+fn plain_response(status: u16, message: String) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(message)));
+    *response.status_mut() = status.try_into().unwrap_or_default();
+    response
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::request_processing::parse_request_metadata;
+    use aws_lambda_events::http::Method;
+
+    fn parts(builder: aws_lambda_events::http::request::Builder) -> Parts {
+        builder.body(()).unwrap().into_parts().0
+    }
+
+    #[test]
+    fn test_to_proxy_request() {
+        let parts = parts(
+            Request::builder()
+                .method("GET")
+                .uri("/users/123?name=John%20Doe&tag=a&tag=b"),
+        );
+        let request = to_proxy_request(&parts, Vec::new(), None);
+        assert_eq!(request.http_method, Method::GET);
+        assert_eq!(request.path_parameters["proxy"], "users/123");
+        assert_eq!(
+            request.query_string_parameters.first("name"),
+            Some("John Doe")
+        );
+        assert_eq!(
+            request.query_string_parameters.all("tag"),
+            Some(vec!["a", "b"])
+        );
+        assert!(request.body.is_none());
+        assert!(!parse_request_metadata(&request).unwrap().is_authenticated);
+    }
+
+    #[test]
+    fn test_fake_claims() {
+        let parts_without_override = parts(Request::builder().method("POST").uri("/fn"));
+        let request = to_proxy_request(
+            &parts_without_override,
+            b"{}".to_vec(),
+            Some(&FakeClaims::admin("admin-sub")),
+        );
+        let metadata = parse_request_metadata(&request).unwrap();
+        assert!(metadata.is_admin);
+        assert_eq!(metadata.user_sub.as_deref(), Some("admin-sub"));
+        assert_eq!(request.body.as_deref(), Some("{}"));
+
+        let parts_with_override = parts(
+            Request::builder()
+                .method("POST")
+                .uri("/fn")
+                .header(FAKE_SUB_HEADER, "other-sub"),
+        );
+        let request = to_proxy_request(
+            &parts_with_override,
+            Vec::new(),
+            Some(&FakeClaims::admin("admin-sub")),
+        );
+        let metadata = parse_request_metadata(&request).unwrap();
+        assert!(!metadata.is_admin);
+        assert_eq!(metadata.user_sub.as_deref(), Some("other-sub"));
+    }
+
+    #[test]
+    fn test_to_http_response() {
+        let mut response = ApiGatewayProxyResponse::default();
+        response.status_code = 404;
+        response.body = Some(Body::Text("Not found.".to_string()));
+        let http_response = to_http_response(response);
+        assert_eq!(http_response.status(), 404);
+    }
+}
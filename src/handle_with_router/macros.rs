@@ -1,11 +1,27 @@
 #[macro_export]
 macro_rules! aws_lambda_handle_with_router {
     ($config:expr) => {
+        $crate::aws_lambda_handle_with_router!(
+            $config,
+            event = ::aws_lambda_events::apigw::ApiGatewayProxyRequest
+        );
+    };
+    // The state is built by awaiting the given future once per cold start, and
+    // is then shared by all requests (see `AppState`).
+    ($config:expr, state = $state:expr) => {
+        $crate::aws_lambda_handle_with_router!(
+            $config,
+            event = ::aws_lambda_events::apigw::ApiGatewayProxyRequest,
+            state = $state
+        );
+    };
+    // The event type can be any `ApiEvent` (e.g.
+    // `::aws_lambda_events::apigw::ApiGatewayV2httpRequest` for HTTP APIs, or
+    // `::aws_lambda_events::alb::AlbTargetGroupRequest` for ALB targets).
+    ($config:expr, event = $event:ty) => {
         async fn __handler(
-            event: ::lambda_runtime::LambdaEvent<
-                ::aws_lambda_events::apigw::ApiGatewayProxyRequest,
-            >,
-        ) -> Result<::aws_lambda_events::apigw::ApiGatewayProxyResponse, ::lambda_runtime::Error> {
+            event: ::lambda_runtime::LambdaEvent<$event>,
+        ) -> Result<<$event as $crate::ApiEvent>::Response, ::lambda_runtime::Error> {
             static CONFIG: ::std::sync::OnceLock<$crate::RoutingConfig> =
                 ::std::sync::OnceLock::new();
             let config_ref = CONFIG.get_or_init(|| $config);
@@ -13,4 +29,20 @@ macro_rules! aws_lambda_handle_with_router {
         }
         $crate::aws_lambda_handle_raw!(__handler);
     };
+    ($config:expr, event = $event:ty, state = $state:expr) => {
+        static __STATE: ::std::sync::OnceLock<$crate::AppState> = ::std::sync::OnceLock::new();
+        async fn __handler(
+            event: ::lambda_runtime::LambdaEvent<$event>,
+        ) -> Result<<$event as $crate::ApiEvent>::Response, ::lambda_runtime::Error> {
+            static CONFIG: ::std::sync::OnceLock<$crate::RoutingConfig> =
+                ::std::sync::OnceLock::new();
+            let config_ref = CONFIG.get_or_init(|| $config);
+            config_ref
+                .handle_with_state(event, __STATE.get().cloned())
+                .await
+        }
+        $crate::aws_lambda_handle_raw!(__handler, async {
+            let _ = __STATE.set(::std::sync::Arc::new($state.await));
+        });
+    };
 }
@@ -0,0 +1,347 @@
+use std::{sync::Arc, time::Instant};
+
+use async_trait::async_trait;
+use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use lambda_runtime::Error;
+
+use crate::{
+    errors::InvalidRouteError,
+    handle_with_router::routing_config::{
+        CrudInfo, CrudSpec, FunctionInfo, FunctionSpec, RouteContext,
+    },
+    shared::response_building::build_err_with_status,
+};
+
+// Middleware.
+// --------------------------------------------------
+//
+// Middleware wraps route resolution, for cross-cutting concerns (logging,
+// timing, header injection, auth enrichment, ...) that would otherwise be
+// repeated in every handler. It can be attached:
+//
+// - To all requests, or to a group of routes sharing a path prefix, through
+//   `RoutingConfig::middleware` (see `RouterMiddleware`).
+// - To a single route, by wrapping its spec in `WithMiddleware`.
+//
+// Router middleware runs first (in order), followed by route middleware. Each
+// middleware decides whether and how to continue through `Next::run`, e.g.:
+//
+//   struct RequestId;
+//
+//   #[async_trait]
+//   impl Middleware for RequestId {
+//       async fn handle(
+//           &self,
+//           request: &ApiGatewayProxyRequest,
+//           ctx: &RouteContext,
+//           next: Next<'_>,
+//       ) -> Result<ApiGatewayProxyResponse, Error> {
+//           let mut response = next.run(request, ctx).await?;
+//           if let Some(id) = &request.request_context.request_id {
+//               response.headers.insert("x-request-id", id.parse()?);
+//           }
+//           Ok(response)
+//       }
+//   }
+
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    /// Called instead of resolving the route. Continue resolution with
+    /// `next.run` (possibly with a modified request or context), or return a
+    /// response directly to short-circuit it.
+    async fn handle(
+        &self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+        next: Next<'_>,
+    ) -> Result<ApiGatewayProxyResponse, Error>;
+}
+
+#[async_trait]
+impl<M: Middleware + ?Sized> Middleware for Arc<M> {
+    async fn handle(
+        &self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+        next: Next<'_>,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        self.as_ref().handle(request, ctx, next).await
+    }
+}
+
+/// Remainder of the pipeline: the following middleware, then the route.
+pub struct Next<'a> {
+    middleware: &'a [Arc<dyn Middleware>],
+    target: Target<'a>,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Target<'a> {
+    Function(&'a dyn FunctionSpec),
+    Crud(&'a dyn CrudSpec),
+    /// No route matched the request.
+    NotFound,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(middleware: &'a [Arc<dyn Middleware>], target: Target<'a>) -> Self {
+        Self { middleware, target }
+    }
+
+    pub async fn run(
+        self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        match self.middleware.split_first() {
+            Some((first, rest)) => {
+                let next = Next {
+                    middleware: rest,
+                    target: self.target,
+                };
+                first.handle(request, ctx, next).await
+            }
+            None => match self.target {
+                Target::Function(spec) => spec.resolve(request, ctx).await,
+                Target::Crud(spec) => spec.resolve(request, ctx).await,
+                Target::NotFound => build_err_with_status(
+                    InvalidRouteError::new(request.path.clone()),
+                    404,
+                    &ctx.response_options(request),
+                ),
+            },
+        }
+    }
+}
+
+/// Middleware attached to a `RoutingConfig`.
+#[derive(Clone)]
+pub struct RouterMiddleware {
+    /// Path prefix of the requests the middleware applies to (e.g. "admin"
+    /// for "admin" and "admin/users"), whether or not they match a route. If
+    /// `None`, it applies to all requests.
+    pub group: Option<&'static str>,
+    pub middleware: Arc<dyn Middleware>,
+}
+
+impl RouterMiddleware {
+    pub fn global(middleware: impl Middleware + 'static) -> Self {
+        Self {
+            group: None,
+            middleware: Arc::new(middleware),
+        }
+    }
+
+    pub fn group(prefix: &'static str, middleware: impl Middleware + 'static) -> Self {
+        Self {
+            group: Some(prefix),
+            middleware: Arc::new(middleware),
+        }
+    }
+
+    /// `path` is the request's path, or `None` if it is unknown.
+    pub(crate) fn applies_to(&self, path: Option<&str>) -> bool {
+        match (self.group, path) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(prefix), Some(path)) => {
+                let prefix = prefix.trim_matches('/');
+                let path = path.trim_matches('/');
+                path.strip_prefix(prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            }
+        }
+    }
+}
+
+/// Wraps a function or CRUD spec to run middleware around a single route,
+/// after the router's middleware. Can be nested to attach several.
+pub struct WithMiddleware<S> {
+    pub middleware: Arc<dyn Middleware>,
+    pub spec: S,
+}
+
+impl<S> WithMiddleware<S> {
+    pub fn new(middleware: impl Middleware + 'static, spec: S) -> Self {
+        Self {
+            middleware: Arc::new(middleware),
+            spec,
+        }
+    }
+}
+
+#[async_trait]
+impl<S: FunctionSpec> FunctionSpec for WithMiddleware<S> {
+    async fn resolve(
+        &self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let middleware = [self.middleware.clone()];
+        Next::new(&middleware, Target::Function(&self.spec))
+            .run(request, ctx)
+            .await
+    }
+
+    fn info(&self) -> FunctionInfo {
+        FunctionSpec::info(&self.spec)
+    }
+}
+
+#[async_trait]
+impl<S: CrudSpec> CrudSpec for WithMiddleware<S> {
+    async fn resolve(
+        &self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let middleware = [self.middleware.clone()];
+        Next::new(&middleware, Target::Crud(&self.spec))
+            .run(request, ctx)
+            .await
+    }
+
+    fn info(&self) -> CrudInfo {
+        CrudSpec::info(&self.spec)
+    }
+}
+
+// Standard middleware.
+// --------------------------------------------------
+
+/// Logs the method, path, status and duration of each request.
+pub struct RequestLogging;
+
+#[async_trait]
+impl Middleware for RequestLogging {
+    async fn handle(
+        &self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+        next: Next<'_>,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let start = Instant::now();
+        let result = next.run(request, ctx).await;
+        let status = match &result {
+            Ok(response) => response.status_code.to_string(),
+            Err(_) => "failed".to_string(),
+        };
+        println!(
+            "INFO\n{} {} -> {} ({} ms)",
+            request.http_method,
+            request.path.as_deref().unwrap_or_default(),
+            status,
+            start.elapsed().as_millis()
+        );
+        result
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle_with_router::{
+        routing_config::{Access, RoutingConfig, Validation},
+        std::function_specs::NullaryFunction,
+        test_client::TestClient,
+    };
+    use aws_lambda_events::http::HeaderValue;
+
+    /// Appends its name to the 'x-trace' response header.
+    struct Trace(&'static str);
+
+    #[async_trait]
+    impl Middleware for Trace {
+        async fn handle(
+            &self,
+            request: &ApiGatewayProxyRequest,
+            ctx: &RouteContext,
+            next: Next<'_>,
+        ) -> Result<ApiGatewayProxyResponse, Error> {
+            let mut response = next.run(request, ctx).await?;
+            let trace = match response.headers.get("x-trace") {
+                Some(inner) => format!("{},{}", inner.to_str()?, self.0),
+                None => self.0.to_string(),
+            };
+            response
+                .headers
+                .insert("x-trace", HeaderValue::from_str(&trace)?);
+            Ok(response)
+        }
+    }
+
+    /// Rejects all requests without calling the route.
+    struct Block;
+
+    #[async_trait]
+    impl Middleware for Block {
+        async fn handle(
+            &self,
+            _request: &ApiGatewayProxyRequest,
+            _ctx: &RouteContext,
+            _next: Next<'_>,
+        ) -> Result<ApiGatewayProxyResponse, Error> {
+            let mut response = ApiGatewayProxyResponse::default();
+            response.status_code = 403;
+            Ok(response)
+        }
+    }
+
+    fn client() -> TestClient {
+        let ping = || NullaryFunction::new(Access::Guest, Validation::None, || async { Ok(()) });
+        let mut config = RoutingConfig::default();
+        config.function_routes.insert("ping", ping());
+        config.function_routes.insert(
+            "admin/users",
+            Box::new(WithMiddleware::new(Trace("route"), ping())),
+        );
+        config
+            .function_routes
+            .insert("blocked", Box::new(WithMiddleware::new(Block, ping())));
+        config.middleware = vec![
+            RouterMiddleware::global(Trace("global")),
+            RouterMiddleware::group("admin", Trace("group")),
+        ];
+        TestClient::new(config)
+    }
+
+    #[tokio::test]
+    async fn test_order() {
+        let client = client();
+        let response = client.post("admin/users").send().await;
+        assert_eq!(response.status_code(), 200);
+        // Inner middleware sets the header first.
+        assert_eq!(response.header("x-trace"), Some("route,group,global"));
+
+        let response = client.post("ping").send().await;
+        assert_eq!(response.header("x-trace"), Some("global"));
+
+        let response = client.post("missing").send().await;
+        assert_eq!(response.status_code(), 404);
+        assert_eq!(response.header("x-trace"), Some("global"));
+
+        // Group middleware also wraps unmatched paths of its group.
+        let response = client.post("admin/missing").send().await;
+        assert_eq!(response.status_code(), 404);
+        assert_eq!(response.header("x-trace"), Some("group,global"));
+    }
+
+    #[tokio::test]
+    async fn test_short_circuit() {
+        let response = client().post("blocked").send().await;
+        assert_eq!(response.status_code(), 403);
+    }
+
+    #[test]
+    fn test_applies_to() {
+        let group = RouterMiddleware::group("/admin/", RequestLogging);
+        assert!(group.applies_to(Some("admin")));
+        assert!(group.applies_to(Some("admin/users")));
+        assert!(!group.applies_to(Some("administrators")));
+        assert!(!group.applies_to(None));
+        assert!(RouterMiddleware::global(RequestLogging).applies_to(None));
+    }
+}
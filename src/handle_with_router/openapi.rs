@@ -0,0 +1,623 @@
+use std::collections::BTreeMap;
+
+use aws_lambda_events::http::Method;
+use schemars::JsonSchema;
+use serde_json::{json, Value};
+
+use crate::{
+    handle_with_router::{
+        path_templates::template_params,
+        routing_config::{
+            split_route_key, AccessRule, CrudInfo, CrudOperationKind, FunctionInfo, RoutingConfig,
+            TypeRef,
+        },
+        schema_registry::SchemaRegistry,
+    },
+    shared::{api_keys::API_KEY_HEADER, response_building::ErrorMode},
+};
+
+// OpenAPI document generation.
+// --------------------------------------------------
+//
+// Generates an OpenAPI 3.0 document from a `RoutingConfig`, based on the
+// routes' own descriptions (see `FunctionSpec::info` and `CrudSpec::info`).
+// Schemas are derived with `schemars::JsonSchema`, and must be registered
+// for each type used by the routes (see `SchemaRegistry`).
+//
+// Routes whose access is `None` are always denied, and are therefore left
+// out of the document.
+
+/// Name of the security scheme used for Cognito-authenticated routes.
+pub const COGNITO_SECURITY_SCHEME: &str = "cognito";
+
+/// Name of the security scheme used for routes accepting API keys.
+pub const API_KEY_SECURITY_SCHEME: &str = "api_key";
+
+pub struct OpenApi {
+    pub title: String,
+    pub version: String,
+    /// Base URLs of the API (e.g. "https://api.fractic.io/prod").
+    pub servers: Vec<String>,
+    pub schemas: SchemaRegistry,
+}
+
+impl OpenApi {
+    pub fn new(title: &str, version: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            version: version.to_string(),
+            servers: Vec::new(),
+            schemas: SchemaRegistry::default(),
+        }
+    }
+
+    pub fn server(mut self, url: &str) -> Self {
+        self.servers.push(url.to_string());
+        self
+    }
+
+    /// Registers the schema of a type used as a route's input, output, path
+    /// parameters or CRUD item.
+    pub fn schema<T: JsonSchema + ?Sized + 'static>(mut self) -> Self {
+        self.schemas.register::<T>();
+        self
+    }
+
+    pub fn generate(mut self, config: &RoutingConfig) -> Value {
+        let mut paths: BTreeMap<String, BTreeMap<String, Value>> = BTreeMap::new();
+
+        let mut function_routes: Vec<_> = config.function_routes.iter().collect();
+        function_routes.sort_by_key(|(key, _)| **key);
+        for (key, spec) in function_routes {
+            let (method, path) = split_route_key(key);
+            let info = spec.info();
+            if info.access.as_ref().is_some_and(AccessRule::is_none) {
+                continue;
+            }
+            let operation = self.function_operation(&method, path, &info, &config.error_mode);
+            paths
+                .entry(format!("/{}", path.trim_matches('/')))
+                .or_default()
+                .insert(method.as_str().to_ascii_lowercase(), operation);
+        }
+
+        let mut crud_routes: Vec<_> = config.crud_routes.iter().collect();
+        crud_routes.sort_by_key(|(key, _)| **key);
+        for (key, spec) in crud_routes {
+            let info = spec.info();
+            let operations = [
+                (Method::GET, self.crud_get(key, &info)),
+                (Method::POST, self.crud_post(key, &info)),
+                (Method::PUT, self.crud_put(key, &info)),
+                (Method::DELETE, self.crud_delete(key, &info)),
+            ];
+            for (method, operation) in operations {
+                let Some(mut operation) = operation else {
+                    continue;
+                };
+                operation["operationId"] = json!(operation_id(&method, key));
+                operation["responses"] = self.responses(info.output, &config.error_mode);
+                paths
+                    .entry(format!("/{}", key.trim_matches('/')))
+                    .or_default()
+                    .insert(method.as_str().to_ascii_lowercase(), operation);
+            }
+        }
+
+        json!({
+            "openapi": "3.0.3",
+            "info": { "title": self.title, "version": self.version },
+            "servers": self.servers.iter().map(|url| json!({ "url": url })).collect::<Vec<_>>(),
+            "paths": paths,
+            "components": {
+                "schemas": self.schemas.definitions().into_iter().collect::<BTreeMap<_, _>>(),
+                "securitySchemes": {
+                    COGNITO_SECURITY_SCHEME: {
+                        "type": "apiKey",
+                        "in": "header",
+                        "name": "Authorization",
+                        "description": "Cognito ID token, validated by the API Gateway authorizer.",
+                    },
+                    API_KEY_SECURITY_SCHEME: {
+                        "type": "apiKey",
+                        "in": "header",
+                        "name": API_KEY_HEADER,
+                        "description": "API key, looked up in the router's API key store.",
+                    },
+                },
+            },
+        })
+    }
+
+    fn function_operation(
+        &self,
+        method: &Method,
+        path: &str,
+        info: &FunctionInfo,
+        error_mode: &ErrorMode,
+    ) -> Value {
+        let mut parameters: Vec<Value> = template_params(path)
+            .into_iter()
+            .map(|name| {
+                json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "string" },
+                })
+            })
+            .collect();
+        let mut operation = json!({
+            "operationId": operation_id(method, path),
+            "responses": self.responses(info.output, error_mode),
+        });
+        // GET functions read their input from the query string (see
+        // `parse_request_input`).
+        match info.input {
+            Some(input) if *method == Method::GET => {
+                parameters.extend(self.query_parameters(input));
+            }
+            Some(input) => {
+                operation["requestBody"] = json!({
+                    "required": true,
+                    "content": { "application/json": { "schema": self.schema_of(input) } },
+                });
+            }
+            None => {}
+        }
+        operation["parameters"] = json!(parameters);
+        if let Some(access) = &info.access {
+            set_security(&mut operation, &[access]);
+            operation["x-access"] = json!(access.to_string());
+        }
+        operation
+    }
+
+    fn crud_get(&self, route: &str, info: &CrudInfo) -> Option<Value> {
+        let list = is_enabled(info, CrudOperationKind::List);
+        let read = is_enabled(info, CrudOperationKind::Read);
+        if !list && !read {
+            return None;
+        }
+        let mut parameters = Vec::new();
+        if list {
+            parameters.push(flag_parameter(
+                "all",
+                "List all items (under `parent_id`, if given).",
+            ));
+        }
+        if read {
+            parameters.extend(item_ref_parameters(info.allow_batching));
+        }
+        parameters.push(parent_id_parameter());
+        Some(crud_operation(
+            format!("Lists or reads items of '{route}'."),
+            parameters,
+            info,
+            &[CrudOperationKind::List, CrudOperationKind::Read],
+        ))
+    }
+
+    fn crud_post(&self, route: &str, info: &CrudInfo) -> Option<Value> {
+        let create = is_enabled(info, CrudOperationKind::Create);
+        let replace_all = is_enabled(info, CrudOperationKind::ReplaceAll);
+        if !create && !replace_all {
+            return None;
+        }
+        let data = self.schema_of_opt(info.data);
+        let data_list = json!({ "type": "array", "items": data });
+        let mut parameters = vec![parent_id_parameter()];
+        if create {
+            parameters.push(string_parameter(
+                "after",
+                "ID of the item after which to insert the new item(s).",
+            ));
+        }
+        if replace_all {
+            parameters.push(flag_parameter(
+                "replace_all",
+                "Replace all items (under `parent_id`, if given) with the given list.",
+            ));
+        }
+        let body = match (create, replace_all || info.allow_batching) {
+            (true, true) => json!({ "oneOf": [data, data_list] }),
+            (true, false) => data,
+            (false, _) => data_list,
+        };
+        let mut operation = crud_operation(
+            format!("Creates or replaces items of '{route}'."),
+            parameters,
+            info,
+            &[CrudOperationKind::Create, CrudOperationKind::ReplaceAll],
+        );
+        operation["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": body } },
+        });
+        Some(operation)
+    }
+
+    fn crud_put(&self, route: &str, info: &CrudInfo) -> Option<Value> {
+        if !is_enabled(info, CrudOperationKind::Update) {
+            return None;
+        }
+        let mut operation = crud_operation(
+            format!("Updates an item of '{route}'."),
+            Vec::new(),
+            info,
+            &[CrudOperationKind::Update],
+        );
+        operation["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": self.schema_of_opt(info.item) } },
+        });
+        Some(operation)
+    }
+
+    fn crud_delete(&self, route: &str, info: &CrudInfo) -> Option<Value> {
+        let delete = is_enabled(info, CrudOperationKind::Delete);
+        let delete_all = is_enabled(info, CrudOperationKind::DeleteAll);
+        if !delete && !delete_all {
+            return None;
+        }
+        let mut parameters = Vec::new();
+        if delete_all {
+            parameters.push(flag_parameter(
+                "all",
+                "Delete all items (under `parent_id`, if given).",
+            ));
+        }
+        if delete {
+            parameters.extend(item_ref_parameters(info.allow_batching));
+        }
+        parameters.push(parent_id_parameter());
+        if info.allow_non_recursive_delete {
+            parameters.push(flag_parameter(
+                "non_recursive",
+                "Only delete the item(s) themselves, not their children.",
+            ));
+        }
+        Some(crud_operation(
+            format!("Deletes items of '{route}'."),
+            parameters,
+            info,
+            &[CrudOperationKind::Delete, CrudOperationKind::DeleteAll],
+        ))
+    }
+
+    fn responses(&self, output: Option<TypeRef>, error_mode: &ErrorMode) -> Value {
+        let envelope = json!({
+            "type": "object",
+            "required": ["ok"],
+            "properties": {
+                "ok": { "type": "boolean" },
+                "data": {
+                    "description": "Response data. By default gzip-compressed and \
+                        base64-encoded JSON; embedded as plain JSON if requested with \
+                        `encoding=identity`.",
+                    "oneOf": [
+                        { "type": "string", "format": "byte" },
+                        self.schema_of_opt(output),
+                    ],
+                },
+                "error": {
+                    "type": "string",
+                    "description": "If not OK, error message safe to show to the user.",
+                },
+            },
+        });
+        match error_mode {
+            ErrorMode::Envelope => json!({
+                "200": {
+                    "description": "Response envelope. Client errors are also returned \
+                        with status 200, with `ok == false`.",
+                    "content": { "application/json": { "schema": envelope } },
+                },
+                "401": {
+                    "description": "Not authorized.",
+                    "content": { "text/plain": { "schema": { "type": "string" } } },
+                },
+                "500": {
+                    "description": "Internal server error.",
+                    "content": { "text/plain": { "schema": { "type": "string" } } },
+                },
+            }),
+            ErrorMode::Problem(_) => json!({
+                "200": {
+                    "description": "Response envelope.",
+                    "content": { "application/json": { "schema": envelope } },
+                },
+                "default": {
+                    "description": "Error (RFC 7807 problem details).",
+                    "content": {
+                        "application/problem+json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "type": { "type": "string" },
+                                    "title": { "type": "string" },
+                                    "status": { "type": "integer" },
+                                    "detail": { "type": "string" },
+                                },
+                            },
+                        },
+                    },
+                },
+            }),
+        }
+    }
+
+    /// Query parameters of a GET function, i.e. the properties of its input
+    /// type's schema.
+    fn query_parameters(&self, input: TypeRef) -> Vec<Value> {
+        let Some(schema) = self
+            .schemas
+            .get(input)
+            .and_then(|s| self.schemas.resolve(s))
+        else {
+            return Vec::new();
+        };
+        let required: Vec<&str> = schema
+            .get("required")
+            .and_then(|r| r.as_array())
+            .map(|r| r.iter().filter_map(|name| name.as_str()).collect())
+            .unwrap_or_default();
+        schema
+            .get("properties")
+            .and_then(|p| p.as_object())
+            .map(|properties| {
+                properties
+                    .iter()
+                    .map(|(name, property)| {
+                        json!({
+                            "name": name,
+                            "in": "query",
+                            "required": required.contains(&name.as_str()),
+                            "schema": property,
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn schema_of(&self, type_ref: TypeRef) -> Value {
+        match self.schemas.get(type_ref) {
+            Some(schema) => json!(schema),
+            None => {
+                json!({ "description": format!("`{}` (no schema registered).", type_ref.name) })
+            }
+        }
+    }
+
+    fn schema_of_opt(&self, type_ref: Option<TypeRef>) -> Value {
+        type_ref.map(|t| self.schema_of(t)).unwrap_or(json!({}))
+    }
+}
+
+// Helper functions.
+// --------------------------------------------------
+
+/// Operations of custom specs that don't describe their access are assumed to
+/// be enabled.
+fn is_enabled(info: &CrudInfo, operation: CrudOperationKind) -> bool {
+    match info.access_of(operation) {
+        Some(rule) => !rule.is_none(),
+        None => info.access.is_empty(),
+    }
+}
+
+fn crud_operation(
+    summary: String,
+    parameters: Vec<Value>,
+    info: &CrudInfo,
+    operations: &[CrudOperationKind],
+) -> Value {
+    let rules: Vec<(CrudOperationKind, &AccessRule)> = operations
+        .iter()
+        .filter_map(|op| info.access_of(*op).map(|rule| (*op, rule)))
+        .filter(|(_, rule)| !rule.is_none())
+        .collect();
+    let mut operation = json!({
+        "summary": summary,
+        "parameters": parameters,
+    });
+    if !rules.is_empty() {
+        set_security(
+            &mut operation,
+            &rules.iter().map(|(_, rule)| *rule).collect::<Vec<_>>(),
+        );
+        operation["x-access"] = json!(rules
+            .iter()
+            .map(|(op, rule)| (format!("{op:?}"), rule.to_string()))
+            .collect::<BTreeMap<_, _>>());
+    }
+    operation
+}
+
+/// Authentication is optional if any of the rules allows guests.
+fn set_security(operation: &mut Value, rules: &[&AccessRule]) {
+    let mut security = Vec::new();
+    if rules.iter().any(|rule| rule.allows_guests()) {
+        security.push(json!({}));
+    }
+    if rules.iter().any(|rule| !rule.uses_api_key()) {
+        security.push(json!({ COGNITO_SECURITY_SCHEME: [] }));
+    }
+    if rules.iter().any(|rule| rule.uses_api_key()) {
+        security.push(json!({ API_KEY_SECURITY_SCHEME: [] }));
+    }
+    operation["security"] = Value::Array(security);
+}
+
+fn operation_id(method: &Method, path: &str) -> String {
+    let path: String = path
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let path: Vec<&str> = path.split('_').filter(|s| !s.is_empty()).collect();
+    format!(
+        "{}_{}",
+        method.as_str().to_ascii_lowercase(),
+        path.join("_")
+    )
+}
+
+fn string_parameter(name: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "query",
+        "required": false,
+        "description": description,
+        "schema": { "type": "string" },
+    })
+}
+
+fn flag_parameter(name: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "query",
+        "required": false,
+        "description": format!("{description} Flag (any value)."),
+        "allowEmptyValue": true,
+        "schema": { "type": "string" },
+    })
+}
+
+fn parent_id_parameter() -> Value {
+    string_parameter("parent_id", "ID of the parent item (defaults to the root).")
+}
+
+fn item_ref_parameters(allow_batching: bool) -> Vec<Value> {
+    let mut parameters = vec![
+        string_parameter("id", "Item ID."),
+        string_parameter("key", "Item key (under `parent_id`, if given)."),
+    ];
+    if allow_batching {
+        parameters.push(string_parameter("ids", "Comma-separated item IDs."));
+        parameters.push(string_parameter(
+            "keys",
+            "Comma-separated item keys (under `parent_id`, if given).",
+        ));
+    }
+    parameters
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle_with_router::{
+        routing_config::{Access, Validation},
+        std::function_specs::{Function, NullaryFunction},
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, JsonSchema)]
+    struct Search {
+        query: String,
+        limit: Option<u32>,
+    }
+
+    #[derive(Serialize, Deserialize, JsonSchema)]
+    struct User {
+        name: String,
+    }
+
+    fn config() -> RoutingConfig {
+        let mut config = RoutingConfig::default();
+        config.function_routes.insert(
+            "GET users/search",
+            Function::new(Access::AnyUser, Validation::None, |_: Search| async {
+                Ok(Vec::<User>::new())
+            }),
+        );
+        config.function_routes.insert(
+            "users/{user_id}/rename",
+            Function::new(Access::Admin, Validation::None, |user: User| async move {
+                Ok(user)
+            }),
+        );
+        config.function_routes.insert(
+            "ping",
+            NullaryFunction::new(Access::Guest, Validation::None, || async { Ok(()) }),
+        );
+        config.function_routes.insert(
+            "disabled",
+            NullaryFunction::new(Access::None, Validation::None, || async { Ok(()) }),
+        );
+        config.function_routes.insert(
+            "GET partners/orders",
+            NullaryFunction::new(
+                Access::ApiKeyPermission("orders:read"),
+                Validation::None,
+                || async { Ok(()) },
+            ),
+        );
+        config
+    }
+
+    #[test]
+    fn test_function_routes() {
+        let doc = OpenApi::new("Test API", "1.0.0")
+            .schema::<Search>()
+            .schema::<User>()
+            .schema::<Vec<User>>()
+            .generate(&config());
+
+        assert_eq!(doc["openapi"], "3.0.3");
+        assert!(doc["paths"].get("/disabled").is_none());
+
+        let search = &doc["paths"]["/users/search"]["get"];
+        assert_eq!(search["operationId"], "get_users_search");
+        assert_eq!(search["x-access"], "any_user");
+        assert_eq!(search["security"], json!([{ "cognito": [] }]));
+        let parameters = search["parameters"].as_array().unwrap();
+        assert_eq!(parameters.len(), 2);
+        assert!(parameters
+            .iter()
+            .any(|p| p["name"] == "query" && p["required"] == true));
+        assert!(parameters
+            .iter()
+            .any(|p| p["name"] == "limit" && p["required"] == false));
+
+        let rename = &doc["paths"]["/users/{user_id}/rename"]["post"];
+        assert_eq!(rename["x-access"], "admin");
+        assert_eq!(rename["parameters"][0]["name"], "user_id");
+        assert_eq!(rename["parameters"][0]["in"], "path");
+        assert_eq!(
+            rename["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/User"
+        );
+        assert!(doc["components"]["schemas"].get("User").is_some());
+
+        let ping = &doc["paths"]["/ping"]["post"];
+        assert_eq!(ping["security"], json!([{}, { "cognito": [] }]));
+        assert!(ping.get("requestBody").is_none());
+
+        let orders = &doc["paths"]["/partners/orders"]["get"];
+        assert_eq!(orders["x-access"], "api_key_permission(orders:read)");
+        assert_eq!(orders["security"], json!([{ "api_key": [] }]));
+    }
+
+    #[test]
+    fn test_unregistered_schema() {
+        let doc = OpenApi::new("Test API", "1.0.0").generate(&config());
+        let schema = &doc["paths"]["/users/{user_id}/rename"]["post"]["requestBody"]["content"]
+            ["application/json"]["schema"];
+        assert!(schema["description"]
+            .as_str()
+            .unwrap()
+            .contains("no schema registered"));
+    }
+
+    #[test]
+    fn test_operation_id() {
+        assert_eq!(
+            operation_id(&Method::DELETE, "users/{user_id}/orders"),
+            "delete_users_user_id_orders"
+        );
+    }
+}
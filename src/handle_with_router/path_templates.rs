@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+// Route path template utils.
+// --------------------------------------------------
+//
+// Route keys may contain `{param}` segments (e.g.
+// "users/{user_id}/orders/{order_id}"), which match any single non-empty path
+// segment. Captured values are made available to specs through the request's
+// `path_parameters`, exactly as API Gateway does for templated resources.
+
+pub(crate) fn is_template(route: &str) -> bool {
+    split_segments(route).any(|s| param_name(s).is_some())
+}
+
+/// Names of the `{param}` segments of `template`, in order.
+pub(crate) fn template_params(template: &str) -> Vec<&str> {
+    split_segments(template).filter_map(param_name).collect()
+}
+
+/// Describes why `template` is malformed, if it is.
+pub(crate) fn template_problem(template: &str) -> Option<String> {
+    if template.trim_matches('/').is_empty() {
+        return Some("path is empty".to_string());
+    }
+    if template.chars().any(char::is_whitespace) {
+        return Some("path contains whitespace".to_string());
+    }
+    let mut names = Vec::new();
+    for segment in split_segments(template) {
+        if segment.is_empty() {
+            return Some("path contains an empty segment".to_string());
+        }
+        match param_name(segment) {
+            Some(name) if name.contains(['{', '}']) => {
+                return Some(format!("invalid parameter segment '{segment}'"));
+            }
+            Some(name) if names.contains(&name) => {
+                return Some(format!("duplicate parameter '{name}'"));
+            }
+            Some(name) => names.push(name),
+            None if segment.contains(['{', '}']) => {
+                return Some(format!("invalid parameter segment '{segment}'"));
+            }
+            None => {}
+        }
+    }
+    None
+}
+
+/// Template with parameter names erased, so that templates matching the same
+/// paths compare equal (e.g. "users/{id}" and "/users/{user_id}").
+pub(crate) fn template_shape(template: &str) -> String {
+    split_segments(template)
+        .map(|s| if param_name(s).is_some() { "{}" } else { s })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Attempts to match `path` against `template`, returning the captured
+/// parameters on success.
+pub(crate) fn match_template(template: &str, path: &str) -> Option<HashMap<String, String>> {
+    let template_segments: Vec<&str> = split_segments(template).collect();
+    let path_segments: Vec<&str> = split_segments(path).collect();
+    if template_segments.len() != path_segments.len() {
+        return None;
+    }
+    let mut params = HashMap::new();
+    for (t, p) in template_segments.iter().zip(path_segments.iter()) {
+        match param_name(t) {
+            Some(name) => {
+                if p.is_empty() {
+                    return None;
+                }
+                params.insert(name.to_string(), p.to_string());
+            }
+            None => {
+                if t != p {
+                    return None;
+                }
+            }
+        }
+    }
+    Some(params)
+}
+
+/// Segments of `template`, with `{param}` segments replaced by their value in
+/// `params`. Returns the name of the first missing parameter on failure.
+pub(crate) fn fill_template<'a>(
+    template: &'a str,
+    params: &[(&str, &'a str)],
+) -> Result<Vec<&'a str>, &'a str> {
+    split_segments(template)
+        .map(|segment| match param_name(segment) {
+            Some(name) => params
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| *value)
+                .ok_or(name),
+            None => Ok(segment),
+        })
+        .collect()
+}
+
+/// Finds the most specific template matching `path`, given `(key, template)`
+/// pairs. Templates with more literal segments take precedence, and ties are
+/// broken alphabetically so that the result does not depend on map iteration
+/// order.
+pub(crate) fn find_best_match<'a, K>(
+    routes: impl Iterator<Item = (K, &'a str)>,
+    path: &str,
+) -> Option<(K, HashMap<String, String>)> {
+    routes
+        .filter(|(_, template)| is_template(template))
+        .filter_map(|(key, template)| {
+            match_template(template, path).map(|params| (key, template, params))
+        })
+        .max_by(|(_, a, _), (_, b, _)| {
+            literal_count(a)
+                .cmp(&literal_count(b))
+                .then_with(|| b.cmp(a))
+        })
+        .map(|(key, _, params)| (key, params))
+}
+
+// Helper functions.
+// --------------------------------------------------
+
+fn split_segments(path: &str) -> impl Iterator<Item = &str> {
+    path.trim_matches('/').split('/')
+}
+
+fn param_name(segment: &str) -> Option<&str> {
+    segment
+        .strip_prefix('{')
+        .and_then(|s| s.strip_suffix('}'))
+        .filter(|name| !name.is_empty())
+}
+
+fn literal_count(template: &str) -> usize {
+    split_segments(template)
+        .filter(|s| param_name(s).is_none())
+        .count()
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_template() {
+        assert!(is_template("users/{user_id}"));
+        assert!(!is_template("users/list"));
+        assert!(!is_template("users/{}"));
+        assert_eq!(
+            template_params("users/{user_id}/orders/{order_id}"),
+            vec!["user_id", "order_id"]
+        );
+    }
+
+    #[test]
+    fn test_match_template() {
+        let params =
+            match_template("users/{user_id}/orders/{order_id}", "users/abc/orders/123").unwrap();
+        assert_eq!(params.get("user_id").unwrap(), "abc");
+        assert_eq!(params.get("order_id").unwrap(), "123");
+
+        assert!(match_template("users/{user_id}", "users/abc/orders").is_none());
+        assert!(match_template("users/{user_id}", "accounts/abc").is_none());
+        assert!(match_template("users/{user_id}", "users/").is_none());
+    }
+
+    #[test]
+    fn test_template_problem() {
+        assert_eq!(template_problem("users/{user_id}/orders"), None);
+        assert!(template_problem("/").is_some());
+        assert!(template_problem("users//orders").is_some());
+        assert!(template_problem("users/{user_id").is_some());
+        assert!(template_problem("users/{}").is_some());
+        assert!(template_problem("a/{id}/b/{id}").is_some());
+        assert_eq!(
+            template_shape("/users/{id}/"),
+            template_shape("users/{user_id}")
+        );
+    }
+
+    #[test]
+    fn test_fill_template() {
+        assert_eq!(
+            fill_template("users/{user_id}/orders", &[("user_id", "a b")]),
+            Ok(vec!["users", "a b", "orders"])
+        );
+        assert_eq!(fill_template("users/{user_id}", &[]), Err("user_id"));
+    }
+
+    #[test]
+    fn test_find_best_match_prefers_literal_segments() {
+        let routes = ["users/{user_id}", "users/me", "users/{user_id}/orders"];
+        let (route, params) =
+            find_best_match(routes.into_iter().map(|r| (r, r)), "users/abc").unwrap();
+        assert_eq!(route, "users/{user_id}");
+        assert_eq!(params.get("user_id").unwrap(), "abc");
+
+        let routes = ["{a}/orders", "users/{user_id}"];
+        let (route, _) =
+            find_best_match(routes.into_iter().map(|r| (r, r)), "users/orders").unwrap();
+        assert_eq!(route, "users/{user_id}");
+
+        assert!(find_best_match(routes.into_iter().map(|r| (r, r)), "other").is_none());
+    }
+}
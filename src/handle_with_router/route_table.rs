@@ -0,0 +1,444 @@
+use std::sync::Arc;
+
+use aws_lambda_events::http::Method;
+use fractic_server_error::ServerError;
+use serde::{Serialize, Serializer};
+
+use crate::{
+    errors::InvalidRoutingConfig,
+    handle_with_router::{
+        middleware::{Middleware, RouterMiddleware},
+        path_templates::{is_template, match_template, template_problem, template_shape},
+        routes::RouteSpec,
+        routing_config::{
+            parse_route_key, split_route_key, Access, AccessRule, AppState, CrudOperationKind,
+            CrudSpec, FunctionSpec, RoutingConfig, Validation,
+        },
+        std::function_specs::NullaryFunction,
+    },
+    shared::{
+        api_keys::ApiKeyStore, auth_utils::RoleConfig, cors::CorsPolicy,
+        identity::IdentityExtractor, response_building::ErrorMode,
+    },
+};
+
+// Route table validation and introspection.
+// --------------------------------------------------
+//
+// `RoutingConfig` routes are plain maps, so mistakes in route keys only show
+// up as requests being routed elsewhere (e.g. a function route "items"
+// silently shadows POST requests to the CRUD route "items"). The builder
+// below checks the keys once, at construction time, and `routes` lists the
+// resulting table for audits:
+//
+//   let config = RoutingConfig::builder()
+//       .function("GET users/{user_id}", get_user_spec)
+//       .crud("items", items_spec)
+//       .routes_endpoint("GET _routes", Access::Admin)
+//       .build()?;
+
+/// Entry of the route table. CRUD routes have one entry per operation.
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteSummary {
+    /// Key in `RoutingConfig::function_routes` or `RoutingConfig::crud_routes`.
+    pub key: &'static str,
+    #[serde(serialize_with = "serialize_method")]
+    pub method: Method,
+    /// Path without leading or trailing slashes (possibly templated).
+    pub path: String,
+    /// `None` for function routes.
+    pub operation: Option<CrudOperationKind>,
+    /// `None` if the spec does not describe its access (see
+    /// `FunctionSpec::info`).
+    pub access: Option<AccessRule>,
+}
+
+impl RoutingConfig {
+    pub fn builder() -> RoutingConfigBuilder {
+        RoutingConfigBuilder::default()
+    }
+
+    /// Lists all routes, sorted by path and method.
+    pub fn routes(&self) -> Vec<RouteSummary> {
+        let mut routes = Vec::new();
+        for (&key, spec) in &self.function_routes {
+            let (method, path) = split_route_key(key);
+            routes.push(RouteSummary {
+                key,
+                method,
+                path: path.trim_matches('/').to_string(),
+                operation: None,
+                access: spec.info().access,
+            });
+        }
+        for (&key, spec) in &self.crud_routes {
+            let info = spec.info();
+            for operation in CrudOperationKind::ALL {
+                routes.push(RouteSummary {
+                    key,
+                    method: operation.method(),
+                    path: key.to_string(),
+                    operation: Some(operation),
+                    access: info.access_of(operation).cloned(),
+                });
+            }
+        }
+        routes.sort_by(|a, b| {
+            (&a.path, a.method.as_str(), a.operation, a.key).cmp(&(
+                &b.path,
+                b.method.as_str(),
+                b.operation,
+                b.key,
+            ))
+        });
+        routes
+    }
+
+    /// Checks the route keys for malformed keys and for routes shadowing each
+    /// other. All problems found are listed in the error.
+    pub fn validate(&self) -> Result<(), ServerError> {
+        into_result((), self.problems())
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut function_keys: Vec<&str> = self.function_routes.keys().copied().collect();
+        function_keys.sort();
+        let mut crud_keys: Vec<&str> = self.crud_routes.keys().copied().collect();
+        crud_keys.sort();
+
+        for (i, key) in function_keys.iter().enumerate() {
+            if let Some(problem) = function_key_problem(key) {
+                problems.push(format!("function route '{key}': {problem}"));
+                continue;
+            }
+            let (method, path) = split_route_key(key);
+            for other in &function_keys[..i] {
+                let (other_method, other_path) = split_route_key(other);
+                if method == other_method && template_shape(path) == template_shape(other_path) {
+                    problems.push(format!(
+                        "function routes '{other}' and '{key}' match the same requests"
+                    ));
+                }
+            }
+            for crud_key in &crud_keys {
+                let shadows = if is_template(path) {
+                    match_template(path, crud_key).is_some()
+                } else {
+                    path.trim_matches('/') == *crud_key
+                };
+                if shadows {
+                    problems.push(format!(
+                        "function route '{key}' shadows {method} requests to CRUD route '{crud_key}'"
+                    ));
+                }
+            }
+        }
+        for key in &crud_keys {
+            if let Some(problem) = crud_key_problem(key) {
+                problems.push(format!("CRUD route '{key}': {problem}"));
+            }
+        }
+
+        problems
+    }
+}
+
+/// Builds a `RoutingConfig`, validating the routes (see
+/// `RoutingConfig::validate`) and rejecting keys registered twice or with an
+/// unknown method prefix.
+#[derive(Default)]
+pub struct RoutingConfigBuilder {
+    config: RoutingConfig,
+    /// Problems found while registering routes, reported by `build`.
+    problems: Vec<String>,
+    routes_endpoint: Option<(&'static str, Access)>,
+}
+
+impl RoutingConfigBuilder {
+    pub fn function(mut self, key: &'static str, spec: Box<dyn FunctionSpec>) -> Self {
+        // `FunctionRoutes::insert` panics on such keys.
+        if let Err(problem) = parse_route_key(key) {
+            self.problems
+                .push(format!("function route '{key}': {problem}"));
+        } else if self.config.function_routes.insert(key, spec).is_some() {
+            self.problems
+                .push(format!("function route '{key}' is registered twice"));
+        }
+        self
+    }
+
+    pub fn crud(mut self, key: &'static str, spec: Box<dyn CrudSpec>) -> Self {
+        if self.config.crud_routes.insert(key, spec).is_some() {
+            self.problems
+                .push(format!("CRUD route '{key}' is registered twice"));
+        }
+        self
+    }
+
+    /// Registers a spec built for a declared route under the route's key (see
+    /// `Function::for_route`).
+    pub fn function_route(self, route: RouteSpec<dyn FunctionSpec>) -> Self {
+        self.function(route.key, route.spec)
+    }
+
+    /// Registers a spec built for a declared route under the route's key (see
+    /// `Crud::for_route`).
+    pub fn crud_route(self, route: RouteSpec<dyn CrudSpec>) -> Self {
+        self.crud(route.key, route.spec)
+    }
+
+    pub fn state(mut self, state: AppState) -> Self {
+        self.config.state = Some(state);
+        self
+    }
+
+    pub fn cors(mut self, cors: CorsPolicy) -> Self {
+        self.config.cors = Some(cors);
+        self
+    }
+
+    pub fn error_mode(mut self, error_mode: ErrorMode) -> Self {
+        self.config.error_mode = error_mode;
+        self
+    }
+
+    pub fn roles(mut self, roles: RoleConfig) -> Self {
+        self.config.roles = roles;
+        self
+    }
+
+    pub fn identity(mut self, identity: impl IdentityExtractor + 'static) -> Self {
+        self.config.identity = Arc::new(identity);
+        self
+    }
+
+    pub fn api_keys(mut self, store: impl ApiKeyStore + 'static) -> Self {
+        self.config.api_keys = Some(Arc::new(store));
+        self
+    }
+
+    /// Adds middleware applying to all requests (see `RouterMiddleware`).
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.config
+            .middleware
+            .push(RouterMiddleware::global(middleware));
+        self
+    }
+
+    /// Adds middleware applying to the routes under `prefix` (see
+    /// `RouterMiddleware`).
+    pub fn group_middleware(
+        mut self,
+        prefix: &'static str,
+        middleware: impl Middleware + 'static,
+    ) -> Self {
+        self.config
+            .middleware
+            .push(RouterMiddleware::group(prefix, middleware));
+        self
+    }
+
+    /// Adds a function route returning the route table (see
+    /// `RoutingConfig::routes`), e.g. `routes_endpoint("GET _routes",
+    /// Access::Admin)`.
+    pub fn routes_endpoint(mut self, key: &'static str, access: Access) -> Self {
+        self.routes_endpoint = Some((key, access));
+        self
+    }
+
+    pub fn build(mut self) -> Result<RoutingConfig, ServerError> {
+        if let Some((key, access)) = self.routes_endpoint.take() {
+            // Registered with an empty table first, so that the endpoint is
+            // listed in the table it returns.
+            self = self.function(key, routes_function(access.clone(), Vec::new()));
+            if self.config.function_routes.contains_key(key) {
+                let routes = self.config.routes();
+                self.config
+                    .function_routes
+                    .insert(key, routes_function(access, routes));
+            }
+        }
+        let mut problems = std::mem::take(&mut self.problems);
+        problems.extend(self.config.problems());
+        into_result(self.config, problems)
+    }
+}
+
+// Helper functions.
+// --------------------------------------------------
+
+fn into_result<T>(value: T, problems: Vec<String>) -> Result<T, ServerError> {
+    if problems.is_empty() {
+        Ok(value)
+    } else {
+        Err(InvalidRoutingConfig::new(&problems.join("; ")))
+    }
+}
+
+fn routes_function(access: Access, routes: Vec<RouteSummary>) -> Box<dyn FunctionSpec> {
+    NullaryFunction::new(access, Validation::None, move || {
+        let routes = routes.clone();
+        async move { Ok(routes) }
+    })
+}
+
+fn function_key_problem(key: &str) -> Option<String> {
+    match parse_route_key(key) {
+        Ok((_, path)) => template_problem(path),
+        Err(problem) => Some(problem),
+    }
+}
+
+fn crud_key_problem(key: &str) -> Option<String> {
+    // CRUD routes are looked up by exact match of the request path.
+    if key.starts_with('/') || key.ends_with('/') {
+        return Some("key must not start or end with '/'".to_string());
+    }
+    if key.contains(['{', '}']) {
+        return Some("key must not be templated".to_string());
+    }
+    template_problem(key)
+}
+
+fn serialize_method<S: Serializer>(method: &Method, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(method.as_str())
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle_with_router::{
+        routes::FunctionRoute,
+        routing_config::{CrudAccess, CrudInfo, RouteContext},
+        std::function_specs::PathFunction,
+    };
+    use async_trait::async_trait;
+    use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+    use lambda_runtime::Error;
+
+    struct TestCrud;
+
+    #[async_trait]
+    impl CrudSpec for TestCrud {
+        async fn resolve(
+            &self,
+            _request: &ApiGatewayProxyRequest,
+            _ctx: &RouteContext,
+        ) -> Result<ApiGatewayProxyResponse, Error> {
+            Ok(ApiGatewayProxyResponse::default())
+        }
+
+        fn info(&self) -> CrudInfo {
+            CrudInfo {
+                access: CrudAccess {
+                    list: Access::AnyUser,
+                    ..Default::default()
+                }
+                .rules(),
+                ..Default::default()
+            }
+        }
+    }
+
+    struct Echo;
+
+    impl FunctionRoute for Echo {
+        const KEY: &'static str = "POST echo/{id}";
+        type Path = EchoPath;
+        type Input = String;
+        type Output = String;
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct EchoPath {
+        id: String,
+    }
+
+    fn ping() -> Box<dyn FunctionSpec> {
+        NullaryFunction::new(Access::Guest, Validation::None, || async { Ok(()) })
+    }
+
+    #[test]
+    fn test_build() {
+        let config = RoutingConfig::builder()
+            .function("GET users/{user_id}", ping())
+            .function("users/{user_id}", ping())
+            .crud("items", Box::new(TestCrud))
+            .routes_endpoint("GET _routes", Access::Admin)
+            .build()
+            .unwrap();
+
+        let routes = config.routes();
+        assert_eq!(routes.len(), 3 + CrudOperationKind::ALL.len());
+        assert_eq!(routes[0].key, "GET _routes");
+        assert_eq!(routes[0].access.as_ref().unwrap().to_string(), "admin");
+        let list = routes
+            .iter()
+            .find(|r| r.operation == Some(CrudOperationKind::List))
+            .unwrap();
+        assert_eq!((list.method.as_str(), list.path.as_str()), ("GET", "items"));
+        assert_eq!(list.access.as_ref().unwrap().to_string(), "any_user");
+        assert_eq!(
+            serde_json::to_string(&routes[0]).unwrap(),
+            r#"{"key":"GET _routes","method":"GET","path":"_routes","operation":null,"access":"admin"}"#
+        );
+    }
+
+    #[test]
+    fn test_function_route() {
+        let route = PathFunction::for_route(
+            Echo,
+            Access::Guest,
+            Validation::None,
+            |path: EchoPath, input: String| async move { Ok(format!("{}: {input}", path.id)) },
+        );
+        assert_eq!(route.key, "POST echo/{id}");
+        let config = RoutingConfig::builder()
+            .function_route(route)
+            .build()
+            .unwrap();
+        assert!(config.function_routes.contains_key("POST echo/{id}"));
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let error = RoutingConfig::builder()
+            .function("items", ping())
+            .function("GET {name}", ping())
+            .function("GET users/{id}", ping())
+            .function("GET /users/{user_id}", ping())
+            .crud("items", Box::new(TestCrud))
+            .crud("items", Box::new(TestCrud))
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("CRUD route 'items' is registered twice"));
+        assert!(error.contains("function route 'items' shadows POST requests"));
+        assert!(error.contains("function route 'GET {name}' shadows GET requests"));
+        assert!(error.contains("'GET /users/{user_id}' and 'GET users/{id}' match the same"));
+    }
+
+    #[test]
+    fn test_malformed_keys() {
+        for key in [
+            "get users",
+            "OPTIONS users",
+            "users//orders",
+            "users/{id",
+            "",
+        ] {
+            let result = RoutingConfig::builder().function(key, ping()).build();
+            assert!(result.is_err(), "{key:?} should be rejected");
+        }
+        for key in ["/items", "items/{id}", "my items"] {
+            let result = RoutingConfig::builder()
+                .crud(key, Box::new(TestCrud))
+                .build();
+            assert!(result.is_err(), "{key:?} should be rejected");
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use fractic_aws_dynamo::schema::DynamoObject;
+use serde::{de::DeserializeOwned, Serialize};
+
+// Route definitions.
+// --------------------------------------------------
+//
+// Routes can be declared once (e.g. in a crate shared by the server and its
+// clients), so that the route keys and types can't drift apart:
+//
+//   #[derive(Serialize, Deserialize)]
+//   pub struct UserPath {
+//       pub user_id: String,
+//   }
+//
+//   pub struct RenameUser;
+//   impl FunctionRoute for RenameUser {
+//       const KEY: &'static str = "users/{user_id}/rename";
+//       type Path = UserPath;
+//       type Input = NewName;
+//       type Output = User;
+//   }
+//
+// Server (`for_route` checks that `rename_user` takes `UserPath` and
+// `NewName`, and returns `User`):
+//
+//   let config = RoutingConfig::builder()
+//       .function_route(PathFunction::for_route(
+//           RenameUser,
+//           Access::Admin,
+//           Validation::None,
+//           rename_user,
+//       ))
+//       .build()?;
+//
+// Client (see `ApiClient`):
+//
+//   let user = client
+//       .call_with_path::<RenameUser>(&UserPath { user_id: "123".into() }, &new_name)
+//       .await?;
+
+pub trait FunctionRoute {
+    /// Key of the route in `RoutingConfig::function_routes` (e.g.
+    /// "GET users/{user_id}").
+    const KEY: &'static str;
+    /// Parameters of the key's `{param}` segments, deserialized from (and
+    /// serialized into) an object keyed by parameter name. `()` for routes
+    /// without parameters.
+    type Path: Serialize + DeserializeOwned;
+    /// `()` for routes without input.
+    type Input: Serialize + DeserializeOwned;
+    type Output: Serialize + DeserializeOwned;
+}
+
+pub trait CrudRoute {
+    /// Key of the route in `RoutingConfig::crud_routes`.
+    const KEY: &'static str;
+    type Item: DynamoObject + Serialize + DeserializeOwned;
+    type Output: Serialize + DeserializeOwned;
+}
+
+/// Spec built for a declared route (e.g. through `Function::for_route` or
+/// `Crud::for_route`), along with the route's key.
+pub struct RouteSpec<S: ?Sized> {
+    pub key: &'static str,
+    pub spec: Box<S>,
+}
+
+impl<S: ?Sized> RouteSpec<S> {
+    pub(crate) fn new(key: &'static str, spec: Box<S>) -> Self {
+        Self { key, spec }
+    }
+}
@@ -1,16 +1,34 @@
-use std::collections::HashMap;
+use std::{
+    any::{Any, TypeId},
+    borrow::Cow,
+    collections::{hash_map, HashMap},
+    fmt,
+    sync::Arc,
+};
 
 use async_trait::async_trait;
 use aws_lambda_events::{
     apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse},
-    http::Method,
+    http::{header::ORIGIN, Method},
 };
 use fractic_server_error::{define_sensitive_error, ServerError};
 use lambda_runtime::{Error, LambdaEvent};
+use serde::{Serialize, Serializer};
 
 use crate::{
-    errors::InvalidRouteError,
-    shared::{request_processing::RequestMetadata, response_building::build_err},
+    handle_with_router::{
+        middleware::{Middleware, Next, RouterMiddleware, Target},
+        path_templates::{find_best_match, is_template},
+    },
+    shared::{
+        api_events::ApiEvent,
+        api_keys::{authenticate_api_key, ApiKeyStore},
+        auth_utils::RoleConfig,
+        cors::CorsPolicy,
+        identity::{CognitoIdentity, IdentityExtractor},
+        request_processing::RequestMetadata,
+        response_building::{ErrorMode, ResponseOptions},
+    },
 };
 
 define_sensitive_error!(
@@ -23,7 +41,7 @@ define_sensitive_error!(
 // --------------------------------------------------
 
 /// Access control for non-owned routes.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Access {
     /// Any user, including unauthenticated users.
     Guest,
@@ -31,11 +49,23 @@ pub enum Access {
     AnyUser,
     /// Only admin users.
     Admin,
+    /// Users in at least one of the given user pool groups (e.g.
+    /// `Access::AnyOf(&["editor", "moderator"])`).
+    AnyOf(&'static [&'static str]),
+    /// Users in all of the given user pool groups.
+    AllOf(&'static [&'static str]),
+    /// Tokens granted the given OAuth2 scope (e.g. "orders/read"), such as
+    /// client-credentials tokens of machine clients.
+    Scope(&'static str),
+    /// Any valid API key (see `ApiKeyStore`).
+    ApiKey,
+    /// API keys granted the given permission (e.g. "orders:read").
+    ApiKeyPermission(&'static str),
     /// All access is denied.
     None,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CrudAccess {
     pub list: Access,
     pub create: Access,
@@ -65,7 +95,7 @@ impl Default for CrudAccess {
 }
 
 /// Access control for owned routes.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum OwnedAccess {
     /// Any user, including unauthenticated users.
     Guest,
@@ -77,11 +107,17 @@ pub enum OwnedAccess {
     Admin,
     /// Owner or admin users.
     OwnerOrAdmin,
+    /// Users in at least one of the given user pool groups.
+    AnyOf(&'static [&'static str]),
+    /// Users in all of the given user pool groups.
+    AllOf(&'static [&'static str]),
+    /// Tokens granted the given OAuth2 scope.
+    Scope(&'static str),
     /// All access is denied.
     None,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct OwnedCrudAccess {
     pub list: OwnedAccess,
     pub create: OwnedAccess,
@@ -110,13 +146,71 @@ impl Default for OwnedCrudAccess {
     }
 }
 
+/// Application state, built once per cold start and shared by all requests.
+/// Handlers access it through `RequestContext::state` or the `State<S>`
+/// extractor.
+pub type AppState = Arc<dyn Any + Send + Sync>;
+
+/// Router-level values passed to specs while resolving a request.
+#[derive(Clone)]
+pub struct RouteContext {
+    pub state: Option<AppState>,
+    pub error_mode: ErrorMode,
+    pub roles: RoleConfig,
+    pub identity: Arc<dyn IdentityExtractor>,
+    pub api_keys: Option<Arc<dyn ApiKeyStore>>,
+}
+
+impl Default for RouteContext {
+    fn default() -> Self {
+        Self {
+            state: None,
+            error_mode: ErrorMode::default(),
+            roles: RoleConfig::default(),
+            identity: Arc::new(CognitoIdentity),
+            api_keys: None,
+        }
+    }
+}
+
+impl RouteContext {
+    /// Parses the request metadata, with the router's `IdentityExtractor`,
+    /// `RoleConfig` and `ApiKeyStore`.
+    pub async fn request_metadata(
+        &self,
+        request: &ApiGatewayProxyRequest,
+    ) -> Result<RequestMetadata, ServerError> {
+        let identity = self.identity.extract(request).await?;
+        let mut metadata = RequestMetadata::from_identity(identity, &self.roles);
+        if let Some(store) = &self.api_keys {
+            metadata.api_key = authenticate_api_key(store.as_ref(), request).await?;
+        }
+        Ok(metadata)
+    }
+
+    /// Options with which specs should build their responses.
+    pub fn response_options(&self, request: &ApiGatewayProxyRequest) -> ResponseOptions {
+        ResponseOptions {
+            error_mode: self.error_mode,
+            ..ResponseOptions::negotiate(request)
+        }
+    }
+}
+
 /// Trait implemented by function route specifications.
 #[async_trait]
 pub trait FunctionSpec: Send + Sync {
     async fn resolve(
         &self,
         request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
     ) -> Result<ApiGatewayProxyResponse, Error>;
+
+    /// Describes the route for documentation. Custom specs may leave this
+    /// empty.
+    fn info(&self) -> FunctionInfo {
+        FunctionInfo::default()
+    }
 }
 
 /// Trait implemented by CRUD route specifications.
@@ -125,7 +219,295 @@ pub trait CrudSpec: Send + Sync {
     async fn resolve(
         &self,
         request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
     ) -> Result<ApiGatewayProxyResponse, Error>;
+
+    /// Describes the route for documentation. Custom specs may leave this
+    /// empty.
+    fn info(&self) -> CrudInfo {
+        CrudInfo::default()
+    }
+}
+
+#[async_trait]
+impl<S: FunctionSpec + ?Sized> FunctionSpec for Box<S> {
+    async fn resolve(
+        &self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        FunctionSpec::resolve(self.as_ref(), request, ctx).await
+    }
+
+    fn info(&self) -> FunctionInfo {
+        FunctionSpec::info(self.as_ref())
+    }
+}
+
+#[async_trait]
+impl<S: CrudSpec + ?Sized> CrudSpec for Box<S> {
+    async fn resolve(
+        &self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        CrudSpec::resolve(self.as_ref(), request, ctx).await
+    }
+
+    fn info(&self) -> CrudInfo {
+        CrudSpec::info(self.as_ref())
+    }
+}
+
+/// Wraps a function or CRUD spec to override the router's `ErrorMode` for a
+/// single route.
+pub struct WithErrorMode<S> {
+    pub error_mode: ErrorMode,
+    pub spec: S,
+}
+
+impl<S> WithErrorMode<S> {
+    pub fn new(error_mode: ErrorMode, spec: S) -> Self {
+        Self { error_mode, spec }
+    }
+}
+
+#[async_trait]
+impl<S: FunctionSpec> FunctionSpec for WithErrorMode<S> {
+    async fn resolve(
+        &self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let ctx = RouteContext {
+            error_mode: self.error_mode,
+            ..ctx.clone()
+        };
+        FunctionSpec::resolve(&self.spec, request, &ctx).await
+    }
+
+    fn info(&self) -> FunctionInfo {
+        FunctionSpec::info(&self.spec)
+    }
+}
+
+#[async_trait]
+impl<S: CrudSpec> CrudSpec for WithErrorMode<S> {
+    async fn resolve(
+        &self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let ctx = RouteContext {
+            error_mode: self.error_mode,
+            ..ctx.clone()
+        };
+        CrudSpec::resolve(&self.spec, request, &ctx).await
+    }
+
+    fn info(&self) -> CrudInfo {
+        CrudSpec::info(&self.spec)
+    }
+}
+
+// Route descriptions.
+// --------------------------------------------------
+
+/// Rust type used by a route. Documentation generators look up schemas by
+/// `id` (see `OpenApi`), and fall back to `name` otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeRef {
+    pub id: TypeId,
+    pub name: &'static str,
+}
+
+impl TypeRef {
+    pub fn of<T: ?Sized + 'static>() -> Self {
+        Self {
+            id: TypeId::of::<T>(),
+            name: std::any::type_name::<T>(),
+        }
+    }
+}
+
+/// Access control of a route (or of a single CRUD operation).
+#[derive(Debug, Clone)]
+pub enum AccessRule {
+    Access(Access),
+    Owned(OwnedAccess),
+}
+
+impl AccessRule {
+    /// True if all access is denied.
+    pub fn is_none(&self) -> bool {
+        matches!(
+            self,
+            AccessRule::Access(Access::None) | AccessRule::Owned(OwnedAccess::None)
+        )
+    }
+
+    /// True if unauthenticated users are allowed.
+    pub fn allows_guests(&self) -> bool {
+        matches!(
+            self,
+            AccessRule::Access(Access::Guest) | AccessRule::Owned(OwnedAccess::Guest)
+        )
+    }
+
+    /// True if callers authenticate with an API key rather than a token.
+    pub fn uses_api_key(&self) -> bool {
+        matches!(
+            self,
+            AccessRule::Access(Access::ApiKey | Access::ApiKeyPermission(_))
+        )
+    }
+}
+
+impl fmt::Display for AccessRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AccessRule::Access(Access::Guest) | AccessRule::Owned(OwnedAccess::Guest) => "guest",
+            AccessRule::Access(Access::AnyUser) | AccessRule::Owned(OwnedAccess::AnyUser) => {
+                "any_user"
+            }
+            AccessRule::Access(Access::Admin) | AccessRule::Owned(OwnedAccess::Admin) => "admin",
+            AccessRule::Owned(OwnedAccess::Owner) => "owner",
+            AccessRule::Owned(OwnedAccess::OwnerOrAdmin) => "owner_or_admin",
+            AccessRule::Access(Access::AnyOf(groups))
+            | AccessRule::Owned(OwnedAccess::AnyOf(groups)) => {
+                return write!(f, "any_of({})", groups.join(","));
+            }
+            AccessRule::Access(Access::AllOf(groups))
+            | AccessRule::Owned(OwnedAccess::AllOf(groups)) => {
+                return write!(f, "all_of({})", groups.join(","));
+            }
+            AccessRule::Access(Access::Scope(scope))
+            | AccessRule::Owned(OwnedAccess::Scope(scope)) => {
+                return write!(f, "scope({scope})");
+            }
+            AccessRule::Access(Access::ApiKey) => "api_key",
+            AccessRule::Access(Access::ApiKeyPermission(permission)) => {
+                return write!(f, "api_key_permission({permission})");
+            }
+            AccessRule::Access(Access::None) | AccessRule::Owned(OwnedAccess::None) => "none",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Serialized as its display name (e.g. "owner_or_admin" or
+/// "any_of(editor,moderator)").
+impl Serialize for AccessRule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Description of a function route.
+#[derive(Debug, Clone, Default)]
+pub struct FunctionInfo {
+    pub access: Option<AccessRule>,
+    /// Type the route's path parameters are parsed into, if any.
+    pub path_params: Option<TypeRef>,
+    /// Type of the request body (or query string, for GET functions), if any.
+    pub input: Option<TypeRef>,
+    pub output: Option<TypeRef>,
+}
+
+/// Operations of a CRUD route, as selected by the request's method and query
+/// parameters (see `CrudOperation`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrudOperationKind {
+    List,
+    Create,
+    Read,
+    Update,
+    Delete,
+    DeleteAll,
+    ReplaceAll,
+}
+
+impl CrudOperationKind {
+    pub const ALL: [CrudOperationKind; 7] = [
+        CrudOperationKind::List,
+        CrudOperationKind::Create,
+        CrudOperationKind::Read,
+        CrudOperationKind::Update,
+        CrudOperationKind::Delete,
+        CrudOperationKind::DeleteAll,
+        CrudOperationKind::ReplaceAll,
+    ];
+
+    /// HTTP method of the operation. Operations sharing a method are told
+    /// apart by query parameters (e.g. 'all' for lists).
+    pub fn method(&self) -> Method {
+        match self {
+            CrudOperationKind::List | CrudOperationKind::Read => Method::GET,
+            CrudOperationKind::Create | CrudOperationKind::ReplaceAll => Method::POST,
+            CrudOperationKind::Update => Method::PUT,
+            CrudOperationKind::Delete | CrudOperationKind::DeleteAll => Method::DELETE,
+        }
+    }
+}
+
+/// Description of a CRUD route.
+#[derive(Debug, Clone, Default)]
+pub struct CrudInfo {
+    /// Access control of each operation.
+    pub access: Vec<(CrudOperationKind, AccessRule)>,
+    pub allow_non_recursive_delete: bool,
+    pub allow_batching: bool,
+    /// Item type (used by updates).
+    pub item: Option<TypeRef>,
+    /// Data of new items (used by creates).
+    pub data: Option<TypeRef>,
+    pub output: Option<TypeRef>,
+}
+
+impl CrudInfo {
+    pub fn access_of(&self, operation: CrudOperationKind) -> Option<&AccessRule> {
+        self.access
+            .iter()
+            .find(|(kind, _)| *kind == operation)
+            .map(|(_, rule)| rule)
+    }
+}
+
+impl CrudAccess {
+    pub(crate) fn rules(&self) -> Vec<(CrudOperationKind, AccessRule)> {
+        use CrudOperationKind as K;
+        [
+            (K::List, &self.list),
+            (K::Create, &self.create),
+            (K::Read, &self.read),
+            (K::Update, &self.update),
+            (K::Delete, &self.delete),
+            (K::DeleteAll, &self.delete_all),
+            (K::ReplaceAll, &self.replace_all),
+        ]
+        .into_iter()
+        .map(|(kind, access)| (kind, AccessRule::Access(access.clone())))
+        .collect()
+    }
+}
+
+impl OwnedCrudAccess {
+    pub(crate) fn rules(&self) -> Vec<(CrudOperationKind, AccessRule)> {
+        use CrudOperationKind as K;
+        [
+            (K::List, &self.list),
+            (K::Create, &self.create),
+            (K::Read, &self.read),
+            (K::Update, &self.update),
+            (K::Delete, &self.delete),
+            (K::DeleteAll, &self.delete_all),
+            (K::ReplaceAll, &self.replace_all),
+        ]
+        .into_iter()
+        .map(|(kind, access)| (kind, AccessRule::Owned(access.clone())))
+        .collect()
+    }
 }
 
 pub enum Validation<T> {
@@ -144,9 +526,175 @@ pub trait ValidatorSpec<T>: Send + Sync {
     ) -> Result<(), ServerError>;
 }
 
+/// Requests can be REST API, HTTP API, Lambda Function URL or ALB events (see
+/// `ApiEvent`).
+///
+/// Function route keys may be prefixed by an HTTP method (e.g. "GET users"),
+/// and default to POST otherwise. Keys with an unknown method prefix (e.g.
+/// "get users") are rejected when inserted (see `FunctionRoutes::insert`).
+/// GET functions read their input from the query string instead of the body
+/// (see `parse_request_input`).
+///
+/// Function route keys may also be templated (e.g.
+/// "users/{user_id}/orders/{order_id}"). Exact keys take precedence over
+/// templates, and captured segments are passed to the spec through the
+/// request's `path_parameters` (see `parse_path_params`).
 pub struct RoutingConfig {
-    pub function_routes: HashMap<&'static str, Box<dyn FunctionSpec>>,
+    pub function_routes: FunctionRoutes,
     pub crud_routes: HashMap<&'static str, Box<dyn CrudSpec>>,
+    /// Optional application state (see `AppState`). When using
+    /// `aws_lambda_handle_with_router!`, prefer passing an async initializer
+    /// to the macro instead.
+    pub state: Option<AppState>,
+    /// CORS policy applied to all responses, also used to answer preflight
+    /// OPTIONS requests. Defaults to `CorsPolicy::global()`.
+    pub cors: Option<CorsPolicy>,
+    /// How errors are returned to the client. Can be overridden per route by
+    /// wrapping the spec in `WithErrorMode`.
+    pub error_mode: ErrorMode,
+    /// Middleware wrapping route resolution, in order (the first entry is the
+    /// outermost). See `Middleware`.
+    pub middleware: Vec<RouterMiddleware>,
+    /// Which groups grant admin access.
+    pub roles: RoleConfig,
+    /// Where the caller's identity is read from. Defaults to
+    /// `CognitoIdentity`.
+    pub identity: Arc<dyn IdentityExtractor>,
+    /// Store of the API keys accepted in the `X-Api-Key` header. If `None`,
+    /// the header is ignored.
+    pub api_keys: Option<Arc<dyn ApiKeyStore>>,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            function_routes: FunctionRoutes::default(),
+            crud_routes: HashMap::new(),
+            state: None,
+            cors: None,
+            error_mode: ErrorMode::default(),
+            middleware: Vec::new(),
+            roles: RoleConfig::default(),
+            identity: Arc::new(CognitoIdentity),
+            api_keys: None,
+        }
+    }
+}
+
+/// Function routes of a `RoutingConfig`, by route key. Keys are parsed as they
+/// are inserted, and routes indexed by method and path, so that requests are
+/// matched without scanning all keys.
+#[derive(Default)]
+pub struct FunctionRoutes {
+    specs: HashMap<&'static str, Box<dyn FunctionSpec>>,
+    by_method: HashMap<Method, MethodRoutes>,
+}
+
+#[derive(Default)]
+struct MethodRoutes {
+    /// Keys of non-templated routes, by path without leading or trailing
+    /// slashes.
+    exact: HashMap<&'static str, &'static str>,
+    /// Keys and paths of templated routes.
+    templates: Vec<(&'static str, &'static str)>,
+}
+
+impl FunctionRoutes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the spec previously registered under `key`, if any.
+    ///
+    /// Panics if `key` has an unknown method prefix (e.g. "get users"), since
+    /// such a route could never be matched. `RoutingConfig::builder` reports
+    /// these keys as errors instead.
+    pub fn insert(
+        &mut self,
+        key: &'static str,
+        spec: Box<dyn FunctionSpec>,
+    ) -> Option<Box<dyn FunctionSpec>> {
+        let (method, path) = parse_route_key(key)
+            .unwrap_or_else(|problem| panic!("function route '{key}': {problem}"));
+        let previous = self.specs.insert(key, spec);
+        if previous.is_none() {
+            let routes = self.by_method.entry(method).or_default();
+            if is_template(path) {
+                routes.templates.push((key, path));
+            } else {
+                routes.exact.insert(path.trim_matches('/'), key);
+            }
+        }
+        previous
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Box<dyn FunctionSpec>> {
+        let (key, spec) = self.specs.remove_entry(key)?;
+        for routes in self.by_method.values_mut() {
+            routes.exact.retain(|_, k| *k != key);
+            routes.templates.retain(|(k, _)| *k != key);
+        }
+        Some(spec)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&dyn FunctionSpec> {
+        self.specs.get(key).map(|spec| spec.as_ref())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.specs.contains_key(key)
+    }
+
+    pub fn keys(&self) -> hash_map::Keys<'_, &'static str, Box<dyn FunctionSpec>> {
+        self.specs.keys()
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<'_, &'static str, Box<dyn FunctionSpec>> {
+        self.specs.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.specs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.specs.is_empty()
+    }
+
+    /// Finds the route for the given method and path, along with the
+    /// parameters captured by templated routes. Exact routes take precedence
+    /// over templates.
+    fn find(
+        &self,
+        method: &Method,
+        path: &str,
+    ) -> Option<(&dyn FunctionSpec, HashMap<String, String>)> {
+        let routes = self.by_method.get(method)?;
+        if let Some(key) = routes.exact.get(path.trim_matches('/')) {
+            return Some((self.specs[key].as_ref(), HashMap::new()));
+        }
+        find_best_match(routes.templates.iter().copied(), path)
+            .map(|(key, params)| (self.specs[key].as_ref(), params))
+    }
+}
+
+impl FromIterator<(&'static str, Box<dyn FunctionSpec>)> for FunctionRoutes {
+    fn from_iter<T: IntoIterator<Item = (&'static str, Box<dyn FunctionSpec>)>>(iter: T) -> Self {
+        let mut routes = Self::default();
+        for (key, spec) in iter {
+            routes.insert(key, spec);
+        }
+        routes
+    }
+}
+
+impl<'a> IntoIterator for &'a FunctionRoutes {
+    type Item = (&'a &'static str, &'a Box<dyn FunctionSpec>);
+    type IntoIter = hash_map::Iter<'a, &'static str, Box<dyn FunctionSpec>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.specs.iter()
+    }
 }
 
 // API Gateway routing utils.
@@ -154,43 +702,89 @@ pub struct RoutingConfig {
 
 impl RoutingConfig {
     // NOTE: Called by macro-generated tokens, so must be publically visible.
-    pub async fn handle(
+    pub async fn handle<E: ApiEvent>(&self, event: LambdaEvent<E>) -> Result<E::Response, Error> {
+        self.handle_with_state(event, self.state.clone()).await
+    }
+
+    /// Like `handle`, but uses the given application state instead of
+    /// `self.state`.
+    pub async fn handle_with_state<E: ApiEvent>(
+        &self,
+        event: LambdaEvent<E>,
+        state: Option<AppState>,
+    ) -> Result<E::Response, Error> {
+        let request = event.payload.into_proxy_request();
+        let response = self.handle_proxy_request(&request, state).await?;
+        Ok(E::from_proxy_response(response, &request))
+    }
+
+    async fn handle_proxy_request(
         &self,
-        event: LambdaEvent<ApiGatewayProxyRequest>,
+        request: &ApiGatewayProxyRequest,
+        state: Option<AppState>,
     ) -> Result<ApiGatewayProxyResponse, Error> {
-        let route_spec = self
-            .find_function_spec(&event)
-            .or_else(|| self.find_crud_spec(&event));
-        match route_spec {
-            Some(RouteSpecRef::Function(spec)) => spec.resolve(&event.payload).await,
-            Some(RouteSpecRef::Crud(spec)) => spec.resolve(&event.payload).await,
-            None => build_err(InvalidRouteError::new(event.payload.path)),
+        let ctx = RouteContext {
+            state,
+            error_mode: self.error_mode,
+            roles: self.roles.clone(),
+            identity: self.identity.clone(),
+            api_keys: self.api_keys.clone(),
+        };
+        let cors = self.cors.as_ref().unwrap_or_else(CorsPolicy::global);
+        let origin = request
+            .headers
+            .get(ORIGIN)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned);
+        if request.http_method == Method::OPTIONS {
+            return Ok(cors.preflight_response(origin.as_deref()));
         }
+        let route_spec = self
+            .find_function_spec(request)
+            .or_else(|| self.find_crud_spec(request));
+        let (request, target) = match route_spec {
+            Some(RouteSpecRef::Function(spec, path_params)) => {
+                if path_params.is_empty() {
+                    (Cow::Borrowed(request), Target::Function(spec))
+                } else {
+                    let mut request = request.clone();
+                    request.path_parameters.extend(path_params);
+                    (Cow::Owned(request), Target::Function(spec))
+                }
+            }
+            Some(RouteSpecRef::Crud(spec)) => (Cow::Borrowed(request), Target::Crud(spec)),
+            None => (Cow::Borrowed(request), Target::NotFound),
+        };
+        // Group middleware also runs for unmatched paths in its group (e.g. so
+        // that auth middleware of "admin" answers "admin/missing" too).
+        let path = request
+            .path_parameters
+            .get("proxy")
+            .or(request.path.as_ref())
+            .map(String::as_str);
+        let middleware: Vec<Arc<dyn Middleware>> = self
+            .middleware
+            .iter()
+            .filter(|m| m.applies_to(path))
+            .map(|m| m.middleware.clone())
+            .collect();
+        let mut response = Next::new(&middleware, target).run(&request, &ctx).await?;
+        cors.apply(origin.as_deref(), &mut response.headers);
+        Ok(response)
     }
 
     fn find_function_spec<'a>(
         &'a self,
-        event: &LambdaEvent<ApiGatewayProxyRequest>,
+        request: &ApiGatewayProxyRequest,
     ) -> Option<RouteSpecRef<'a>> {
-        let method = &event.payload.http_method;
-        if method == Method::POST {
-            event
-                .payload
-                .path_parameters
-                .get("proxy")
-                .and_then(|proxy| self.function_routes.get(proxy.as_str()))
-                .map(|spec| RouteSpecRef::Function(spec.as_ref()))
-        } else {
-            None
-        }
+        let proxy = request.path_parameters.get("proxy")?;
+        self.function_routes
+            .find(&request.http_method, proxy)
+            .map(|(spec, params)| RouteSpecRef::Function(spec, params))
     }
 
-    fn find_crud_spec<'a>(
-        &'a self,
-        event: &LambdaEvent<ApiGatewayProxyRequest>,
-    ) -> Option<RouteSpecRef<'a>> {
-        event
-            .payload
+    fn find_crud_spec<'a>(&'a self, request: &ApiGatewayProxyRequest) -> Option<RouteSpecRef<'a>> {
+        request
             .path_parameters
             .get("proxy")
             .and_then(|proxy| self.crud_routes.get(proxy.as_str()))
@@ -198,8 +792,45 @@ impl RoutingConfig {
     }
 }
 
+/// Methods function routes can be registered for. Extension methods would
+/// parse, but can't be sent through API Gateway (and lowercase methods are
+/// likely typos).
+const ROUTE_METHODS: [Method; 6] = [
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::PATCH,
+    Method::DELETE,
+    Method::HEAD,
+];
+
+/// Splits a function route key into its HTTP method (POST if not specified)
+/// and path, rejecting unknown method prefixes (e.g. "get users").
+pub(crate) fn parse_route_key(key: &str) -> Result<(Method, &str), String> {
+    let Some((prefix, path)) = key.split_once(' ') else {
+        return Ok((Method::POST, key));
+    };
+    match ROUTE_METHODS
+        .iter()
+        .find(|method| method.as_str() == prefix)
+    {
+        Some(method) => Ok((method.clone(), path.trim())),
+        None if prefix == Method::OPTIONS.as_str() => {
+            Err("OPTIONS requests are answered by the CORS policy".to_string())
+        }
+        None => Err(format!("unknown method '{prefix}'")),
+    }
+}
+
+/// Like `parse_route_key`, but treats keys with an unknown method prefix as
+/// POST routes, for keys that were already checked (see
+/// `FunctionRoutes::insert`) or are checked by the server.
+pub(crate) fn split_route_key(key: &str) -> (Method, &str) {
+    parse_route_key(key).unwrap_or((Method::POST, key))
+}
+
 enum RouteSpecRef<'a> {
-    Function(&'a dyn FunctionSpec),
+    Function(&'a dyn FunctionSpec, HashMap<String, String>),
     Crud(&'a dyn CrudSpec),
 }
 
@@ -241,6 +872,14 @@ pub(crate) fn is_allowed_access(metadata: &RequestMetadata, access: &Access) ->
         Access::Guest => true,
         Access::AnyUser => metadata.is_authenticated,
         Access::Admin => metadata.is_authenticated && metadata.is_admin,
+        Access::AnyOf(groups) => metadata.is_authenticated && metadata.has_any_group(groups),
+        Access::AllOf(groups) => metadata.is_authenticated && metadata.has_all_groups(groups),
+        Access::Scope(scope) => metadata.has_token() && metadata.has_scope(scope),
+        Access::ApiKey => metadata.api_key.is_some(),
+        Access::ApiKeyPermission(permission) => metadata
+            .api_key
+            .as_ref()
+            .is_some_and(|key| key.has_permission(permission)),
         Access::None => false,
     }
 }
@@ -254,6 +893,9 @@ pub(crate) fn is_allowed_owned_access(
         OwnedAccess::Guest => true,
         OwnedAccess::AnyUser => metadata.is_authenticated,
         OwnedAccess::Admin => metadata.is_authenticated && metadata.is_admin,
+        OwnedAccess::AnyOf(groups) => metadata.is_authenticated && metadata.has_any_group(groups),
+        OwnedAccess::AllOf(groups) => metadata.is_authenticated && metadata.has_all_groups(groups),
+        OwnedAccess::Scope(scope) => metadata.has_token() && metadata.has_scope(scope),
         OwnedAccess::Owner => match (owner, &metadata.user_sub) {
             (Some(owner_sub), Some(user_sub)) => owner_sub == user_sub,
             _ => false,
@@ -280,6 +922,9 @@ pub(crate) fn preliminary_access_check(metadata: &RequestMetadata, access: &Owne
         OwnedAccess::Guest => true,
         OwnedAccess::AnyUser => metadata.is_authenticated,
         OwnedAccess::Admin => metadata.is_authenticated && metadata.is_admin,
+        OwnedAccess::AnyOf(groups) => metadata.is_authenticated && metadata.has_any_group(groups),
+        OwnedAccess::AllOf(groups) => metadata.is_authenticated && metadata.has_all_groups(groups),
+        OwnedAccess::Scope(scope) => metadata.has_token() && metadata.has_scope(scope),
         OwnedAccess::Owner => metadata.is_authenticated,
         OwnedAccess::OwnerOrAdmin => {
             if metadata.is_authenticated && metadata.is_admin {
@@ -291,3 +936,89 @@ pub(crate) fn preliminary_access_check(metadata: &RequestMetadata, access: &Owne
         OwnedAccess::None => false,
     }
 }
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Not zero-sized, so each boxed spec has its own address.
+    struct Noop(u8);
+
+    #[async_trait]
+    impl FunctionSpec for Noop {
+        async fn resolve(
+            &self,
+            _request: &ApiGatewayProxyRequest,
+            _ctx: &RouteContext,
+        ) -> Result<ApiGatewayProxyResponse, Error> {
+            Ok(ApiGatewayProxyResponse::default())
+        }
+    }
+
+    fn find_key(routes: &FunctionRoutes, method: Method, path: &str) -> Option<&'static str> {
+        let (spec, _) = routes.find(&method, path)?;
+        routes
+            .iter()
+            .find(|(_, s)| std::ptr::addr_eq(s.as_ref(), spec))
+            .map(|(key, _)| *key)
+    }
+
+    #[test]
+    fn test_function_routes() {
+        let mut routes: FunctionRoutes = [
+            "users/{user_id}",
+            "users/me",
+            "GET users/{user_id}",
+            "DELETE /users/{user_id}/",
+        ]
+        .into_iter()
+        .map(|key| (key, Box::new(Noop(0)) as Box<dyn FunctionSpec>))
+        .collect();
+        assert_eq!(
+            find_key(&routes, Method::POST, "/users/me/"),
+            Some("users/me")
+        );
+        assert_eq!(
+            find_key(&routes, Method::POST, "users/abc"),
+            Some("users/{user_id}")
+        );
+        assert_eq!(
+            find_key(&routes, Method::GET, "users/me"),
+            Some("GET users/{user_id}")
+        );
+        assert_eq!(
+            find_key(&routes, Method::DELETE, "users/abc"),
+            Some("DELETE /users/{user_id}/")
+        );
+        assert_eq!(find_key(&routes, Method::PUT, "users/abc"), None);
+
+        let (_, params) = routes.find(&Method::GET, "users/abc").unwrap();
+        assert_eq!(params.get("user_id").unwrap(), "abc");
+
+        routes.remove("users/me");
+        assert_eq!(
+            find_key(&routes, Method::POST, "users/me"),
+            Some("users/{user_id}")
+        );
+    }
+
+    #[test]
+    fn test_unknown_method_prefix() {
+        assert_eq!(
+            parse_route_key("GET users").unwrap(),
+            (Method::GET, "users")
+        );
+        assert_eq!(parse_route_key("users").unwrap(), (Method::POST, "users"));
+        assert!(parse_route_key("get users").is_err());
+        assert!(parse_route_key("OPTIONS users").is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "function route 'get users': unknown method 'get'")]
+    fn test_insert_unknown_method_prefix() {
+        FunctionRoutes::new().insert("get users", Box::new(Noop(0)));
+    }
+}
@@ -0,0 +1,77 @@
+use std::{any::TypeId, collections::HashMap};
+
+use schemars::{generate::SchemaSettings, JsonSchema, Schema, SchemaGenerator};
+
+use crate::handle_with_router::routing_config::TypeRef;
+
+// Schema registry.
+// --------------------------------------------------
+//
+// Route specs only know the Rust types they use (see `TypeRef`), so schemas
+// must be registered for each type that should appear in generated
+// documentation or clients (see `OpenApi` and `TypeScriptClient`). Types
+// without a registered schema are treated as any value.
+//
+// Schemas are generated with OpenAPI 3 settings, so named schemas are
+// referenced as "#/components/schemas/{name}".
+
+pub struct SchemaRegistry {
+    generator: SchemaGenerator,
+    schemas: HashMap<TypeId, Schema>,
+}
+
+impl Default for SchemaRegistry {
+    fn default() -> Self {
+        Self {
+            generator: SchemaGenerator::new(SchemaSettings::openapi3()),
+            schemas: HashMap::new(),
+        }
+    }
+}
+
+impl SchemaRegistry {
+    pub fn register<T: JsonSchema + ?Sized + 'static>(&mut self) {
+        let mut schema = self.generator.subschema_for::<T>();
+        for transform in self.generator.transforms_mut() {
+            transform.transform(&mut schema);
+        }
+        self.schemas.insert(TypeId::of::<T>(), schema);
+    }
+
+    /// Schema of the type (usually a reference to a named schema, see
+    /// `definition`).
+    pub(crate) fn get(&self, type_ref: TypeRef) -> Option<&Schema> {
+        self.schemas.get(&type_ref.id)
+    }
+
+    /// Follows the schema's reference to a named schema, if any.
+    pub(crate) fn resolve<'a>(&'a self, schema: &'a Schema) -> Option<&'a Schema> {
+        match schema.get("$ref").and_then(|r| r.as_str()) {
+            Some(reference) => reference
+                .rsplit('/')
+                .next()
+                .and_then(|name| self.definition(name)),
+            None => Some(schema),
+        }
+    }
+
+    /// Named schema, referenced by other schemas.
+    pub(crate) fn definition(&self, name: &str) -> Option<&Schema> {
+        self.generator
+            .definitions()
+            .get(name)
+            .and_then(|value| value.try_into().ok())
+    }
+
+    /// All named schemas, sorted by name.
+    pub(crate) fn definitions(&mut self) -> Vec<(String, Schema)> {
+        let mut definitions: Vec<(String, Schema)> = self
+            .generator
+            .take_definitions(true)
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value.try_into().ok()?)))
+            .collect();
+        definitions.sort_by(|(a, _), (b, _)| a.cmp(b));
+        definitions
+    }
+}
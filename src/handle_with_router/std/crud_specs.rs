@@ -11,12 +11,16 @@ use std::pin::Pin;
 
 use crate::{
     errors::{InvalidRequestError, UnauthorizedError},
-    handle_with_router::routing_config::{
-        is_allowed_access, is_allowed_owned_access, preliminary_access_check, CrudSpec,
+    handle_with_router::{
+        routes::{CrudRoute, RouteSpec},
+        routing_config::{
+            is_allowed_access, is_allowed_owned_access, preliminary_access_check, CrudInfo,
+            CrudSpec, RouteContext, TypeRef,
+        },
     },
     shared::{
-        request_processing::{parse_request_data, parse_request_metadata},
-        response_building::{build_err, build_result},
+        request_processing::{parse_request_data, PendingContext, RequestContext},
+        response_building::{build_err_for, build_err_with, build_result_with},
     },
     CrudAccess, OwnedCrudAccess, Validation,
 };
@@ -83,6 +87,7 @@ pub enum CrudOperation<T: DynamoObject> {
 
 type BoxedCrudHandler<T, O> = Box<
     dyn Fn(
+            PendingContext<'_>,
             CrudOperation<T>,
         ) -> Pin<Box<dyn std::future::Future<Output = Result<O, ServerError>> + Send>>
         + Send
@@ -117,9 +122,43 @@ where
         Box::new(Self {
             access,
             validation,
-            handler: Box::new(move |op| Box::pin(handler(op))),
+            handler: Box::new(move |_, op| Box::pin(handler(op))),
         })
     }
+
+    /// Like `new`, but the handler also receives the `RequestContext`.
+    pub fn with_context<H, Fut>(
+        access: CrudAccess,
+        validation: Validation<CrudOperation<T>>,
+        handler: H,
+    ) -> Box<dyn CrudSpec>
+    where
+        H: Fn(RequestContext, CrudOperation<T>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        Box::new(Self {
+            access,
+            validation,
+            handler: Box::new(move |ctx, op| Box::pin(handler(ctx.build(), op))),
+        })
+    }
+
+    /// Like `new`, for a declared route (see `CrudRoute`): the handler's item
+    /// and output types must be the route's, and the spec is returned with the
+    /// route's key (see `RoutingConfigBuilder::crud_route`).
+    pub fn for_route<R, H, Fut>(
+        _route: R,
+        access: CrudAccess,
+        validation: Validation<CrudOperation<T>>,
+        handler: H,
+    ) -> RouteSpec<dyn CrudSpec>
+    where
+        R: CrudRoute<Item = T, Output = O>,
+        H: Fn(CrudOperation<T>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        RouteSpec::new(R::KEY, Self::new(access, validation, handler))
+    }
 }
 
 #[async_trait]
@@ -131,44 +170,50 @@ where
     async fn resolve(
         &self,
         request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
     ) -> Result<ApiGatewayProxyResponse, Error> {
-        let metadata = match parse_request_metadata(request) {
+        let options = ctx.response_options(request);
+        let metadata = match ctx.request_metadata(request).await {
             Ok(m) => m,
-            Err(e) => return build_err(e),
+            Err(e) => return build_err_with(e, &options),
         };
         let method = &request.http_method;
         let op = match method {
             &Method::POST => {
                 if has_flag(request, "replace_all") {
                     if !is_allowed_access(&metadata, &self.access.replace_all) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     let parent_id = match get_optional_pksk(request, "parent_id") {
                         Ok(v) => v,
-                        Err(e) => return build_err(e),
+                        Err(e) => return build_err_with(e, &options),
                     };
                     let data = match parse_request_data::<Vec<T::Data>>(request) {
                         Ok(d) => d,
-                        Err(e) => return build_err(e),
+                        Err(e) => return build_err_with(e, &options),
                     };
                     CrudOperation::ReplaceAll { parent_id, data }
                 } else {
                     if !is_allowed_access(&metadata, &self.access.create) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     let parent_id = match get_optional_pksk(request, "parent_id") {
                         Ok(v) => v,
-                        Err(e) => return build_err(e),
+                        Err(e) => return build_err_with(e, &options),
                     };
                     let after = match get_optional_pksk(request, "after") {
                         Ok(v) => v,
-                        Err(e) => return build_err(e),
+                        Err(e) => return build_err_with(e, &options),
                     };
                     // Batch create if body is a list; fall back to single.
                     match parse_request_data::<Vec<T::Data>>(request) {
                         Ok(list) => {
                             if !self.access.allow_batching {
-                                return build_err(UnauthorizedError::new());
+                                return build_err_for(
+                                    UnauthorizedError::new(),
+                                    &metadata,
+                                    &options,
+                                );
                             }
                             CrudOperation::CreateMultiple {
                                 parent_id,
@@ -179,7 +224,7 @@ where
                         Err(_) => {
                             let data = match parse_request_data::<T::Data>(request) {
                                 Ok(d) => d,
-                                Err(e) => return build_err(e),
+                                Err(e) => return build_err_with(e, &options),
                             };
                             CrudOperation::Create {
                                 parent_id,
@@ -193,35 +238,35 @@ where
             &Method::GET => {
                 if has_flag(request, "all") {
                     if !is_allowed_access(&metadata, &self.access.list) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     let parent_id = match get_optional_pksk(request, "parent_id") {
                         Ok(v) => v,
-                        Err(e) => return build_err(e),
+                        Err(e) => return build_err_with(e, &options),
                     };
                     CrudOperation::List { parent_id }
                 } else {
                     if !is_allowed_access(&metadata, &self.access.read) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     if let Some(res) = maybe_ids(request) {
                         if !self.access.allow_batching {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         let ids = match res {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         CrudOperation::ReadMultiple {
                             item_refs: ItemRefs::Id(ids),
                         }
                     } else if let Some(res) = maybe_keys(request) {
                         if !self.access.allow_batching {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         let (parent_id, keys) = match res {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         CrudOperation::ReadMultiple {
                             item_refs: ItemRefs::Key { parent_id, keys },
@@ -229,7 +274,7 @@ where
                     } else if let Some(res) = maybe_id(request) {
                         let id = match res {
                             Ok(id) => id,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         CrudOperation::Read {
                             item_ref: ItemRef::Id(id),
@@ -237,7 +282,7 @@ where
                     } else if let Some(res) = maybe_key(request) {
                         let (parent_id, key) = match res {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         CrudOperation::Read {
                             item_ref: ItemRef::Key { parent_id, key },
@@ -245,7 +290,7 @@ where
                     } else {
                         let parent_id = match get_optional_pksk(request, "parent_id") {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         CrudOperation::Read {
                             item_ref: ItemRef::None { parent_id },
@@ -255,26 +300,26 @@ where
             }
             &Method::PUT => {
                 if !is_allowed_access(&metadata, &self.access.update) {
-                    return build_err(UnauthorizedError::new());
+                    return build_err_for(UnauthorizedError::new(), &metadata, &options);
                 }
                 let item = match parse_request_data::<T>(request) {
                     Ok(i) => i,
-                    Err(e) => return build_err(e),
+                    Err(e) => return build_err_with(e, &options),
                 };
                 CrudOperation::Update { item }
             }
             &Method::DELETE => {
                 let non_recursive = has_flag(request, "non_recursive");
                 if non_recursive && !self.access.allow_non_recursive_delete {
-                    return build_err(UnauthorizedError::new());
+                    return build_err_for(UnauthorizedError::new(), &metadata, &options);
                 }
                 if has_flag(request, "all") {
                     if !is_allowed_access(&metadata, &self.access.delete_all) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     let parent_id = match get_optional_pksk(request, "parent_id") {
                         Ok(v) => v,
-                        Err(e) => return build_err(e),
+                        Err(e) => return build_err_with(e, &options),
                     };
                     CrudOperation::DeleteAll {
                         parent_id,
@@ -282,15 +327,15 @@ where
                     }
                 } else {
                     if !is_allowed_access(&metadata, &self.access.delete) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     if let Some(res) = maybe_ids(request) {
                         if !self.access.allow_batching {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         let ids = match res {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         CrudOperation::DeleteMultiple {
                             item_refs: ItemRefs::Id(ids),
@@ -298,11 +343,11 @@ where
                         }
                     } else if let Some(res) = maybe_keys(request) {
                         if !self.access.allow_batching {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         let (parent_id, keys) = match res {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         CrudOperation::DeleteMultiple {
                             item_refs: ItemRefs::Key { parent_id, keys },
@@ -311,7 +356,7 @@ where
                     } else if let Some(res) = maybe_id(request) {
                         let id = match res {
                             Ok(id) => id,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         CrudOperation::Delete {
                             item_ref: ItemRef::Id(id),
@@ -320,7 +365,7 @@ where
                     } else if let Some(res) = maybe_key(request) {
                         let (parent_id, key) = match res {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         CrudOperation::Delete {
                             item_ref: ItemRef::Key { parent_id, key },
@@ -329,7 +374,7 @@ where
                     } else {
                         let parent_id = match get_optional_pksk(request, "parent_id") {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         CrudOperation::Delete {
                             item_ref: ItemRef::None { parent_id },
@@ -338,12 +383,31 @@ where
                     }
                 }
             }
-            _ => return build_err(CriticalError::new("unsupported HTTP method for CRUD route")),
+            _ => {
+                return build_err_with(
+                    CriticalError::new("unsupported HTTP method for CRUD route"),
+                    &options,
+                )
+            }
         };
         if let Err(e) = self.validation.validate(request, &op, &metadata) {
-            return build_err(e);
+            return build_err_for(e, &metadata, &options);
+        }
+        build_result_with(
+            (self.handler)(PendingContext::new(request, metadata, ctx), op).await,
+            &options,
+        )
+    }
+
+    fn info(&self) -> CrudInfo {
+        CrudInfo {
+            access: self.access.rules(),
+            allow_non_recursive_delete: self.access.allow_non_recursive_delete,
+            allow_batching: self.access.allow_batching,
+            item: Some(TypeRef::of::<T>()),
+            data: Some(TypeRef::of::<T::Data>()),
+            output: Some(TypeRef::of::<O>()),
         }
-        build_result((self.handler)(op).await)
     }
 }
 
@@ -383,9 +447,54 @@ where
             owner_of_parent_id: Box::new(owner_of_parent_id),
             access,
             validation,
-            handler: Box::new(move |op| Box::pin(handler(op))),
+            handler: Box::new(move |_, op| Box::pin(handler(op))),
+        })
+    }
+
+    /// Like `new`, but the handler also receives the `RequestContext`.
+    pub fn with_context<H, Fut, FOwnerId, FOwnerParentId>(
+        owner_of_id: FOwnerId,
+        owner_of_parent_id: FOwnerParentId,
+        access: OwnedCrudAccess,
+        validation: Validation<CrudOperation<T>>,
+        handler: H,
+    ) -> Box<dyn CrudSpec>
+    where
+        FOwnerId: Fn(&PkSk) -> Option<&str> + Send + Sync + 'static,
+        FOwnerParentId: Fn(&PkSk) -> Option<&str> + Send + Sync + 'static,
+        H: Fn(RequestContext, CrudOperation<T>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        Box::new(Self {
+            owner_of_id: Box::new(owner_of_id),
+            owner_of_parent_id: Box::new(owner_of_parent_id),
+            access,
+            validation,
+            handler: Box::new(move |ctx, op| Box::pin(handler(ctx.build(), op))),
         })
     }
+
+    /// Like `new`, for a declared route (see `Crud::for_route`).
+    pub fn for_route<R, H, Fut, FOwnerId, FOwnerParentId>(
+        _route: R,
+        owner_of_id: FOwnerId,
+        owner_of_parent_id: FOwnerParentId,
+        access: OwnedCrudAccess,
+        validation: Validation<CrudOperation<T>>,
+        handler: H,
+    ) -> RouteSpec<dyn CrudSpec>
+    where
+        R: CrudRoute<Item = T, Output = O>,
+        FOwnerId: Fn(&PkSk) -> Option<&str> + Send + Sync + 'static,
+        FOwnerParentId: Fn(&PkSk) -> Option<&str> + Send + Sync + 'static,
+        H: Fn(CrudOperation<T>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        RouteSpec::new(
+            R::KEY,
+            Self::new(owner_of_id, owner_of_parent_id, access, validation, handler),
+        )
+    }
 }
 
 #[async_trait]
@@ -397,51 +506,57 @@ where
     async fn resolve(
         &self,
         request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
     ) -> Result<ApiGatewayProxyResponse, Error> {
-        let metadata = match parse_request_metadata(request) {
+        let options = ctx.response_options(request);
+        let metadata = match ctx.request_metadata(request).await {
             Ok(m) => m,
-            Err(e) => return build_err(e),
+            Err(e) => return build_err_with(e, &options),
         };
         let method = &request.http_method;
         let op = match method {
             &Method::POST => {
                 if has_flag(request, "replace_all") {
                     if !preliminary_access_check(&metadata, &self.access.replace_all) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     let parent_id = match get_optional_pksk(request, "parent_id") {
                         Ok(v) => v,
-                        Err(e) => return build_err(e),
+                        Err(e) => return build_err_with(e, &options),
                     };
                     if !is_allowed_owned_access(
                         &metadata,
                         &self.access.replace_all,
                         (self.owner_of_parent_id)(parent_id.as_ref().unwrap_or(PkSk::root())),
                     ) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     let data = match parse_request_data::<Vec<T::Data>>(request) {
                         Ok(d) => d,
-                        Err(e) => return build_err(e),
+                        Err(e) => return build_err_with(e, &options),
                     };
                     CrudOperation::ReplaceAll { parent_id, data }
                 } else {
                     if !preliminary_access_check(&metadata, &self.access.create) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     let parent_id = match get_optional_pksk(request, "parent_id") {
                         Ok(v) => v,
-                        Err(e) => return build_err(e),
+                        Err(e) => return build_err_with(e, &options),
                     };
                     let after = match get_optional_pksk(request, "after") {
                         Ok(v) => v,
-                        Err(e) => return build_err(e),
+                        Err(e) => return build_err_with(e, &options),
                     };
                     // Batch create if body is a list; fall back to single.
                     match parse_request_data::<Vec<T::Data>>(request) {
                         Ok(list) => {
                             if !self.access.allow_batching {
-                                return build_err(UnauthorizedError::new());
+                                return build_err_for(
+                                    UnauthorizedError::new(),
+                                    &metadata,
+                                    &options,
+                                );
                             }
                             if !is_allowed_owned_access(
                                 &metadata,
@@ -450,7 +565,11 @@ where
                                     parent_id.as_ref().unwrap_or(PkSk::root()),
                                 ),
                             ) {
-                                return build_err(UnauthorizedError::new());
+                                return build_err_for(
+                                    UnauthorizedError::new(),
+                                    &metadata,
+                                    &options,
+                                );
                             }
                             CrudOperation::CreateMultiple {
                                 parent_id,
@@ -466,11 +585,15 @@ where
                                     parent_id.as_ref().unwrap_or(PkSk::root()),
                                 ),
                             ) {
-                                return build_err(UnauthorizedError::new());
+                                return build_err_for(
+                                    UnauthorizedError::new(),
+                                    &metadata,
+                                    &options,
+                                );
                             }
                             let data = match parse_request_data::<T::Data>(request) {
                                 Ok(d) => d,
-                                Err(e) => return build_err(e),
+                                Err(e) => return build_err_with(e, &options),
                             };
                             CrudOperation::Create {
                                 parent_id,
@@ -484,31 +607,31 @@ where
             &Method::GET => {
                 if has_flag(request, "all") {
                     if !preliminary_access_check(&metadata, &self.access.list) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     let parent_id = match get_optional_pksk(request, "parent_id") {
                         Ok(v) => v,
-                        Err(e) => return build_err(e),
+                        Err(e) => return build_err_with(e, &options),
                     };
                     if !is_allowed_owned_access(
                         &metadata,
                         &self.access.list,
                         (self.owner_of_parent_id)(parent_id.as_ref().unwrap_or(PkSk::root())),
                     ) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     CrudOperation::List { parent_id }
                 } else {
                     if !preliminary_access_check(&metadata, &self.access.read) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     if let Some(res) = maybe_ids(request) {
                         if !self.access.allow_batching {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         let ids = match res {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         let all_authorized = ids.iter().all(|id| {
                             is_allowed_owned_access(
@@ -518,25 +641,25 @@ where
                             )
                         });
                         if !all_authorized {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         CrudOperation::ReadMultiple {
                             item_refs: ItemRefs::Id(ids),
                         }
                     } else if let Some(res) = maybe_keys(request) {
                         if !self.access.allow_batching {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         let (parent_id, keys) = match res {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         if !is_allowed_owned_access(
                             &metadata,
                             &self.access.read,
                             (self.owner_of_parent_id)(parent_id.as_ref().unwrap_or(PkSk::root())),
                         ) {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         CrudOperation::ReadMultiple {
                             item_refs: ItemRefs::Key { parent_id, keys },
@@ -544,14 +667,14 @@ where
                     } else if let Some(res) = maybe_id(request) {
                         let id = match res {
                             Ok(id) => id,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         if !is_allowed_owned_access(
                             &metadata,
                             &self.access.read,
                             (self.owner_of_id)(&id),
                         ) {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         CrudOperation::Read {
                             item_ref: ItemRef::Id(id),
@@ -559,14 +682,14 @@ where
                     } else if let Some(res) = maybe_key(request) {
                         let (parent_id, key) = match res {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         if !is_allowed_owned_access(
                             &metadata,
                             &self.access.read,
                             (self.owner_of_parent_id)(parent_id.as_ref().unwrap_or(PkSk::root())),
                         ) {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         CrudOperation::Read {
                             item_ref: ItemRef::Key { parent_id, key },
@@ -574,14 +697,14 @@ where
                     } else {
                         let parent_id = match get_optional_pksk(request, "parent_id") {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         if !is_allowed_owned_access(
                             &metadata,
                             &self.access.read,
                             (self.owner_of_parent_id)(parent_id.as_ref().unwrap_or(PkSk::root())),
                         ) {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         CrudOperation::Read {
                             item_ref: ItemRef::None { parent_id },
@@ -591,40 +714,40 @@ where
             }
             &Method::PUT => {
                 if !preliminary_access_check(&metadata, &self.access.update) {
-                    return build_err(UnauthorizedError::new());
+                    return build_err_for(UnauthorizedError::new(), &metadata, &options);
                 }
                 let item = match parse_request_data::<T>(request) {
                     Ok(i) => i,
-                    Err(e) => return build_err(e),
+                    Err(e) => return build_err_with(e, &options),
                 };
                 if !is_allowed_owned_access(
                     &metadata,
                     &self.access.update,
                     (self.owner_of_id)(item.id()),
                 ) {
-                    return build_err(UnauthorizedError::new());
+                    return build_err_for(UnauthorizedError::new(), &metadata, &options);
                 }
                 CrudOperation::Update { item }
             }
             &Method::DELETE => {
                 let non_recursive = has_flag(request, "non_recursive");
                 if non_recursive && !self.access.allow_non_recursive_delete {
-                    return build_err(UnauthorizedError::new());
+                    return build_err_for(UnauthorizedError::new(), &metadata, &options);
                 }
                 if has_flag(request, "all") {
                     if !preliminary_access_check(&metadata, &self.access.delete_all) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     let parent_id = match get_optional_pksk(request, "parent_id") {
                         Ok(v) => v,
-                        Err(e) => return build_err(e),
+                        Err(e) => return build_err_with(e, &options),
                     };
                     if !is_allowed_owned_access(
                         &metadata,
                         &self.access.delete_all,
                         (self.owner_of_parent_id)(parent_id.as_ref().unwrap_or(PkSk::root())),
                     ) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     CrudOperation::DeleteAll {
                         parent_id,
@@ -632,15 +755,15 @@ where
                     }
                 } else {
                     if !preliminary_access_check(&metadata, &self.access.delete) {
-                        return build_err(UnauthorizedError::new());
+                        return build_err_for(UnauthorizedError::new(), &metadata, &options);
                     }
                     if let Some(res) = maybe_ids(request) {
                         if !self.access.allow_batching {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         let ids = match res {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         let all_authorized = ids.iter().all(|id| {
                             is_allowed_owned_access(
@@ -650,7 +773,7 @@ where
                             )
                         });
                         if !all_authorized {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         CrudOperation::DeleteMultiple {
                             item_refs: ItemRefs::Id(ids),
@@ -658,18 +781,18 @@ where
                         }
                     } else if let Some(res) = maybe_keys(request) {
                         if !self.access.allow_batching {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         let (parent_id, keys) = match res {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         if !is_allowed_owned_access(
                             &metadata,
                             &self.access.delete,
                             (self.owner_of_parent_id)(parent_id.as_ref().unwrap_or(PkSk::root())),
                         ) {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         CrudOperation::DeleteMultiple {
                             item_refs: ItemRefs::Key { parent_id, keys },
@@ -678,14 +801,14 @@ where
                     } else if let Some(res) = maybe_id(request) {
                         let id = match res {
                             Ok(id) => id,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         if !is_allowed_owned_access(
                             &metadata,
                             &self.access.delete,
                             (self.owner_of_id)(&id),
                         ) {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         CrudOperation::Delete {
                             item_ref: ItemRef::Id(id),
@@ -694,14 +817,14 @@ where
                     } else if let Some(res) = maybe_key(request) {
                         let (parent_id, key) = match res {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         if !is_allowed_owned_access(
                             &metadata,
                             &self.access.delete,
                             (self.owner_of_parent_id)(parent_id.as_ref().unwrap_or(PkSk::root())),
                         ) {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         CrudOperation::Delete {
                             item_ref: ItemRef::Key { parent_id, key },
@@ -710,14 +833,14 @@ where
                     } else {
                         let parent_id = match get_optional_pksk(request, "parent_id") {
                             Ok(v) => v,
-                            Err(e) => return build_err(e),
+                            Err(e) => return build_err_with(e, &options),
                         };
                         if !is_allowed_owned_access(
                             &metadata,
                             &self.access.delete,
                             (self.owner_of_parent_id)(parent_id.as_ref().unwrap_or(PkSk::root())),
                         ) {
-                            return build_err(UnauthorizedError::new());
+                            return build_err_for(UnauthorizedError::new(), &metadata, &options);
                         }
                         CrudOperation::Delete {
                             item_ref: ItemRef::None { parent_id },
@@ -726,12 +849,31 @@ where
                     }
                 }
             }
-            _ => return build_err(CriticalError::new("unsupported HTTP method for CRUD route")),
+            _ => {
+                return build_err_with(
+                    CriticalError::new("unsupported HTTP method for CRUD route"),
+                    &options,
+                )
+            }
         };
         if let Err(e) = self.validation.validate(request, &op, &metadata) {
-            return build_err(e);
+            return build_err_for(e, &metadata, &options);
+        }
+        build_result_with(
+            (self.handler)(PendingContext::new(request, metadata, ctx), op).await,
+            &options,
+        )
+    }
+
+    fn info(&self) -> CrudInfo {
+        CrudInfo {
+            access: self.access.rules(),
+            allow_non_recursive_delete: self.access.allow_non_recursive_delete,
+            allow_batching: self.access.allow_batching,
+            item: Some(TypeRef::of::<T>()),
+            data: Some(TypeRef::of::<T::Data>()),
+            output: Some(TypeRef::of::<O>()),
         }
-        build_result((self.handler)(op).await)
     }
 }
 
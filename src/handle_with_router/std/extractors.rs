@@ -0,0 +1,205 @@
+use std::{any::Any, ops::Deref, sync::Arc};
+
+use fractic_server_error::{CriticalError, ServerError};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    errors::InvalidRequestError,
+    handle_with_router::handler::FromRequest,
+    shared::request_processing::{
+        parse_path_params, parse_query_params, parse_request_data, RequestContext, RequestMetadata,
+    },
+};
+
+/// JSON request body.
+#[derive(Debug, Clone)]
+pub struct Json<T>(pub T);
+
+impl<T> FromRequest for Json<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    fn from_request(ctx: &RequestContext) -> Result<Self, ServerError> {
+        parse_request_data(&ctx.request).map(Json)
+    }
+}
+
+/// Query string parameters.
+#[derive(Debug, Clone)]
+pub struct Query<Q>(pub Q);
+
+impl<Q> FromRequest for Query<Q>
+where
+    Q: DeserializeOwned + Send + 'static,
+{
+    fn from_request(ctx: &RequestContext) -> Result<Self, ServerError> {
+        parse_query_params(&ctx.request).map(Query)
+    }
+}
+
+/// Path parameters, including those captured by templated route keys.
+#[derive(Debug, Clone)]
+pub struct Path<P>(pub P);
+
+impl<P> FromRequest for Path<P>
+where
+    P: DeserializeOwned + Send + 'static,
+{
+    fn from_request(ctx: &RequestContext) -> Result<Self, ServerError> {
+        parse_path_params(&ctx.request).map(Path)
+    }
+}
+
+/// Authentication metadata of the caller.
+#[derive(Debug, Clone)]
+pub struct Metadata(pub RequestMetadata);
+
+impl FromRequest for Metadata {
+    fn from_request(ctx: &RequestContext) -> Result<Self, ServerError> {
+        Ok(Metadata(ctx.metadata.clone()))
+    }
+}
+
+impl FromRequest for RequestContext {
+    fn from_request(ctx: &RequestContext) -> Result<Self, ServerError> {
+        Ok(ctx.clone())
+    }
+}
+
+/// Application state configured on the `RoutingConfig` (or passed to
+/// `aws_lambda_handle_with_router!`). Fails if no state of type `S` is
+/// configured.
+pub struct State<S>(pub Arc<S>);
+
+impl<S> Deref for State<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.0
+    }
+}
+
+impl<S> FromRequest for State<S>
+where
+    S: Any + Send + Sync,
+{
+    fn from_request(ctx: &RequestContext) -> Result<Self, ServerError> {
+        ctx.state::<S>().map(State).ok_or_else(|| {
+            CriticalError::new("application state is missing or of an unexpected type")
+        })
+    }
+}
+
+/// Header types that can be extracted through `Header<H>`.
+pub trait TypedHeader: Sized + Send + 'static {
+    const NAME: &'static str;
+
+    fn decode(value: &str) -> Result<Self, ServerError>;
+}
+
+/// Typed request header. Fails if the header is missing; use
+/// `Option<Header<H>>` for optional headers.
+#[derive(Debug, Clone)]
+pub struct Header<H>(pub H);
+
+impl<H: TypedHeader> FromRequest for Header<H> {
+    fn from_request(ctx: &RequestContext) -> Result<Self, ServerError> {
+        match ctx.header(H::NAME) {
+            Some(value) => H::decode(value).map(Header),
+            None => Err(InvalidRequestError::new(&format!(
+                "missing header '{}'",
+                H::NAME
+            ))),
+        }
+    }
+}
+
+impl<H: TypedHeader> FromRequest for Option<Header<H>> {
+    fn from_request(ctx: &RequestContext) -> Result<Self, ServerError> {
+        match ctx.header(H::NAME) {
+            Some(value) => H::decode(value).map(|h| Some(Header(h))),
+            None => Ok(None),
+        }
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle_with_router::routing_config::RouteContext;
+    use aws_lambda_events::{apigw::ApiGatewayProxyRequest, http::HeaderValue};
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct TestData {
+        key: String,
+    }
+
+    struct ClientVersion(u32);
+
+    impl TypedHeader for ClientVersion {
+        const NAME: &'static str = "x-client-version";
+
+        fn decode(value: &str) -> Result<Self, ServerError> {
+            value
+                .parse()
+                .map(ClientVersion)
+                .map_err(|e| InvalidRequestError::with_debug("invalid client version", &e))
+        }
+    }
+
+    struct TestState {
+        name: &'static str,
+    }
+
+    fn context(request: ApiGatewayProxyRequest) -> RequestContext {
+        RequestContext::new(
+            &request,
+            RequestMetadata::from_identity(None, &Default::default()),
+            &RouteContext {
+                state: Some(Arc::new(TestState { name: "test" })),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_json_extractor() {
+        let mut request = ApiGatewayProxyRequest::default();
+        request.body = Some("{\"key\":\"value\"}".to_string());
+        let Json(data) = Json::<TestData>::from_request(&context(request)).unwrap();
+        assert_eq!(
+            data,
+            TestData {
+                key: "value".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_header_extractor() {
+        let mut request = ApiGatewayProxyRequest::default();
+        let ctx = context(request.clone());
+        assert!(Header::<ClientVersion>::from_request(&ctx).is_err());
+        assert!(Option::<Header<ClientVersion>>::from_request(&ctx)
+            .unwrap()
+            .is_none());
+
+        request
+            .headers
+            .insert("x-client-version", HeaderValue::from_static("42"));
+        let Header(version) = Header::<ClientVersion>::from_request(&context(request)).unwrap();
+        assert_eq!(version.0, 42);
+    }
+
+    #[test]
+    fn test_state_extractor() {
+        let ctx = context(ApiGatewayProxyRequest::default());
+        let state = State::<TestState>::from_request(&ctx).unwrap();
+        assert_eq!(state.name, "test");
+        assert!(State::<String>::from_request(&ctx).is_err());
+    }
+}
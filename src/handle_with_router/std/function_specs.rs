@@ -7,25 +7,55 @@ use std::pin::Pin;
 
 use crate::{
     errors::UnauthorizedError,
-    handle_with_router::routing_config::{
-        is_allowed_access, is_allowed_owned_access, preliminary_access_check, Access, FunctionSpec,
-        OwnedAccess,
+    handle_with_router::{
+        handler::{Handler, HandlerFuture, JsonHandler},
+        routes::{FunctionRoute, RouteSpec},
+        routing_config::{
+            is_allowed_access, is_allowed_owned_access, preliminary_access_check, Access,
+            AccessRule, FunctionInfo, FunctionSpec, OwnedAccess, RouteContext, TypeRef,
+        },
     },
     shared::{
-        request_processing::{parse_request_data, parse_request_metadata},
-        response_building::{build_err, build_result},
+        request_processing::{
+            parse_path_params, parse_request_input, PendingContext, RequestContext,
+        },
+        response_building::{build_err_for, build_err_with, build_result_with},
     },
     Validation,
 };
 
 type BoxedFuncHandler<I, O> = Box<
-    dyn Fn(I) -> Pin<Box<dyn std::future::Future<Output = Result<O, ServerError>> + Send>>
+    dyn Fn(
+            PendingContext<'_>,
+            I,
+        ) -> Pin<Box<dyn std::future::Future<Output = Result<O, ServerError>> + Send>>
         + Send
         + Sync,
 >;
 
 type BoxedVoidHandler<O> = Box<
-    dyn Fn() -> Pin<Box<dyn std::future::Future<Output = Result<O, ServerError>> + Send>>
+    dyn Fn(
+            PendingContext<'_>,
+        ) -> Pin<Box<dyn std::future::Future<Output = Result<O, ServerError>> + Send>>
+        + Send
+        + Sync,
+>;
+
+type BoxedPathFuncHandler<P, I, O> = Box<
+    dyn Fn(
+            PendingContext<'_>,
+            P,
+            I,
+        ) -> Pin<Box<dyn std::future::Future<Output = Result<O, ServerError>> + Send>>
+        + Send
+        + Sync,
+>;
+
+type BoxedPathVoidHandler<P, O> = Box<
+    dyn Fn(
+            PendingContext<'_>,
+            P,
+        ) -> Pin<Box<dyn std::future::Future<Output = Result<O, ServerError>> + Send>>
         + Send
         + Sync,
 >;
@@ -55,9 +85,43 @@ where
         Box::new(Self {
             access,
             validation,
-            handler: Box::new(move || Box::pin(handler())),
+            handler: Box::new(move |_| Box::pin(handler())),
+        })
+    }
+
+    /// Like `new`, but the handler also receives the `RequestContext`.
+    pub fn with_context<H, Fut>(
+        access: Access,
+        validation: Validation<()>,
+        handler: H,
+    ) -> Box<dyn FunctionSpec>
+    where
+        H: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        Box::new(Self {
+            access,
+            validation,
+            handler: Box::new(move |ctx| Box::pin(handler(ctx.build()))),
         })
     }
+
+    /// Like `new`, for a declared route (see `FunctionRoute`): the handler's
+    /// output must be the route's, and the spec is returned with the route's
+    /// key (see `RoutingConfigBuilder::function_route`).
+    pub fn for_route<R, H, Fut>(
+        _route: R,
+        access: Access,
+        validation: Validation<()>,
+        handler: H,
+    ) -> RouteSpec<dyn FunctionSpec>
+    where
+        R: FunctionRoute<Path = (), Input = (), Output = O>,
+        H: Fn() -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        RouteSpec::new(R::KEY, Self::new(access, validation, handler))
+    }
 }
 
 #[async_trait]
@@ -68,18 +132,31 @@ where
     async fn resolve(
         &self,
         request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
     ) -> Result<ApiGatewayProxyResponse, Error> {
-        let metadata = match parse_request_metadata(request) {
+        let options = ctx.response_options(request);
+        let metadata = match ctx.request_metadata(request).await {
             Ok(m) => m,
-            Err(e) => return build_err(e),
+            Err(e) => return build_err_with(e, &options),
         };
         if !is_allowed_access(&metadata, &self.access) {
-            return build_err(UnauthorizedError::new());
+            return build_err_for(UnauthorizedError::new(), &metadata, &options);
         }
         if let Err(e) = self.validation.validate(request, &(), &metadata) {
-            return build_err(e);
+            return build_err_for(e, &metadata, &options);
+        }
+        build_result_with(
+            (self.handler)(PendingContext::new(request, metadata, ctx)).await,
+            &options,
+        )
+    }
+
+    fn info(&self) -> FunctionInfo {
+        FunctionInfo {
+            access: Some(AccessRule::Access(self.access.clone())),
+            output: Some(TypeRef::of::<O>()),
+            ..Default::default()
         }
-        build_result((self.handler)().await)
     }
 }
 
@@ -110,9 +187,44 @@ where
         Box::new(Self {
             access,
             validation,
-            handler: Box::new(move |i| Box::pin(handler(i))),
+            handler: Box::new(move |_, i| Box::pin(handler(i))),
         })
     }
+
+    /// Like `new`, but the handler also receives the `RequestContext`.
+    pub fn with_context<H, Fut>(
+        access: Access,
+        validation: Validation<I>,
+        handler: H,
+    ) -> Box<dyn FunctionSpec>
+    where
+        H: Fn(RequestContext, I) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        Box::new(Self {
+            access,
+            validation,
+            handler: Box::new(move |ctx, i| Box::pin(handler(ctx.build(), i))),
+        })
+    }
+
+    /// Like `new`, for a declared route (see `FunctionRoute`): the handler's
+    /// input and output must be the route's, and the spec is returned with the
+    /// route's key (see `RoutingConfigBuilder::function_route`). Routes with
+    /// path parameters use `PathFunction::for_route` instead.
+    pub fn for_route<R, H, Fut>(
+        _route: R,
+        access: Access,
+        validation: Validation<I>,
+        handler: H,
+    ) -> RouteSpec<dyn FunctionSpec>
+    where
+        R: FunctionRoute<Path = (), Input = I, Output = O>,
+        H: Fn(I) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        RouteSpec::new(R::KEY, Self::new(access, validation, handler))
+    }
 }
 
 #[async_trait]
@@ -124,22 +236,36 @@ where
     async fn resolve(
         &self,
         request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
     ) -> Result<ApiGatewayProxyResponse, Error> {
-        let metadata = match parse_request_metadata(request) {
+        let options = ctx.response_options(request);
+        let metadata = match ctx.request_metadata(request).await {
             Ok(m) => m,
-            Err(e) => return build_err(e),
+            Err(e) => return build_err_with(e, &options),
         };
         if !is_allowed_access(&metadata, &self.access) {
-            return build_err(UnauthorizedError::new());
+            return build_err_for(UnauthorizedError::new(), &metadata, &options);
         }
-        let input = match parse_request_data::<I>(request) {
+        let input = match parse_request_input::<I>(request) {
             Ok(i) => i,
-            Err(e) => return build_err(e),
+            Err(e) => return build_err_with(e, &options),
         };
         if let Err(e) = self.validation.validate(request, &input, &metadata) {
-            return build_err(e);
+            return build_err_for(e, &metadata, &options);
+        }
+        build_result_with(
+            (self.handler)(PendingContext::new(request, metadata, ctx), input).await,
+            &options,
+        )
+    }
+
+    fn info(&self) -> FunctionInfo {
+        FunctionInfo {
+            access: Some(AccessRule::Access(self.access.clone())),
+            input: Some(TypeRef::of::<I>()),
+            output: Some(TypeRef::of::<O>()),
+            ..Default::default()
         }
-        build_result((self.handler)(input).await)
     }
 }
 
@@ -174,9 +300,46 @@ where
             owner_of: Box::new(owner_of),
             access,
             validation,
-            handler: Box::new(move |i| Box::pin(handler(i))),
+            handler: Box::new(move |_, i| Box::pin(handler(i))),
         })
     }
+
+    /// Like `new`, but the handler also receives the `RequestContext`.
+    pub fn with_context<H, Fut, FOwner>(
+        owner_of: FOwner,
+        access: OwnedAccess,
+        validation: Validation<I>,
+        handler: H,
+    ) -> Box<dyn FunctionSpec>
+    where
+        FOwner: Fn(&I) -> &str + Send + Sync + 'static,
+        H: Fn(RequestContext, I) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        Box::new(Self {
+            owner_of: Box::new(owner_of),
+            access,
+            validation,
+            handler: Box::new(move |ctx, i| Box::pin(handler(ctx.build(), i))),
+        })
+    }
+
+    /// Like `new`, for a declared route (see `Function::for_route`).
+    pub fn for_route<R, H, Fut, FOwner>(
+        _route: R,
+        owner_of: FOwner,
+        access: OwnedAccess,
+        validation: Validation<I>,
+        handler: H,
+    ) -> RouteSpec<dyn FunctionSpec>
+    where
+        R: FunctionRoute<Path = (), Input = I, Output = O>,
+        FOwner: Fn(&I) -> &str + Send + Sync + 'static,
+        H: Fn(I) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        RouteSpec::new(R::KEY, Self::new(owner_of, access, validation, handler))
+    }
 }
 
 #[async_trait]
@@ -188,25 +351,623 @@ where
     async fn resolve(
         &self,
         request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
     ) -> Result<ApiGatewayProxyResponse, Error> {
-        let metadata = match parse_request_metadata(request) {
+        let options = ctx.response_options(request);
+        let metadata = match ctx.request_metadata(request).await {
             Ok(m) => m,
-            Err(e) => return build_err(e),
+            Err(e) => return build_err_with(e, &options),
         };
         if !preliminary_access_check(&metadata, &self.access) {
-            return build_err(UnauthorizedError::new());
+            return build_err_for(UnauthorizedError::new(), &metadata, &options);
         }
-        let input = match parse_request_data::<I>(request) {
+        let input = match parse_request_input::<I>(request) {
             Ok(i) => i,
-            Err(e) => return build_err(e),
+            Err(e) => return build_err_with(e, &options),
         };
         let owner = (self.owner_of)(&input);
         if !is_allowed_owned_access(&metadata, &self.access, Some(owner)) {
-            return build_err(UnauthorizedError::new());
+            return build_err_for(UnauthorizedError::new(), &metadata, &options);
+        }
+        if let Err(e) = self.validation.validate(request, &input, &metadata) {
+            return build_err_for(e, &metadata, &options);
+        }
+        build_result_with(
+            (self.handler)(PendingContext::new(request, metadata, ctx), input).await,
+            &options,
+        )
+    }
+
+    fn info(&self) -> FunctionInfo {
+        FunctionInfo {
+            access: Some(AccessRule::Owned(self.access.clone())),
+            input: Some(TypeRef::of::<I>()),
+            output: Some(TypeRef::of::<O>()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Function whose only input is the path parameters captured by a templated
+/// route key (e.g. "users/{user_id}").
+pub struct NullaryPathFunction<P, O>
+where
+    P: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    access: Access,
+    validation: Validation<P>,
+    handler: BoxedPathVoidHandler<P, O>,
+}
+
+impl<P, O> NullaryPathFunction<P, O>
+where
+    P: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    pub fn new<H, Fut>(
+        access: Access,
+        validation: Validation<P>,
+        handler: H,
+    ) -> Box<dyn FunctionSpec>
+    where
+        H: Fn(P) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        Box::new(Self {
+            access,
+            validation,
+            handler: Box::new(move |_, p| Box::pin(handler(p))),
+        })
+    }
+
+    /// Like `new`, but the handler also receives the `RequestContext`.
+    pub fn with_context<H, Fut>(
+        access: Access,
+        validation: Validation<P>,
+        handler: H,
+    ) -> Box<dyn FunctionSpec>
+    where
+        H: Fn(RequestContext, P) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        Box::new(Self {
+            access,
+            validation,
+            handler: Box::new(move |ctx, p| Box::pin(handler(ctx.build(), p))),
+        })
+    }
+
+    /// Like `new`, for a declared route without input (see
+    /// `NullaryFunction::for_route`), with the route's `Path` as path
+    /// parameters.
+    pub fn for_route<R, H, Fut>(
+        _route: R,
+        access: Access,
+        validation: Validation<P>,
+        handler: H,
+    ) -> RouteSpec<dyn FunctionSpec>
+    where
+        R: FunctionRoute<Path = P, Input = (), Output = O>,
+        H: Fn(P) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        RouteSpec::new(R::KEY, Self::new(access, validation, handler))
+    }
+}
+
+#[async_trait]
+impl<P, O> FunctionSpec for NullaryPathFunction<P, O>
+where
+    P: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    async fn resolve(
+        &self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let options = ctx.response_options(request);
+        let metadata = match ctx.request_metadata(request).await {
+            Ok(m) => m,
+            Err(e) => return build_err_with(e, &options),
+        };
+        if !is_allowed_access(&metadata, &self.access) {
+            return build_err_for(UnauthorizedError::new(), &metadata, &options);
+        }
+        let path = match parse_path_params::<P>(request) {
+            Ok(p) => p,
+            Err(e) => return build_err_with(e, &options),
+        };
+        if let Err(e) = self.validation.validate(request, &path, &metadata) {
+            return build_err_for(e, &metadata, &options);
+        }
+        build_result_with(
+            (self.handler)(PendingContext::new(request, metadata, ctx), path).await,
+            &options,
+        )
+    }
+
+    fn info(&self) -> FunctionInfo {
+        FunctionInfo {
+            access: Some(AccessRule::Access(self.access.clone())),
+            path_params: Some(TypeRef::of::<P>()),
+            output: Some(TypeRef::of::<O>()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Function receiving both the path parameters captured by a templated route
+/// key and the request body.
+pub struct PathFunction<P, I, O>
+where
+    P: DeserializeOwned + Send + 'static,
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    access: Access,
+    validation: Validation<I>,
+    handler: BoxedPathFuncHandler<P, I, O>,
+}
+
+impl<P, I, O> PathFunction<P, I, O>
+where
+    P: DeserializeOwned + Send + 'static,
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    pub fn new<H, Fut>(
+        access: Access,
+        validation: Validation<I>,
+        handler: H,
+    ) -> Box<dyn FunctionSpec>
+    where
+        H: Fn(P, I) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        Box::new(Self {
+            access,
+            validation,
+            handler: Box::new(move |_, p, i| Box::pin(handler(p, i))),
+        })
+    }
+
+    /// Like `new`, but the handler also receives the `RequestContext`.
+    pub fn with_context<H, Fut>(
+        access: Access,
+        validation: Validation<I>,
+        handler: H,
+    ) -> Box<dyn FunctionSpec>
+    where
+        H: Fn(RequestContext, P, I) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        Box::new(Self {
+            access,
+            validation,
+            handler: Box::new(move |ctx, p, i| Box::pin(handler(ctx.build(), p, i))),
+        })
+    }
+
+    /// Like `new`, for a declared route (see `Function::for_route`), with the
+    /// route's `Path` as path parameters.
+    pub fn for_route<R, H, Fut>(
+        _route: R,
+        access: Access,
+        validation: Validation<I>,
+        handler: H,
+    ) -> RouteSpec<dyn FunctionSpec>
+    where
+        R: FunctionRoute<Path = P, Input = I, Output = O>,
+        H: Fn(P, I) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        RouteSpec::new(R::KEY, Self::new(access, validation, handler))
+    }
+}
+
+#[async_trait]
+impl<P, I, O> FunctionSpec for PathFunction<P, I, O>
+where
+    P: DeserializeOwned + Send + 'static,
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    async fn resolve(
+        &self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let options = ctx.response_options(request);
+        let metadata = match ctx.request_metadata(request).await {
+            Ok(m) => m,
+            Err(e) => return build_err_with(e, &options),
+        };
+        if !is_allowed_access(&metadata, &self.access) {
+            return build_err_for(UnauthorizedError::new(), &metadata, &options);
+        }
+        let path = match parse_path_params::<P>(request) {
+            Ok(p) => p,
+            Err(e) => return build_err_with(e, &options),
+        };
+        let input = match parse_request_input::<I>(request) {
+            Ok(i) => i,
+            Err(e) => return build_err_with(e, &options),
+        };
+        if let Err(e) = self.validation.validate(request, &input, &metadata) {
+            return build_err_for(e, &metadata, &options);
+        }
+        build_result_with(
+            (self.handler)(PendingContext::new(request, metadata, ctx), path, input).await,
+            &options,
+        )
+    }
+
+    fn info(&self) -> FunctionInfo {
+        FunctionInfo {
+            access: Some(AccessRule::Access(self.access.clone())),
+            path_params: Some(TypeRef::of::<P>()),
+            input: Some(TypeRef::of::<I>()),
+            output: Some(TypeRef::of::<O>()),
+        }
+    }
+}
+
+/// Owned variant of `PathFunction`. The owner can be extracted from either the
+/// path parameters or the request body.
+pub struct OwnedPathFunction<P, I, O>
+where
+    P: DeserializeOwned + Send + 'static,
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    owner_of: Box<dyn for<'a> Fn(&'a P, &'a I) -> &'a str + Send + Sync>,
+    access: OwnedAccess,
+    validation: Validation<I>,
+    handler: BoxedPathFuncHandler<P, I, O>,
+}
+
+impl<P, I, O> OwnedPathFunction<P, I, O>
+where
+    P: DeserializeOwned + Send + 'static,
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    pub fn new<H, Fut, FOwner>(
+        owner_of: FOwner,
+        access: OwnedAccess,
+        validation: Validation<I>,
+        handler: H,
+    ) -> Box<dyn FunctionSpec>
+    where
+        FOwner: for<'a> Fn(&'a P, &'a I) -> &'a str + Send + Sync + 'static,
+        H: Fn(P, I) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        Box::new(Self {
+            owner_of: Box::new(owner_of),
+            access,
+            validation,
+            handler: Box::new(move |_, p, i| Box::pin(handler(p, i))),
+        })
+    }
+
+    /// Like `new`, but the handler also receives the `RequestContext`.
+    pub fn with_context<H, Fut, FOwner>(
+        owner_of: FOwner,
+        access: OwnedAccess,
+        validation: Validation<I>,
+        handler: H,
+    ) -> Box<dyn FunctionSpec>
+    where
+        FOwner: for<'a> Fn(&'a P, &'a I) -> &'a str + Send + Sync + 'static,
+        H: Fn(RequestContext, P, I) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        Box::new(Self {
+            owner_of: Box::new(owner_of),
+            access,
+            validation,
+            handler: Box::new(move |ctx, p, i| Box::pin(handler(ctx.build(), p, i))),
+        })
+    }
+
+    /// Like `new`, for a declared route (see `Function::for_route`), with the
+    /// route's `Path` as path parameters.
+    pub fn for_route<R, H, Fut, FOwner>(
+        _route: R,
+        owner_of: FOwner,
+        access: OwnedAccess,
+        validation: Validation<I>,
+        handler: H,
+    ) -> RouteSpec<dyn FunctionSpec>
+    where
+        R: FunctionRoute<Path = P, Input = I, Output = O>,
+        FOwner: for<'a> Fn(&'a P, &'a I) -> &'a str + Send + Sync + 'static,
+        H: Fn(P, I) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        RouteSpec::new(R::KEY, Self::new(owner_of, access, validation, handler))
+    }
+}
+
+#[async_trait]
+impl<P, I, O> FunctionSpec for OwnedPathFunction<P, I, O>
+where
+    P: DeserializeOwned + Send + 'static,
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    async fn resolve(
+        &self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let options = ctx.response_options(request);
+        let metadata = match ctx.request_metadata(request).await {
+            Ok(m) => m,
+            Err(e) => return build_err_with(e, &options),
+        };
+        if !preliminary_access_check(&metadata, &self.access) {
+            return build_err_for(UnauthorizedError::new(), &metadata, &options);
+        }
+        let path = match parse_path_params::<P>(request) {
+            Ok(p) => p,
+            Err(e) => return build_err_with(e, &options),
+        };
+        let input = match parse_request_input::<I>(request) {
+            Ok(i) => i,
+            Err(e) => return build_err_with(e, &options),
+        };
+        let owner = (self.owner_of)(&path, &input);
+        if !is_allowed_owned_access(&metadata, &self.access, Some(owner)) {
+            return build_err_for(UnauthorizedError::new(), &metadata, &options);
         }
         if let Err(e) = self.validation.validate(request, &input, &metadata) {
-            return build_err(e);
+            return build_err_for(e, &metadata, &options);
+        }
+        build_result_with(
+            (self.handler)(PendingContext::new(request, metadata, ctx), path, input).await,
+            &options,
+        )
+    }
+
+    fn info(&self) -> FunctionInfo {
+        FunctionInfo {
+            access: Some(AccessRule::Owned(self.access.clone())),
+            path_params: Some(TypeRef::of::<P>()),
+            input: Some(TypeRef::of::<I>()),
+            output: Some(TypeRef::of::<O>()),
+        }
+    }
+}
+
+type BoxedExtractorHandler<I, O> =
+    Box<dyn Fn(&RequestContext, I) -> HandlerFuture<O> + Send + Sync>;
+
+type InputParser<I> = fn(&ApiGatewayProxyRequest) -> Result<I, ServerError>;
+
+/// Function whose handler declares its inputs as `FromRequest` arguments
+/// (e.g. `Json<T>`, `Query<Q>`, `Path<P>`, `Metadata`, `Header<H>`), rather
+/// than receiving a fixed input shape.
+///
+/// Handlers registered through `with_json` take the request input as their
+/// first argument (`Json<I>`), parsed as for `Function` and checked by the
+/// validation before the handler is called.
+pub struct ExtractorFunction<I, O>
+where
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    access: Access,
+    validation: Validation<I>,
+    parse_input: InputParser<I>,
+    input: Option<TypeRef>,
+    handler: BoxedExtractorHandler<I, O>,
+}
+
+impl<O> ExtractorFunction<(), O>
+where
+    O: serde::Serialize + Send + 'static,
+{
+    pub fn new<H, Args>(
+        access: Access,
+        validation: Validation<()>,
+        handler: H,
+    ) -> Box<dyn FunctionSpec>
+    where
+        H: Handler<Args, O>,
+        Args: 'static,
+    {
+        Box::new(Self {
+            access,
+            validation,
+            parse_input: |_| Ok(()),
+            input: None,
+            handler: Box::new(move |ctx, ()| handler.call(ctx)),
+        })
+    }
+}
+
+impl<I, O> ExtractorFunction<I, O>
+where
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    pub fn with_json<H, Args>(
+        access: Access,
+        validation: Validation<I>,
+        handler: H,
+    ) -> Box<dyn FunctionSpec>
+    where
+        H: JsonHandler<I, Args, O>,
+        Args: 'static,
+    {
+        Box::new(Self {
+            access,
+            validation,
+            parse_input: parse_request_input::<I>,
+            input: Some(TypeRef::of::<I>()),
+            handler: Box::new(move |ctx, i| handler.call(i, ctx)),
+        })
+    }
+}
+
+#[async_trait]
+impl<I, O> FunctionSpec for ExtractorFunction<I, O>
+where
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    async fn resolve(
+        &self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let options = ctx.response_options(request);
+        let metadata = match ctx.request_metadata(request).await {
+            Ok(m) => m,
+            Err(e) => return build_err_with(e, &options),
+        };
+        if !is_allowed_access(&metadata, &self.access) {
+            return build_err_for(UnauthorizedError::new(), &metadata, &options);
+        }
+        let input = match (self.parse_input)(request) {
+            Ok(i) => i,
+            Err(e) => return build_err_with(e, &options),
+        };
+        if let Err(e) = self.validation.validate(request, &input, &metadata) {
+            return build_err_for(e, &metadata, &options);
+        }
+        let request_ctx = RequestContext::new(request, metadata, ctx);
+        build_result_with((self.handler)(&request_ctx, input).await, &options)
+    }
+
+    fn info(&self) -> FunctionInfo {
+        FunctionInfo {
+            access: Some(AccessRule::Access(self.access.clone())),
+            input: self.input,
+            output: Some(TypeRef::of::<O>()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Like `ExtractorFunction`, with access depending on the owner of the
+/// requested resource (see `OwnedAccess`).
+pub struct OwnedExtractorFunction<I, O>
+where
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    owner_of: Box<dyn for<'a> Fn(&'a RequestContext, &'a I) -> Option<&'a str> + Send + Sync>,
+    access: OwnedAccess,
+    validation: Validation<I>,
+    parse_input: InputParser<I>,
+    input: Option<TypeRef>,
+    handler: BoxedExtractorHandler<I, O>,
+}
+
+impl<O> OwnedExtractorFunction<(), O>
+where
+    O: serde::Serialize + Send + 'static,
+{
+    /// `owner_of` reads the owner from the request (e.g.
+    /// `|ctx| ctx.path_param("user_id")`). Requests without one are only
+    /// allowed if the access does not depend on the owner.
+    pub fn new<H, Args, FOwner>(
+        owner_of: FOwner,
+        access: OwnedAccess,
+        validation: Validation<()>,
+        handler: H,
+    ) -> Box<dyn FunctionSpec>
+    where
+        FOwner: for<'a> Fn(&'a RequestContext) -> Option<&'a str> + Send + Sync + 'static,
+        H: Handler<Args, O>,
+        Args: 'static,
+    {
+        Box::new(Self {
+            owner_of: Box::new(move |ctx, _| owner_of(ctx)),
+            access,
+            validation,
+            parse_input: |_| Ok(()),
+            input: None,
+            handler: Box::new(move |ctx, ()| handler.call(ctx)),
+        })
+    }
+}
+
+impl<I, O> OwnedExtractorFunction<I, O>
+where
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    /// Like `ExtractorFunction::with_json`, with the owner read from the
+    /// input.
+    pub fn with_json<H, Args, FOwner>(
+        owner_of: FOwner,
+        access: OwnedAccess,
+        validation: Validation<I>,
+        handler: H,
+    ) -> Box<dyn FunctionSpec>
+    where
+        FOwner: Fn(&I) -> &str + Send + Sync + 'static,
+        H: JsonHandler<I, Args, O>,
+        Args: 'static,
+    {
+        Box::new(Self {
+            owner_of: Box::new(move |_, i| Some(owner_of(i))),
+            access,
+            validation,
+            parse_input: parse_request_input::<I>,
+            input: Some(TypeRef::of::<I>()),
+            handler: Box::new(move |ctx, i| handler.call(i, ctx)),
+        })
+    }
+}
+
+#[async_trait]
+impl<I, O> FunctionSpec for OwnedExtractorFunction<I, O>
+where
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    async fn resolve(
+        &self,
+        request: &ApiGatewayProxyRequest,
+        ctx: &RouteContext,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let options = ctx.response_options(request);
+        let metadata = match ctx.request_metadata(request).await {
+            Ok(m) => m,
+            Err(e) => return build_err_with(e, &options),
+        };
+        if !preliminary_access_check(&metadata, &self.access) {
+            return build_err_for(UnauthorizedError::new(), &metadata, &options);
+        }
+        let input = match (self.parse_input)(request) {
+            Ok(i) => i,
+            Err(e) => return build_err_with(e, &options),
+        };
+        let request_ctx = RequestContext::new(request, metadata, ctx);
+        let metadata = &request_ctx.metadata;
+        let owner = (self.owner_of)(&request_ctx, &input);
+        if !is_allowed_owned_access(metadata, &self.access, owner) {
+            return build_err_for(UnauthorizedError::new(), metadata, &options);
+        }
+        if let Err(e) = self.validation.validate(request, &input, metadata) {
+            return build_err_for(e, metadata, &options);
+        }
+        build_result_with((self.handler)(&request_ctx, input).await, &options)
+    }
+
+    fn info(&self) -> FunctionInfo {
+        FunctionInfo {
+            access: Some(AccessRule::Owned(self.access.clone())),
+            input: self.input,
+            output: Some(TypeRef::of::<O>()),
+            ..Default::default()
         }
-        build_result((self.handler)(input).await)
     }
 }
@@ -39,3 +39,30 @@ impl<I: 'static> ValidatorSpec<I> for AdminOnly<I> {
         }
     }
 }
+
+/// Requires the access token to carry all of the given OAuth2 scopes (see
+/// `RequestMetadata::scopes`).
+pub struct RequireScopes {
+    scopes: &'static [&'static str],
+}
+
+impl RequireScopes {
+    pub fn all<I: 'static>(scopes: &'static [&'static str]) -> Box<dyn ValidatorSpec<I> + 'static> {
+        Box::new(Self { scopes })
+    }
+}
+
+impl<I: 'static> ValidatorSpec<I> for RequireScopes {
+    fn validate(
+        &self,
+        _request: &ApiGatewayProxyRequest,
+        _data: &I,
+        metadata: &RequestMetadata,
+    ) -> Result<(), ServerError> {
+        if metadata.has_token() && metadata.has_all_scopes(self.scopes) {
+            Ok(())
+        } else {
+            Err(UnauthorizedError::new())
+        }
+    }
+}
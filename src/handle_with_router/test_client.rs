@@ -0,0 +1,675 @@
+use std::collections::HashMap;
+
+use aws_lambda_events::{
+    apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse},
+    encodings::Body,
+    http::{header::CONTENT_TYPE, HeaderName, HeaderValue, Method},
+};
+use lambda_runtime::{Context, LambdaEvent};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    handle_with_router::routing_config::RoutingConfig,
+    shared::{
+        fake_claims::FakeClaims,
+        response_decoding::{decode_response, ClientError, ClientVisibleError},
+    },
+};
+
+// In-process test client.
+// --------------------------------------------------
+//
+// Sends requests through a `RoutingConfig` the way API Gateway would (with a
+// '{proxy+}' resource and Cognito authorizer claims), and decodes the response
+// envelope, so that route tests don't need to assemble events or decode
+// payloads by hand.
+//
+// Since the client is in-process, it also works for unit tests of crates
+// using this library, e.g.:
+//
+//   let client = TestClient::new(build_config());
+//   let user: User = client
+//       .post("users")
+//       .as_admin()
+//       .json(&NewUser { name: "John".into() })
+//       .send_json()
+//       .await?;
+
+/// Sub used by `TestRequest::as_admin` if no user was set.
+pub const TEST_ADMIN_SUB: &str = "test-admin";
+/// Sub used by `TestRequest::in_groups` if no user was set.
+pub const TEST_USER_SUB: &str = "test-user";
+
+pub struct TestClient {
+    pub config: RoutingConfig,
+}
+
+impl TestClient {
+    pub fn new(config: RoutingConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn get(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::GET, path)
+    }
+
+    pub fn post(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::POST, path)
+    }
+
+    pub fn put(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::PUT, path)
+    }
+
+    pub fn patch(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::PATCH, path)
+    }
+
+    pub fn delete(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::DELETE, path)
+    }
+
+    /// The path is the route's path, with or without a leading slash (e.g.
+    /// "users/123").
+    pub fn request(&self, method: Method, path: &str) -> TestRequest<'_> {
+        let mut request = ApiGatewayProxyRequest::default();
+        request.http_method = method;
+        let path = path.trim_start_matches('/');
+        request
+            .path_parameters
+            .insert("proxy".to_string(), path.to_string());
+        request.path = Some(format!("/{path}"));
+        request.resource = Some("/{proxy+}".to_string());
+        request.request_context.request_id = Some("test-request".to_string());
+        TestRequest {
+            config: &self.config,
+            request,
+            query: HashMap::new(),
+            claims: None,
+        }
+    }
+}
+
+/// Request being built by a `TestClient`. Unauthenticated unless `as_user`,
+/// `as_admin` or `claims` is used.
+pub struct TestRequest<'a> {
+    config: &'a RoutingConfig,
+    request: ApiGatewayProxyRequest,
+    query: HashMap<String, Vec<String>>,
+    claims: Option<FakeClaims>,
+}
+
+impl TestRequest<'_> {
+    pub fn as_user(mut self, sub: &str) -> Self {
+        self.claims = Some(FakeClaims::user(sub));
+        self
+    }
+
+    /// Adds the first admin group of the config's `RoleConfig` to the user
+    /// set by `as_user`, or authenticates as `TEST_ADMIN_SUB` if no user was
+    /// set.
+    pub fn as_admin(mut self) -> Self {
+        let claims = self
+            .claims
+            .get_or_insert_with(|| FakeClaims::user(TEST_ADMIN_SUB));
+        if let Some(group) = self.config.roles.admin_groups.first() {
+            if !claims.groups.contains(group) {
+                claims.groups.push(group.clone());
+            }
+        }
+        self
+    }
+
+    /// Adds user pool groups to the user set by `as_user`, or authenticates
+    /// as `TEST_USER_SUB` if no user was set.
+    pub fn in_groups(mut self, groups: &[&str]) -> Self {
+        let claims = self
+            .claims
+            .get_or_insert_with(|| FakeClaims::user(TEST_USER_SUB));
+        for group in groups {
+            if !claims.groups.iter().any(|g| g == group) {
+                claims.groups.push(group.to_string());
+            }
+        }
+        self
+    }
+
+    pub fn claims(mut self, claims: FakeClaims) -> Self {
+        self.claims = Some(claims);
+        self
+    }
+
+    /// Adds a query string parameter. Can be repeated for multi-value
+    /// parameters.
+    pub fn query(mut self, key: &str, value: impl ToString) -> Self {
+        self.query
+            .entry(key.to_string())
+            .or_default()
+            .push(value.to_string());
+        self
+    }
+
+    /// Panics if the name or value is not a valid header.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.request.headers.insert(
+            HeaderName::from_bytes(name.as_bytes()).expect("invalid header name"),
+            HeaderValue::from_str(value).expect("invalid header value"),
+        );
+        self
+    }
+
+    /// Sets the body to the JSON serialization of `body`.
+    pub fn json<T: Serialize>(mut self, body: &T) -> Self {
+        self.request.body =
+            Some(serde_json::to_string(body).expect("failed to serialize request body"));
+        self.request.headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/json; charset=utf-8"),
+        );
+        self
+    }
+
+    /// Sets the raw body.
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.request.body = Some(body.into());
+        self
+    }
+
+    pub async fn send(mut self) -> TestResponse {
+        self.request.query_string_parameters = self.query.into();
+        if let Some(claims) = self.claims {
+            self.request.request_context.authorizer = claims.authorizer();
+        }
+        let event = LambdaEvent::new(self.request, Context::default());
+        match self.config.handle(event).await {
+            Ok(response) => TestResponse { response },
+            // What API Gateway returns when the Lambda invocation fails.
+            Err(e) => {
+                eprintln!("ERROR\nHandler failed: {e}");
+                let mut response = ApiGatewayProxyResponse::default();
+                response.status_code = 502;
+                response.body = Some(Body::Text(
+                    r#"{"message": "Internal server error"}"#.to_string(),
+                ));
+                TestResponse { response }
+            }
+        }
+    }
+
+    /// Shorthand for `send` followed by `TestResponse::decode`.
+    pub async fn send_json<T: DeserializeOwned>(self) -> Result<T, ClientVisibleError> {
+        self.send().await.decode()
+    }
+}
+
+/// Response received by a `TestClient`.
+#[derive(Debug)]
+pub struct TestResponse {
+    pub response: ApiGatewayProxyResponse,
+}
+
+impl TestResponse {
+    pub fn status_code(&self) -> i64 {
+        self.response.status_code
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.response
+            .headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+    }
+
+    /// The raw response body.
+    pub fn text(&self) -> &str {
+        match &self.response.body {
+            Some(Body::Text(text)) => text,
+            Some(Body::Binary(bytes)) => std::str::from_utf8(bytes).unwrap_or_default(),
+            _ => "",
+        }
+    }
+
+    /// Decodes the payload of a successful response, in whichever format,
+    /// encoding and envelope version it was returned. Errors are returned as
+    /// the client would see them (envelope errors with `ok == false`, problem
+    /// details, or non-200 responses).
+    ///
+    /// Panics if the response is malformed or the payload is not a `T`.
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, ClientVisibleError> {
+        match decode_response(self.status_code(), &self.response.headers, self.text()) {
+            Ok(data) => Ok(data),
+            Err(ClientError::Api(e)) => Err(e),
+            Err(ClientError::Request(e)) => panic!("failed to decode response: {e}"),
+        }
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        constants::UNAUTHORIZED_ERROR_MSG,
+        handle_with_router::{
+            routing_config::{Access, OwnedAccess, TypeRef, Validation, WithErrorMode},
+            std::{
+                extractors::{Json, Metadata, Path},
+                function_specs::{
+                    ExtractorFunction, Function, NullaryFunction, OwnedExtractorFunction,
+                },
+                validators::{AdminOnly, RequireScopes},
+            },
+        },
+        shared::{
+            api_keys::{InMemoryApiKeyStore, API_KEY_HEADER},
+            auth_utils::RoleConfig,
+            response_building::{ErrorMode, ENVELOPE_VERSION_HEADER},
+        },
+        RequestContext,
+    };
+    use fractic_server_error::define_client_error;
+    use serde::Deserialize;
+    use serde_json::Value;
+    use std::{collections::HashMap, sync::Arc};
+
+    define_client_error!(NameTakenError, "Name is already taken.");
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Greeting {
+        message: String,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Name {
+        name: String,
+    }
+
+    fn client() -> TestClient {
+        let mut config = RoutingConfig::default();
+        config.function_routes.insert(
+            "greet",
+            Function::new(Access::Guest, Validation::None, |input: Name| async move {
+                match input.name.as_str() {
+                    "taken" => Err(NameTakenError::new()),
+                    name => Ok(Greeting {
+                        message: format!("Hello, {name}!"),
+                    }),
+                }
+            }),
+        );
+        config.function_routes.insert(
+            "GET whoami",
+            NullaryFunction::with_context(
+                Access::AnyUser,
+                Validation::None,
+                |ctx: RequestContext| async move {
+                    Ok((ctx.metadata.user_sub, ctx.metadata.is_admin))
+                },
+            ),
+        );
+        TestClient::new(config)
+    }
+
+    #[tokio::test]
+    async fn test_send_json() {
+        let client = client();
+        let greeting: Greeting = client
+            .post("/greet")
+            .json(&serde_json::json!({ "name": "John" }))
+            .send_json()
+            .await
+            .unwrap();
+        assert_eq!(greeting.message, "Hello, John!");
+
+        let error = client
+            .post("greet")
+            .json(&serde_json::json!({ "name": "taken" }))
+            .send_json::<Greeting>()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error,
+            ClientVisibleError {
+                status_code: 200,
+                message: "Name is already taken.".to_string(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_claims() {
+        let client = client();
+        let error = client.get("whoami").send_json::<Value>().await.unwrap_err();
+        assert_eq!(error.status_code, 401);
+        assert_eq!(error.message, UNAUTHORIZED_ERROR_MSG);
+
+        let whoami: (Option<String>, bool) = client
+            .get("whoami")
+            .as_user("user-1")
+            .send_json()
+            .await
+            .unwrap();
+        assert_eq!(whoami, (Some("user-1".to_string()), false));
+
+        let whoami: (Option<String>, bool) = client
+            .get("whoami")
+            .as_user("user-1")
+            .as_admin()
+            .send_json()
+            .await
+            .unwrap();
+        assert_eq!(whoami, (Some("user-1".to_string()), true));
+
+        let whoami: (Option<String>, bool) =
+            client.get("whoami").as_admin().send_json().await.unwrap();
+        assert_eq!(whoami, (Some(TEST_ADMIN_SUB.to_string()), true));
+    }
+
+    #[tokio::test]
+    async fn test_groups() {
+        let mut client = client();
+        client.config.function_routes.insert(
+            "moderate",
+            NullaryFunction::new(
+                Access::AnyOf(&["editor", "moderator"]),
+                Validation::None,
+                || async { Ok(()) },
+            ),
+        );
+        client.config.function_routes.insert(
+            "publish",
+            NullaryFunction::new(
+                Access::AllOf(&["editor", "publisher"]),
+                Validation::None,
+                || async { Ok(()) },
+            ),
+        );
+        let status = async |request: TestRequest| request.send().await.status_code();
+
+        assert_eq!(status(client.post("moderate").as_user("user-1")).await, 401);
+        assert_eq!(status(client.post("moderate").as_admin()).await, 401);
+        assert_eq!(
+            status(client.post("moderate").in_groups(&["editor"])).await,
+            200
+        );
+        assert_eq!(
+            status(client.post("publish").in_groups(&["editor"])).await,
+            401
+        );
+        assert_eq!(
+            status(client.post("publish").in_groups(&["editor", "publisher"])).await,
+            200
+        );
+
+        // Admin groups are configurable.
+        client.config.roles = RoleConfig {
+            admin_groups: vec!["staff".to_string()],
+        };
+        let whoami: (Option<String>, bool) = client
+            .get("whoami")
+            .in_groups(&["admin"])
+            .send_json()
+            .await
+            .unwrap();
+        assert_eq!(whoami, (Some(TEST_USER_SUB.to_string()), false));
+        let whoami: (Option<String>, bool) = client
+            .get("whoami")
+            .in_groups(&["staff"])
+            .send_json()
+            .await
+            .unwrap();
+        assert_eq!(whoami, (Some(TEST_USER_SUB.to_string()), true));
+    }
+
+    #[tokio::test]
+    async fn test_scopes() {
+        let mut client = client();
+        client.config.function_routes.insert(
+            "orders",
+            NullaryFunction::new(
+                Access::Scope("orders/read"),
+                Validation::Require(RequireScopes::all(&["orders/write"])),
+                || async { Ok(()) },
+            ),
+        );
+        let status = async |claims: FakeClaims| {
+            client
+                .post("orders")
+                .claims(claims)
+                .send()
+                .await
+                .status_code()
+        };
+
+        assert_eq!(
+            status(FakeClaims::client("svc", &["orders/read"])).await,
+            401
+        );
+        assert_eq!(
+            status(FakeClaims::client("svc", &["orders/read", "orders/write"])).await,
+            200
+        );
+        assert_eq!(status(FakeClaims::admin("admin-sub")).await, 401);
+
+        // Client-credentials tokens are not users, so routes open to any user
+        // reject them.
+        assert_eq!(
+            client
+                .get("whoami")
+                .claims(FakeClaims::client("svc", &["orders/read"]))
+                .send()
+                .await
+                .status_code(),
+            401
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extractor_functions() {
+        let mut client = client();
+        client.config.function_routes.insert(
+            "rename",
+            ExtractorFunction::with_json(
+                Access::AnyUser,
+                Validation::Require(AdminOnly::if_true(|input: &Name| input.name == "admin")),
+                |Json(input): Json<Name>, Metadata(metadata): Metadata| async move {
+                    Ok((input.name, metadata.user_sub))
+                },
+            ),
+        );
+        client.config.function_routes.insert(
+            "GET users/{user_id}/profile",
+            OwnedExtractorFunction::new(
+                |ctx: &RequestContext| ctx.path_param("user_id"),
+                OwnedAccess::Owner,
+                Validation::None,
+                |Path(path): Path<HashMap<String, String>>| async move {
+                    Ok(path["user_id"].clone())
+                },
+            ),
+        );
+
+        let renamed: (String, Option<String>) = client
+            .post("rename")
+            .as_user("user-1")
+            .json(&serde_json::json!({ "name": "new" }))
+            .send_json()
+            .await
+            .unwrap();
+        assert_eq!(renamed, ("new".to_string(), Some("user-1".to_string())));
+        let response = client
+            .post("rename")
+            .as_user("user-1")
+            .json(&serde_json::json!({ "name": "admin" }))
+            .send()
+            .await;
+        assert_eq!(response.status_code(), 401);
+        let info = client.config.function_routes.get("rename").unwrap().info();
+        assert_eq!(info.input, Some(TypeRef::of::<Name>()));
+
+        let profile: String = client
+            .get("users/user-1/profile")
+            .as_user("user-1")
+            .send_json()
+            .await
+            .unwrap();
+        assert_eq!(profile, "user-1");
+        let response = client
+            .get("users/user-2/profile")
+            .as_user("user-1")
+            .send()
+            .await;
+        assert_eq!(response.status_code(), 401);
+    }
+
+    #[tokio::test]
+    async fn test_api_keys() {
+        let mut client = client();
+        client.config.api_keys = Some(Arc::new(
+            InMemoryApiKeyStore::new()
+                .with_key("key-1", "partner-1", &["orders:read"])
+                .with_key("key-2", "partner-2", &[]),
+        ));
+        client.config.function_routes.insert(
+            "GET partners/orders",
+            NullaryFunction::with_context(
+                Access::ApiKeyPermission("orders:read"),
+                Validation::None,
+                |ctx: RequestContext| async move {
+                    Ok(ctx.metadata.api_key.map(|key| key.principal))
+                },
+            ),
+        );
+        let send = async |key: &str| {
+            client
+                .get("partners/orders")
+                .header(API_KEY_HEADER, key)
+                .send()
+                .await
+        };
+
+        let principal: Option<String> = send("key-1").await.decode().unwrap();
+        assert_eq!(principal.as_deref(), Some("partner-1"));
+        assert_eq!(send("key-2").await.status_code(), 401);
+        assert_eq!(send("unknown").await.status_code(), 401);
+        assert_eq!(
+            client.get("partners/orders").send().await.status_code(),
+            401
+        );
+
+        // API keys don't authenticate users.
+        let error = client
+            .get("whoami")
+            .header(API_KEY_HEADER, "key-1")
+            .send_json::<Value>()
+            .await
+            .unwrap_err();
+        assert_eq!(error.status_code, 401);
+    }
+
+    #[tokio::test]
+    async fn test_decode_negotiated_responses() {
+        let client = client();
+        for (format, encoding, envelope) in [
+            ("json", "identity", "1"),
+            ("json", "br", "2"),
+            ("msgpack", "zstd", "1"),
+            ("cbor", "gzip", "2"),
+        ] {
+            let greeting: Greeting = client
+                .post("greet")
+                .query("format", format)
+                .query("encoding", encoding)
+                .header(ENVELOPE_VERSION_HEADER, envelope)
+                .json(&serde_json::json!({ "name": "John" }))
+                .send_json()
+                .await
+                .unwrap();
+            assert_eq!(greeting.message, "Hello, John!");
+
+            let error = client
+                .post("greet")
+                .header(ENVELOPE_VERSION_HEADER, envelope)
+                .json(&serde_json::json!({ "name": "taken" }))
+                .send_json::<Greeting>()
+                .await
+                .unwrap_err();
+            assert_eq!(error.message, "Name is already taken.");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decode_problem() {
+        let mut client = client();
+        client.config.error_mode = ErrorMode::Problem(Default::default());
+        let error = client
+            .post("missing")
+            .send_json::<Value>()
+            .await
+            .unwrap_err();
+        assert_eq!(error.status_code, 404);
+
+        let error = client
+            .post("greet")
+            .json(&serde_json::json!({ "name": "taken" }))
+            .send_json::<Greeting>()
+            .await
+            .unwrap_err();
+        assert_eq!(error.status_code, 400);
+        assert_eq!(error.message, "Name is already taken.");
+
+        client.config.function_routes.insert(
+            "admin",
+            NullaryFunction::new(Access::Admin, Validation::None, || async { Ok(()) }),
+        );
+        let response = client.post("admin").send().await;
+        assert_eq!(response.status_code(), 401);
+        let response = client.post("admin").as_user("user-1").send().await;
+        assert_eq!(response.status_code(), 403);
+        let response = client.post("admin").as_admin().send().await;
+        assert_eq!(response.status_code(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_with_error_mode() {
+        let mut client = client();
+        client.config.function_routes.insert(
+            "partners/greet",
+            Box::new(WithErrorMode::new(
+                ErrorMode::Problem(Default::default()),
+                Function::new(Access::Guest, Validation::None, |_: Name| async {
+                    Err::<Greeting, _>(NameTakenError::new())
+                }),
+            )),
+        );
+
+        let response = client
+            .post("partners/greet")
+            .json(&serde_json::json!({ "name": "taken" }))
+            .send()
+            .await;
+        assert_eq!(response.status_code(), 400);
+        assert_eq!(
+            response.header("content-type"),
+            Some("application/problem+json")
+        );
+        assert_eq!(
+            response.decode::<Greeting>().unwrap_err().message,
+            "Name is already taken."
+        );
+
+        // Other routes keep the router's error mode.
+        let response = client
+            .post("greet")
+            .json(&serde_json::json!({ "name": "taken" }))
+            .send()
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(
+            response.decode::<Greeting>().unwrap_err().message,
+            "Name is already taken."
+        );
+    }
+}
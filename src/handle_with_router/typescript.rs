@@ -0,0 +1,604 @@
+use std::fmt::Write as _;
+
+use schemars::{JsonSchema, Schema};
+
+use crate::handle_with_router::{
+    path_templates::template_params,
+    routing_config::{
+        split_route_key, AccessRule, CrudInfo, CrudOperationKind, FunctionInfo, RoutingConfig,
+        TypeRef,
+    },
+    schema_registry::SchemaRegistry,
+};
+
+// TypeScript client generation.
+// --------------------------------------------------
+//
+// Generates a typed TypeScript client from a `RoutingConfig`, so that front
+// ends don't need to re-implement route strings and envelope decoding. The
+// client contains:
+//
+// - A type for each registered schema (see `SchemaRegistry`).
+// - A method for each function route (e.g. "GET users/{user_id}" becomes
+//   `getUsersUserId(path, query)`).
+// - An object of CRUD helpers for each CRUD route (e.g. `items.list()`,
+//   `items.create(data)`), limited to the operations the route allows.
+//
+// The client requests the default response encoding (JSON -> gzip -> base64)
+// explicitly, so that a browser's `Accept` header can't negotiate another one,
+// and decodes it using `DecompressionStream`, available in modern browsers and
+// Node 18+. Responses in any other format or encoding are rejected.
+// Errors forwarded in the envelope (`ok == false`) and non-200 responses are
+// thrown as `ApiError`.
+
+pub struct TypeScriptClient {
+    /// Name of the generated client class.
+    pub class_name: String,
+    pub schemas: SchemaRegistry,
+}
+
+impl TypeScriptClient {
+    pub fn new(class_name: &str) -> Self {
+        Self {
+            class_name: class_name.to_string(),
+            schemas: SchemaRegistry::default(),
+        }
+    }
+
+    /// Registers the schema of a type used as a route's input, output, path
+    /// parameters or CRUD item.
+    pub fn schema<T: JsonSchema + ?Sized + 'static>(mut self) -> Self {
+        self.schemas.register::<T>();
+        self
+    }
+
+    /// Returns the source of a TypeScript module exporting the client class
+    /// and types.
+    pub fn generate(mut self, config: &RoutingConfig) -> String {
+        let mut members = String::new();
+
+        let mut function_routes: Vec<_> = config.function_routes.iter().collect();
+        function_routes.sort_by_key(|(key, _)| **key);
+        for (key, spec) in function_routes {
+            let info = spec.info();
+            if info.access.as_ref().is_some_and(AccessRule::is_none) {
+                continue;
+            }
+            members.push_str(&self.function_method(key, &info));
+        }
+
+        let mut crud_routes: Vec<_> = config.crud_routes.iter().collect();
+        crud_routes.sort_by_key(|(key, _)| **key);
+        for (key, spec) in crud_routes {
+            members.push_str(&self.crud_helpers(key, &spec.info()));
+        }
+
+        let mut types = String::new();
+        for (name, schema) in self.schemas.definitions() {
+            let _ = writeln!(
+                types,
+                "export type {} = {};\n",
+                ts_name(&name),
+                ts_type(&schema)
+            );
+        }
+
+        format!(
+            "// Generated from a RoutingConfig. Do not edit.\n\n{types}{RUNTIME}\nexport class {} extends ApiClientBase {{{members}}}\n",
+            self.class_name
+        )
+    }
+
+    fn function_method(&self, key: &str, info: &FunctionInfo) -> String {
+        let (method, path) = split_route_key(key);
+        let path = path.trim_matches('/');
+        let path_params = template_params(path);
+
+        let mut params = Vec::new();
+        if !path_params.is_empty() {
+            let fields: Vec<String> = path_params
+                .iter()
+                .map(|name| format!("{}: string", ts_property(name)))
+                .collect();
+            params.push(format!("path: {{ {} }}", fields.join("; ")));
+        }
+        let (query, body) = match info.input {
+            // GET functions read their input from the query string (see
+            // `parse_request_input`).
+            Some(input) if method == "GET" => {
+                params.push(format!("query: {}", self.ts_type_of(input)));
+                ("query", "undefined")
+            }
+            Some(input) => {
+                params.push(format!("input: {}", self.ts_type_of(input)));
+                ("undefined", "input")
+            }
+            None => ("undefined", "undefined"),
+        };
+        let url = path
+            .split('/')
+            .map(|segment| match template_params(segment).first() {
+                Some(name) => format!("${{encodeURIComponent(path[{}])}}", ts_string(name)),
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let mut out = String::new();
+        let _ = writeln!(out, "\n  /** {key}{} */", access_note(info.access.as_ref()));
+        let _ = writeln!(
+            out,
+            "  {}({}): Promise<{}> {{",
+            method_name(method.as_str(), path),
+            params.join(", "),
+            self.ts_type_of_opt(info.output)
+        );
+        let _ = writeln!(
+            out,
+            "    return this.request({}, `{url}`, {query}, {body});",
+            ts_string(method.as_str())
+        );
+        out.push_str("  }\n");
+        out
+    }
+
+    fn crud_helpers(&self, route: &str, info: &CrudInfo) -> String {
+        use CrudOperationKind as K;
+
+        let path = ts_string(route.trim_matches('/'));
+        let output = self.ts_type_of_opt(info.output);
+        let data = self.ts_type_of_opt(info.data);
+        let item = self.ts_type_of_opt(info.item);
+        let non_recursive = if info.allow_non_recursive_delete {
+            " & { non_recursive?: boolean }"
+        } else {
+            ""
+        };
+
+        let mut helpers: Vec<String> = Vec::new();
+        let mut add = |operation: K, name: &str, signature: String, call: String| {
+            if is_enabled(info, operation) {
+                if let Some(access) = info.access_of(operation) {
+                    helpers.push(format!("    /** Access: {access}. */\n"));
+                }
+                helpers.push(format!(
+                    "    {name}: ({signature}): Promise<{output}> =>\n      this.request({call}),\n"
+                ));
+            }
+        };
+        add(
+            K::List,
+            "list",
+            "params: { parent_id?: string } = {}".to_string(),
+            format!(r#""GET", {path}, {{ ...params, all: true }}"#),
+        );
+        add(
+            K::Read,
+            "read",
+            "ref: ItemRef".to_string(),
+            format!(r#""GET", {path}, ref"#),
+        );
+        add(
+            K::Create,
+            "create",
+            format!("data: {data}, params: {{ parent_id?: string; after?: string }} = {{}}"),
+            format!(r#""POST", {path}, params, data"#),
+        );
+        add(
+            K::Update,
+            "update",
+            format!("item: {item}"),
+            format!(r#""PUT", {path}, undefined, item"#),
+        );
+        add(
+            K::Delete,
+            "delete",
+            format!("ref: ItemRef{non_recursive}"),
+            format!(r#""DELETE", {path}, ref"#),
+        );
+        add(
+            K::DeleteAll,
+            "deleteAll",
+            format!("params: {{ parent_id?: string }}{non_recursive} = {{}}"),
+            format!(r#""DELETE", {path}, {{ ...params, all: true }}"#),
+        );
+        add(
+            K::ReplaceAll,
+            "replaceAll",
+            format!("data: Array<{data}>, params: {{ parent_id?: string }} = {{}}"),
+            format!(r#""POST", {path}, {{ ...params, replace_all: true }}, data"#),
+        );
+        if info.allow_batching {
+            add(
+                K::Read,
+                "readMany",
+                "refs: ItemRefs".to_string(),
+                format!(r#""GET", {path}, refs"#),
+            );
+            add(
+                K::Create,
+                "createMany",
+                format!(
+                    "data: Array<{data}>, params: {{ parent_id?: string; after?: string }} = {{}}"
+                ),
+                format!(r#""POST", {path}, params, data"#),
+            );
+            add(
+                K::Delete,
+                "deleteMany",
+                format!("refs: ItemRefs{non_recursive}"),
+                format!(r#""DELETE", {path}, refs"#),
+            );
+        }
+        if helpers.is_empty() {
+            return String::new();
+        }
+        format!(
+            "\n  /** CRUD route '{route}'. */\n  readonly {} = {{\n{}  }};\n",
+            camel_case(route),
+            helpers.join("")
+        )
+    }
+
+    fn ts_type_of(&self, type_ref: TypeRef) -> String {
+        match self.schemas.get(type_ref) {
+            Some(schema) => ts_type(schema),
+            None => "unknown".to_string(),
+        }
+    }
+
+    fn ts_type_of_opt(&self, type_ref: Option<TypeRef>) -> String {
+        match type_ref {
+            Some(type_ref) => self.ts_type_of(type_ref),
+            None => "unknown".to_string(),
+        }
+    }
+}
+
+/// Runtime shared by all generated clients.
+const RUNTIME: &str = r#"export class ApiError extends Error {
+  constructor(
+    readonly status: number,
+    message: string,
+  ) {
+    super(message);
+    this.name = "ApiError";
+  }
+}
+
+export interface ClientOptions {
+  /** Base URL of the API stage (e.g. "https://api.example.com/prod"). */
+  baseUrl: string;
+  /** Cognito ID token of the current user, if any. */
+  getToken?: () => Promise<string | undefined> | string | undefined;
+  fetch?: typeof fetch;
+}
+
+export type ItemRef = { id: string } | { key: string; parent_id?: string } | { parent_id?: string };
+
+export type ItemRefs = { ids: string[] } | { keys: string[]; parent_id?: string };
+
+type QueryValue = string | number | boolean | string[] | null | undefined;
+
+interface Envelope {
+  ok: boolean;
+  data?: unknown;
+  error?: string | { message: string };
+}
+
+async function decodeData(data: unknown, format: string, encoding: string): Promise<unknown> {
+  if (format !== "json") {
+    throw new Error(`Unsupported payload format: ${format}`);
+  }
+  if (encoding === "identity") {
+    return data;
+  }
+  if (encoding !== "gzip" || typeof data !== "string") {
+    throw new Error(`Unsupported payload encoding: ${encoding}`);
+  }
+  const bytes = Uint8Array.from(atob(data), (c) => c.charCodeAt(0));
+  const stream = new Blob([bytes]).stream().pipeThrough(new DecompressionStream("gzip"));
+  return JSON.parse(await new Response(stream).text());
+}
+
+export class ApiClientBase {
+  constructor(protected readonly options: ClientOptions) {}
+
+  protected async request<T>(
+    method: string,
+    path: string,
+    query?: object,
+    body?: unknown,
+  ): Promise<T> {
+    const search = new URLSearchParams();
+    for (const [key, value] of Object.entries(query ?? {}) as [string, QueryValue][]) {
+      // Flags are only checked for presence, so false values are left out.
+      if (value === undefined || value === null || value === false) continue;
+      search.set(key, Array.isArray(value) ? value.join(",") : String(value));
+    }
+    const qs = search.toString();
+    const url = `${this.options.baseUrl.replace(/\/$/, "")}/${path}${qs ? `?${qs}` : ""}`;
+    const headers: Record<string, string> = {
+      "X-Payload-Format": "json",
+      "X-Payload-Encoding": "gzip",
+    };
+    const token = await this.options.getToken?.();
+    if (token) headers["Authorization"] = token;
+    if (body !== undefined) headers["Content-Type"] = "application/json";
+    const response = await (this.options.fetch ?? fetch)(url, {
+      method,
+      headers,
+      body: body === undefined ? undefined : JSON.stringify(body),
+    });
+    if (response.headers.get("Content-Type")?.startsWith("application/problem+json")) {
+      const problem = await response.json();
+      throw new ApiError(response.status, problem.detail ?? problem.title);
+    }
+    if (response.status !== 200) {
+      throw new ApiError(response.status, await response.text());
+    }
+    const envelope = (await response.json()) as Envelope;
+    if (!envelope.ok) {
+      const error = envelope.error;
+      throw new ApiError(200, typeof error === "string" ? error : (error?.message ?? ""));
+    }
+    // The payload headers are only readable cross-origin if exposed by the
+    // CORS policy, so fall back to the requested format and encoding.
+    const format = response.headers.get("X-Payload-Format") ?? "json";
+    const encoding = response.headers.get("X-Payload-Encoding") ?? "gzip";
+    return (await decodeData(envelope.data, format, encoding)) as T;
+  }
+}
+"#;
+
+// Helper functions.
+// --------------------------------------------------
+
+/// Operations of custom specs that don't describe their access are assumed to
+/// be enabled.
+fn is_enabled(info: &CrudInfo, operation: CrudOperationKind) -> bool {
+    match info.access_of(operation) {
+        Some(rule) => !rule.is_none(),
+        None => info.access.is_empty(),
+    }
+}
+
+fn access_note(access: Option<&AccessRule>) -> String {
+    match access {
+        Some(access) => format!(" (access: {access})"),
+        None => String::new(),
+    }
+}
+
+/// Method for a function route: the camel-cased path, prefixed by the HTTP
+/// method unless it is POST (the default for function routes).
+fn method_name(method: &str, path: &str) -> String {
+    match method {
+        "POST" => camel_case(path),
+        _ => camel_case(&format!("{} {path}", method.to_ascii_lowercase())),
+    }
+}
+
+fn camel_case(s: &str) -> String {
+    let mut out = String::new();
+    for word in s
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+    {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            if out.is_empty() {
+                out.push(first.to_ascii_lowercase());
+            } else {
+                out.push(first.to_ascii_uppercase());
+            }
+            out.extend(chars);
+        }
+    }
+    if out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    out
+}
+
+fn ts_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn ts_string(s: &str) -> String {
+    format!("{s:?}")
+}
+
+fn ts_property(name: &str) -> String {
+    let is_identifier = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if is_identifier {
+        name.to_string()
+    } else {
+        ts_string(name)
+    }
+}
+
+/// Converts an (OpenAPI 3 flavoured) JSON schema into a TypeScript type.
+fn ts_type(schema: &Schema) -> String {
+    if let Some(allowed) = schema.as_bool() {
+        return if allowed { "unknown" } else { "never" }.to_string();
+    }
+    let ty = if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+        ts_name(reference.rsplit('/').next().unwrap_or_default())
+    } else if let Some(values) = schema.get("enum").and_then(|e| e.as_array()) {
+        let literals: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        format!("({})", literals.join(" | "))
+    } else if let Some(value) = schema.get("const") {
+        value.to_string()
+    } else if let Some(variants) = schema.get("oneOf").or_else(|| schema.get("anyOf")) {
+        let types = variants.as_array().into_iter().flatten();
+        ts_join(types.map(ts_subtype).collect(), " | ")
+    } else if let Some(parts) = schema.get("allOf") {
+        let types = parts.as_array().into_iter().flatten();
+        ts_join(types.map(ts_subtype).collect(), " & ")
+    } else {
+        match schema.get("type").and_then(|t| t.as_str()) {
+            Some("string") => "string".to_string(),
+            Some("integer") | Some("number") => "number".to_string(),
+            Some("boolean") => "boolean".to_string(),
+            Some("null") => "null".to_string(),
+            Some("array") => match schema.get("items") {
+                Some(items) => format!("Array<{}>", ts_subtype(items)),
+                None => "unknown[]".to_string(),
+            },
+            Some("object") => ts_object_type(schema),
+            _ if schema.get("properties").is_some() => ts_object_type(schema),
+            _ => "unknown".to_string(),
+        }
+    };
+    match schema.get("nullable").and_then(|n| n.as_bool()) {
+        Some(true) => format!("{ty} | null"),
+        _ => ty,
+    }
+}
+
+/// Converts a nested schema (e.g. a property or array item).
+fn ts_subtype<'a, V>(value: &'a V) -> String
+where
+    &'a Schema: TryFrom<&'a V>,
+{
+    match <&Schema>::try_from(value) {
+        Ok(schema) => ts_type(schema),
+        Err(_) => "unknown".to_string(),
+    }
+}
+
+fn ts_join(types: Vec<String>, separator: &str) -> String {
+    match types.len() {
+        0 => "unknown".to_string(),
+        1 => types[0].clone(),
+        _ => format!("({})", types.join(separator)),
+    }
+}
+
+fn ts_object_type(schema: &Schema) -> String {
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(|r| r.as_array())
+        .map(|r| r.iter().filter_map(|name| name.as_str()).collect())
+        .unwrap_or_default();
+    let properties: Vec<String> = schema
+        .get("properties")
+        .and_then(|p| p.as_object())
+        .into_iter()
+        .flatten()
+        .map(|(name, property)| {
+            let optional = if required.contains(&name.as_str()) {
+                ""
+            } else {
+                "?"
+            };
+            format!("{}{optional}: {}", ts_property(name), ts_subtype(property))
+        })
+        .collect();
+    let additional = schema
+        .get("additionalProperties")
+        .filter(|a| a.is_object())
+        .map(|a| format!("Record<string, {}>", ts_subtype(a)));
+    match (properties.is_empty(), additional) {
+        (true, Some(additional)) => additional,
+        (true, None) => "Record<string, unknown>".to_string(),
+        (false, None) => format!("{{ {} }}", properties.join("; ")),
+        (false, Some(additional)) => format!("{{ {} }} & {additional}", properties.join("; ")),
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle_with_router::{
+        routing_config::{Access, Validation},
+        std::function_specs::{Function, NullaryFunction, NullaryPathFunction},
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, JsonSchema)]
+    struct Search {
+        query: String,
+        limit: Option<u32>,
+    }
+
+    #[derive(Serialize, Deserialize, JsonSchema)]
+    #[serde(rename_all = "snake_case")]
+    enum Role {
+        Member,
+        Owner,
+    }
+
+    #[derive(Serialize, Deserialize, JsonSchema)]
+    struct User {
+        name: String,
+        roles: Vec<Role>,
+    }
+
+    #[derive(Deserialize)]
+    struct UserPath {
+        #[allow(dead_code)]
+        user_id: String,
+    }
+
+    #[test]
+    fn test_generate() {
+        let mut config = RoutingConfig::default();
+        config.function_routes.insert(
+            "GET users/search",
+            Function::new(Access::AnyUser, Validation::None, |_: Search| async {
+                Ok(Vec::<User>::new())
+            }),
+        );
+        config.function_routes.insert(
+            "GET users/{user_id}",
+            NullaryPathFunction::new(Access::Admin, Validation::None, |_: UserPath| async {
+                Ok(None::<User>)
+            }),
+        );
+        config.function_routes.insert(
+            "disabled",
+            NullaryFunction::new(Access::None, Validation::None, || async { Ok(()) }),
+        );
+        let source = TypeScriptClient::new("Api")
+            .schema::<Search>()
+            .schema::<User>()
+            .schema::<Vec<User>>()
+            .generate(&config);
+
+        assert!(source.contains("export class Api extends ApiClientBase {"));
+        assert!(source.contains("export type Role = (\"member\" | \"owner\");"));
+        assert!(source.contains("export type User = { name: string; roles: Array<Role> };"));
+        assert!(source.contains("getUsersSearch(query: Search): Promise<Array<User>> {"));
+        assert!(source.contains(r#"return this.request("GET", `users/search`, query, undefined);"#));
+        assert!(source.contains("getUsersUserId(path: { user_id: string }): Promise<unknown> {"));
+        assert!(source.contains(r#"`users/${encodeURIComponent(path["user_id"])}`"#));
+        assert!(!source.contains("disabled"));
+        assert!(source.contains(r#""X-Payload-Encoding": "gzip","#));
+        assert!(source.contains(r#""X-Payload-Format": "json","#));
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(method_name("POST", "create_user"), "createUser");
+        assert_eq!(
+            method_name("DELETE", "users/{user_id}"),
+            "deleteUsersUserId"
+        );
+        assert_eq!(camel_case("2fa/verify"), "_2faVerify");
+        assert_eq!(ts_property("user_id"), "user_id");
+        assert_eq!(ts_property("content-type"), "\"content-type\"");
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+#[cfg(feature = "websocket-gateway")]
+use aws_sdk_apigatewaymanagement::{config::Builder, primitives::Blob, Client};
+use fractic_server_error::{CriticalError, ServerError};
+use serde::Serialize;
+
+use crate::errors::EncodingError;
+#[cfg(feature = "websocket-gateway")]
+use crate::errors::WebSocketPostError;
+
+// WebSocket connection utils.
+// --------------------------------------------------
+
+/// Connection registered by `WebSocketRoutingConfig` on `$connect`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    pub connection_id: String,
+    /// Sub of the authenticated user that opened the connection, if any.
+    pub user_sub: Option<String>,
+    /// Epoch milliseconds.
+    pub connected_at: i64,
+}
+
+/// Storage for open connections, so that messages can later be posted to
+/// them (e.g. all connections of a user). Connections are added on
+/// `$connect` and removed on `$disconnect`, or when posting to them reports
+/// them as gone.
+#[async_trait]
+pub trait ConnectionStore: Send + Sync {
+    async fn add(&self, connection: Connection) -> Result<(), ServerError>;
+
+    async fn remove(&self, connection_id: &str) -> Result<(), ServerError>;
+
+    async fn get(&self, connection_id: &str) -> Result<Option<Connection>, ServerError>;
+
+    async fn list_for_user(&self, user_sub: &str) -> Result<Vec<Connection>, ServerError>;
+}
+
+/// Result of posting a message to a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostOutcome {
+    Delivered,
+    /// The client has disconnected.
+    Gone,
+}
+
+/// Sends messages to connected clients (i.e. the API Gateway Management API).
+#[async_trait]
+pub trait ConnectionGateway: Send + Sync {
+    async fn post(&self, connection_id: &str, data: Vec<u8>) -> Result<PostOutcome, ServerError>;
+
+    async fn disconnect(&self, connection_id: &str) -> Result<(), ServerError>;
+}
+
+/// Serializes `data` as JSON and posts it to the connection.
+pub async fn post_json<T: Serialize>(
+    gateway: &dyn ConnectionGateway,
+    connection_id: &str,
+    data: &T,
+) -> Result<PostOutcome, ServerError> {
+    let bytes = serde_json::to_vec(data).map_err(|e| EncodingError::with_debug("json", &e))?;
+    gateway.post(connection_id, bytes).await
+}
+
+/// Posts `data` as JSON to all connections of a user, removing connections
+/// that turn out to be gone from the store. Returns the number of connections
+/// the message was delivered to.
+pub async fn post_json_to_user<T: Serialize>(
+    store: &dyn ConnectionStore,
+    gateway: &dyn ConnectionGateway,
+    user_sub: &str,
+    data: &T,
+) -> Result<usize, ServerError> {
+    let bytes = serde_json::to_vec(data).map_err(|e| EncodingError::with_debug("json", &e))?;
+    let mut delivered = 0;
+    for connection in store.list_for_user(user_sub).await? {
+        match gateway
+            .post(&connection.connection_id, bytes.clone())
+            .await?
+        {
+            PostOutcome::Delivered => delivered += 1,
+            PostOutcome::Gone => store.remove(&connection.connection_id).await?,
+        }
+    }
+    Ok(delivered)
+}
+
+// Implementations.
+// --------------------------------------------------
+
+/// `ConnectionGateway` backed by the API Gateway Management API.
+#[cfg(feature = "websocket-gateway")]
+pub struct ApiGatewayConnectionGateway {
+    client: Client,
+}
+
+#[cfg(feature = "websocket-gateway")]
+impl ApiGatewayConnectionGateway {
+    /// The endpoint is the API's stage URL, "https://{domain}/{stage}" (see
+    /// `WebSocketContext::endpoint`).
+    pub async fn new(endpoint: &str) -> Self {
+        let sdk_config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        let config = Builder::from(&sdk_config).endpoint_url(endpoint).build();
+        Self {
+            client: Client::from_conf(config),
+        }
+    }
+}
+
+#[cfg(feature = "websocket-gateway")]
+#[async_trait]
+impl ConnectionGateway for ApiGatewayConnectionGateway {
+    async fn post(&self, connection_id: &str, data: Vec<u8>) -> Result<PostOutcome, ServerError> {
+        match self
+            .client
+            .post_to_connection()
+            .connection_id(connection_id)
+            .data(Blob::new(data))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(PostOutcome::Delivered),
+            Err(e) => {
+                let e = e.into_service_error();
+                if e.is_gone_exception() {
+                    Ok(PostOutcome::Gone)
+                } else {
+                    Err(WebSocketPostError::with_debug(connection_id, &e))
+                }
+            }
+        }
+    }
+
+    async fn disconnect(&self, connection_id: &str) -> Result<(), ServerError> {
+        self.client
+            .delete_connection()
+            .connection_id(connection_id)
+            .send()
+            .await
+            .map_err(|e| WebSocketPostError::with_debug(connection_id, &e))?;
+        Ok(())
+    }
+}
+
+/// In-memory `ConnectionStore`. Only suitable for tests and local
+/// development, since Lambda instances do not share memory.
+#[derive(Default)]
+pub struct InMemoryConnectionStore {
+    connections: Mutex<HashMap<String, Connection>>,
+}
+
+impl InMemoryConnectionStore {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, Connection>>, ServerError> {
+        self.connections
+            .lock()
+            .map_err(|_| CriticalError::new("connection store lock was poisoned"))
+    }
+}
+
+#[async_trait]
+impl ConnectionStore for InMemoryConnectionStore {
+    async fn add(&self, connection: Connection) -> Result<(), ServerError> {
+        self.lock()?
+            .insert(connection.connection_id.clone(), connection);
+        Ok(())
+    }
+
+    async fn remove(&self, connection_id: &str) -> Result<(), ServerError> {
+        self.lock()?.remove(connection_id);
+        Ok(())
+    }
+
+    async fn get(&self, connection_id: &str) -> Result<Option<Connection>, ServerError> {
+        Ok(self.lock()?.get(connection_id).cloned())
+    }
+
+    async fn list_for_user(&self, user_sub: &str) -> Result<Vec<Connection>, ServerError> {
+        Ok(self
+            .lock()?
+            .values()
+            .filter(|c| c.user_sub.as_deref() == Some(user_sub))
+            .cloned()
+            .collect())
+    }
+}
+
+/// In-memory `ConnectionGateway` recording posted messages, for tests. Only
+/// connections marked as open (see `open`) receive messages; posting to any
+/// other connection reports it as gone.
+#[derive(Default)]
+pub struct InMemoryConnectionGateway {
+    messages: Mutex<HashMap<String, Vec<Vec<u8>>>>,
+}
+
+impl InMemoryConnectionGateway {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn open(&self, connection_id: &str) {
+        if let Ok(mut messages) = self.messages.lock() {
+            messages.entry(connection_id.to_string()).or_default();
+        }
+    }
+
+    /// Messages posted to the connection so far.
+    pub fn messages(&self, connection_id: &str) -> Vec<Vec<u8>> {
+        self.messages
+            .lock()
+            .ok()
+            .and_then(|messages| messages.get(connection_id).cloned())
+            .unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl ConnectionGateway for InMemoryConnectionGateway {
+    async fn post(&self, connection_id: &str, data: Vec<u8>) -> Result<PostOutcome, ServerError> {
+        let mut messages = self
+            .messages
+            .lock()
+            .map_err(|_| CriticalError::new("connection gateway lock was poisoned"))?;
+        match messages.get_mut(connection_id) {
+            Some(received) => {
+                received.push(data);
+                Ok(PostOutcome::Delivered)
+            }
+            None => Ok(PostOutcome::Gone),
+        }
+    }
+
+    async fn disconnect(&self, connection_id: &str) -> Result<(), ServerError> {
+        if let Ok(mut messages) = self.messages.lock() {
+            messages.remove(connection_id);
+        }
+        Ok(())
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(connection_id: &str, user_sub: &str) -> Connection {
+        Connection {
+            connection_id: connection_id.to_string(),
+            user_sub: Some(user_sub.to_string()),
+            connected_at: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_store() {
+        let store = InMemoryConnectionStore::new();
+        store.add(connection("a", "user1")).await.unwrap();
+        store.add(connection("b", "user1")).await.unwrap();
+        store.add(connection("c", "user2")).await.unwrap();
+        assert_eq!(store.list_for_user("user1").await.unwrap().len(), 2);
+
+        store.remove("a").await.unwrap();
+        assert!(store.get("a").await.unwrap().is_none());
+        assert_eq!(
+            store.get("c").await.unwrap(),
+            Some(connection("c", "user2"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_post_json_to_user_removes_gone_connections() {
+        let store = InMemoryConnectionStore::new();
+        let gateway = InMemoryConnectionGateway::new();
+        store.add(connection("open", "user")).await.unwrap();
+        store.add(connection("gone", "user")).await.unwrap();
+        gateway.open("open");
+
+        let delivered = post_json_to_user(store.as_ref(), gateway.as_ref(), "user", &"hello")
+            .await
+            .unwrap();
+        assert_eq!(delivered, 1);
+        assert_eq!(gateway.messages("open"), vec![b"\"hello\"".to_vec()]);
+        assert!(store.get("gone").await.unwrap().is_none());
+        assert!(store.get("open").await.unwrap().is_some());
+    }
+}
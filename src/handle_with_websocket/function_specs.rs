@@ -0,0 +1,153 @@
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
+use fractic_server_error::ServerError;
+use lambda_runtime::Error;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    errors::UnauthorizedError,
+    handle_with_router::routing_config::{is_allowed_access, Access},
+    handle_with_websocket::routing_config::{WebSocketContext, WebSocketSpec},
+    shared::{
+        request_processing::parse_request_data,
+        response_building::{build_err_for, build_err_with, build_result_with, ResponseOptions},
+    },
+    Validation,
+};
+
+type BoxedWebSocketHandler<I, O> = Box<
+    dyn Fn(
+            WebSocketContext,
+            I,
+        ) -> Pin<Box<dyn std::future::Future<Output = Result<O, ServerError>> + Send>>
+        + Send
+        + Sync,
+>;
+
+type BoxedWebSocketVoidHandler<O> = Box<
+    dyn Fn(
+            WebSocketContext,
+        ) -> Pin<Box<dyn std::future::Future<Output = Result<O, ServerError>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// WebSocket route without a message body (e.g. "$connect" or
+/// "$disconnect"). Denying access to "$connect" rejects the connection.
+pub struct NullaryWebSocketFunction<O>
+where
+    O: serde::Serialize + Send + 'static,
+{
+    access: Access,
+    validation: Validation<()>,
+    handler: BoxedWebSocketVoidHandler<O>,
+}
+
+impl<O> NullaryWebSocketFunction<O>
+where
+    O: serde::Serialize + Send + 'static,
+{
+    pub fn new<H, Fut>(
+        access: Access,
+        validation: Validation<()>,
+        handler: H,
+    ) -> Box<dyn WebSocketSpec>
+    where
+        H: Fn(WebSocketContext) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        Box::new(Self {
+            access,
+            validation,
+            handler: Box::new(move |ctx| Box::pin(handler(ctx))),
+        })
+    }
+}
+
+#[async_trait]
+impl<O> WebSocketSpec for NullaryWebSocketFunction<O>
+where
+    O: serde::Serialize + Send + 'static,
+{
+    async fn resolve(
+        &self,
+        ctx: &WebSocketContext,
+        options: &ResponseOptions,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let metadata = &ctx.request.metadata;
+        if !is_allowed_access(metadata, &self.access) {
+            return build_err_for(UnauthorizedError::new(), metadata, options);
+        }
+        if let Err(e) = self
+            .validation
+            .validate(&ctx.request.request, &(), metadata)
+        {
+            return build_err_for(e, metadata, options);
+        }
+        build_result_with((self.handler)(ctx.clone()).await, options)
+    }
+}
+
+/// WebSocket route whose message body is parsed as JSON into `I`.
+pub struct WebSocketFunction<I, O>
+where
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    access: Access,
+    validation: Validation<I>,
+    handler: BoxedWebSocketHandler<I, O>,
+}
+
+impl<I, O> WebSocketFunction<I, O>
+where
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    pub fn new<H, Fut>(
+        access: Access,
+        validation: Validation<I>,
+        handler: H,
+    ) -> Box<dyn WebSocketSpec>
+    where
+        H: Fn(WebSocketContext, I) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<O, ServerError>> + Send + 'static,
+    {
+        Box::new(Self {
+            access,
+            validation,
+            handler: Box::new(move |ctx, i| Box::pin(handler(ctx, i))),
+        })
+    }
+}
+
+#[async_trait]
+impl<I, O> WebSocketSpec for WebSocketFunction<I, O>
+where
+    I: DeserializeOwned + Send + 'static,
+    O: serde::Serialize + Send + 'static,
+{
+    async fn resolve(
+        &self,
+        ctx: &WebSocketContext,
+        options: &ResponseOptions,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let metadata = &ctx.request.metadata;
+        if !is_allowed_access(metadata, &self.access) {
+            return build_err_for(UnauthorizedError::new(), metadata, options);
+        }
+        let data: I = match parse_request_data(&ctx.request.request) {
+            Ok(d) => d,
+            Err(e) => return build_err_with(e, options),
+        };
+        if let Err(e) = self
+            .validation
+            .validate(&ctx.request.request, &data, metadata)
+        {
+            return build_err_for(e, metadata, options);
+        }
+        build_result_with((self.handler)(ctx.clone(), data).await, options)
+    }
+}
@@ -0,0 +1,35 @@
+#[macro_export]
+macro_rules! aws_lambda_handle_with_websocket {
+    ($config:expr) => {
+        async fn __handler(
+            event: ::lambda_runtime::LambdaEvent<
+                ::aws_lambda_events::apigw::ApiGatewayWebsocketProxyRequest,
+            >,
+        ) -> Result<::aws_lambda_events::apigw::ApiGatewayProxyResponse, ::lambda_runtime::Error> {
+            static CONFIG: ::std::sync::OnceLock<$crate::WebSocketRoutingConfig> =
+                ::std::sync::OnceLock::new();
+            let config_ref = CONFIG.get_or_init(|| $config);
+            config_ref.handle(event).await
+        }
+        $crate::aws_lambda_handle_raw!(__handler);
+    };
+    // See `aws_lambda_handle_with_router!`.
+    ($config:expr, state = $state:expr) => {
+        static __STATE: ::std::sync::OnceLock<$crate::AppState> = ::std::sync::OnceLock::new();
+        async fn __handler(
+            event: ::lambda_runtime::LambdaEvent<
+                ::aws_lambda_events::apigw::ApiGatewayWebsocketProxyRequest,
+            >,
+        ) -> Result<::aws_lambda_events::apigw::ApiGatewayProxyResponse, ::lambda_runtime::Error> {
+            static CONFIG: ::std::sync::OnceLock<$crate::WebSocketRoutingConfig> =
+                ::std::sync::OnceLock::new();
+            let config_ref = CONFIG.get_or_init(|| $config);
+            config_ref
+                .handle_with_state(event, __STATE.get().cloned())
+                .await
+        }
+        $crate::aws_lambda_handle_raw!(__handler, async {
+            let _ = __STATE.set(::std::sync::Arc::new($state.await));
+        });
+    };
+}
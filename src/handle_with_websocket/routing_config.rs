@@ -0,0 +1,424 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use aws_lambda_events::apigw::{
+    ApiGatewayProxyRequest, ApiGatewayProxyResponse, ApiGatewayWebsocketProxyRequest,
+};
+use lambda_runtime::{Error, LambdaEvent};
+
+use crate::{
+    errors::InvalidWebSocketRouteError,
+    handle_with_router::routing_config::{AppState, RouteContext},
+    handle_with_websocket::connections::{Connection, ConnectionStore},
+    shared::{
+        auth_utils::RoleConfig,
+        identity::{CognitoIdentity, IdentityExtractor},
+        request_processing::RequestContext,
+        response_building::{build_err_with, ErrorMode, PayloadEncoding, ResponseOptions},
+    },
+};
+
+// API Gateway WebSocket routing config.
+// --------------------------------------------------
+
+/// Route keys with a special meaning for API Gateway WebSocket APIs.
+pub const CONNECT_ROUTE: &str = "$connect";
+pub const DISCONNECT_ROUTE: &str = "$disconnect";
+pub const DEFAULT_ROUTE: &str = "$default";
+
+/// Information about the WebSocket event made available to handlers.
+#[derive(Clone)]
+pub struct WebSocketContext {
+    pub connection_id: String,
+    pub route_key: String,
+    /// Management API endpoint of the connection's stage
+    /// ("https://{domain}/{stage}"), used to post messages back to clients
+    /// (see `ApiGatewayConnectionGateway`).
+    pub endpoint: Option<String>,
+    /// Request information (metadata, headers, query parameters and state).
+    /// The request is the WebSocket event converted into a REST API request,
+    /// so that `Validation` rules can be shared with HTTP routes.
+    pub request: RequestContext,
+    /// Connection store configured on the `WebSocketRoutingConfig`, if any.
+    pub connections: Option<Arc<dyn ConnectionStore>>,
+}
+
+/// Trait implemented by WebSocket route specifications.
+#[async_trait]
+pub trait WebSocketSpec: Send + Sync {
+    async fn resolve(
+        &self,
+        ctx: &WebSocketContext,
+        options: &ResponseOptions,
+    ) -> Result<ApiGatewayProxyResponse, Error>;
+}
+
+/// Routes are keyed by the API's route keys (e.g. "$connect", "$disconnect",
+/// "sendMessage"). Messages with an unknown route key are handled by the
+/// "$default" route, if configured.
+///
+/// If a connection store is configured, connections are added to it once the
+/// "$connect" route succeeds (or immediately, if there is no such route), and
+/// removed on "$disconnect".
+///
+/// API Gateway only rejects a connection if "$connect" returns a non-2xx
+/// status, so errors of "$connect" and "$disconnect" always use
+/// `ErrorMode::Problem` (e.g. 401 if access is denied, 400 if validation
+/// fails).
+pub struct WebSocketRoutingConfig {
+    pub routes: HashMap<&'static str, Box<dyn WebSocketSpec>>,
+    pub connections: Option<Arc<dyn ConnectionStore>>,
+    /// Optional application state (see `AppState`).
+    pub state: Option<AppState>,
+    /// Clients cannot negotiate response options per message, so they are
+    /// configured here instead. Defaults to identity-encoded JSON.
+    pub response_options: ResponseOptions,
+    /// Which groups grant admin access.
+    pub roles: RoleConfig,
+    /// Where the caller's identity is read from. Defaults to
+    /// `CognitoIdentity`.
+    pub identity: Arc<dyn IdentityExtractor>,
+}
+
+impl Default for WebSocketRoutingConfig {
+    fn default() -> Self {
+        Self {
+            routes: HashMap::new(),
+            connections: None,
+            state: None,
+            response_options: ResponseOptions {
+                encoding: PayloadEncoding::Identity,
+                ..Default::default()
+            },
+            roles: RoleConfig::default(),
+            identity: Arc::new(CognitoIdentity),
+        }
+    }
+}
+
+// API Gateway WebSocket routing utils.
+// --------------------------------------------------
+
+impl WebSocketRoutingConfig {
+    // NOTE: Called by macro-generated tokens, so must be publically visible.
+    pub async fn handle(
+        &self,
+        event: LambdaEvent<ApiGatewayWebsocketProxyRequest>,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        self.handle_with_state(event, self.state.clone()).await
+    }
+
+    /// Like `handle`, but uses the given application state instead of
+    /// `self.state`.
+    pub async fn handle_with_state(
+        &self,
+        event: LambdaEvent<ApiGatewayWebsocketProxyRequest>,
+        state: Option<AppState>,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let event = event.payload;
+        let route_key = event.request_context.route_key.clone();
+        let connection_id = event
+            .request_context
+            .connection_id
+            .clone()
+            .unwrap_or_default();
+        let connected_at = event.request_context.connected_at;
+        let endpoint = stage_endpoint(&event);
+        let request = to_proxy_request(event);
+        let options = match route_key.as_deref() {
+            Some(CONNECT_ROUTE) | Some(DISCONNECT_ROUTE) => ResponseOptions {
+                error_mode: ErrorMode::Problem(Default::default()),
+                ..self.response_options.clone()
+            },
+            _ => self.response_options.clone(),
+        };
+        let route_ctx = RouteContext {
+            state,
+            roles: self.roles.clone(),
+            identity: self.identity.clone(),
+            ..Default::default()
+        };
+
+        // The connection is gone regardless of the outcome of "$disconnect",
+        // so it is removed before anything about the request can fail.
+        if route_key.as_deref() == Some(DISCONNECT_ROUTE) {
+            if let Some(store) = &self.connections {
+                if let Err(e) = store.remove(&connection_id).await {
+                    return build_err_with(e, &options);
+                }
+            }
+        }
+        let metadata = match route_ctx.request_metadata(&request).await {
+            Ok(m) => m,
+            Err(e) => return build_err_with(e, &options),
+        };
+        let ctx = WebSocketContext {
+            connection_id: connection_id.clone(),
+            route_key: route_key.clone().unwrap_or_default(),
+            endpoint,
+            request: RequestContext::new(&request, metadata, &route_ctx),
+            connections: self.connections.clone(),
+        };
+        let response = match self.find_spec(route_key.as_deref()) {
+            Some(spec) => spec.resolve(&ctx, &options).await?,
+            // API Gateway requires a response to $connect and $disconnect
+            // even if they are not routed.
+            None if matches!(
+                route_key.as_deref(),
+                Some(CONNECT_ROUTE) | Some(DISCONNECT_ROUTE)
+            ) =>
+            {
+                empty_ok()
+            }
+            None => return build_err_with(InvalidWebSocketRouteError::new(route_key), &options),
+        };
+        if route_key.as_deref() == Some(CONNECT_ROUTE) && is_success(&response) {
+            if let Some(store) = &self.connections {
+                let connection = Connection {
+                    connection_id,
+                    user_sub: ctx.request.metadata.user_sub.clone(),
+                    connected_at,
+                };
+                if let Err(e) = store.add(connection).await {
+                    return build_err_with(e, &options);
+                }
+            }
+        }
+        Ok(response)
+    }
+
+    fn find_spec(&self, route_key: Option<&str>) -> Option<&dyn WebSocketSpec> {
+        let route_key = route_key?;
+        self.routes
+            .get(route_key)
+            .or_else(|| match route_key {
+                CONNECT_ROUTE | DISCONNECT_ROUTE => None,
+                _ => self.routes.get(DEFAULT_ROUTE),
+            })
+            .map(|spec| spec.as_ref())
+    }
+}
+
+// Helper functions.
+// --------------------------------------------------
+
+/// Converts the event into a REST API request, so that the request parsing
+/// utils and validators can be reused.
+fn to_proxy_request(event: ApiGatewayWebsocketProxyRequest) -> ApiGatewayProxyRequest {
+    let mut request = ApiGatewayProxyRequest::default();
+    request.headers = event.headers;
+    request.multi_value_headers = event.multi_value_headers;
+    request.query_string_parameters = event.query_string_parameters;
+    request.multi_value_query_string_parameters = event.multi_value_query_string_parameters;
+    request.stage_variables = event.stage_variables;
+    request.body = event.body;
+    request.is_base64_encoded = event.is_base64_encoded;
+    request.request_context.request_id = event.request_context.request_id;
+    request.request_context.stage = event.request_context.stage;
+    request.request_context.authorizer = event.request_context.authorizer.unwrap_or_default();
+    request
+}
+
+fn stage_endpoint(event: &ApiGatewayWebsocketProxyRequest) -> Option<String> {
+    let domain = event.request_context.domain_name.as_deref()?;
+    let stage = event.request_context.stage.as_deref()?;
+    Some(format!("https://{domain}/{stage}"))
+}
+
+fn is_success(response: &ApiGatewayProxyResponse) -> bool {
+    (200..300).contains(&response.status_code)
+}
+
+fn empty_ok() -> ApiGatewayProxyResponse {
+    let mut response = ApiGatewayProxyResponse::default();
+    response.status_code = 200;
+    response
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        errors::{InvalidRequestError, UnauthorizedError},
+        handle_with_router::routing_config::{Access, Validation, ValidatorSpec},
+        handle_with_websocket::{
+            connections::InMemoryConnectionStore, function_specs::NullaryWebSocketFunction,
+        },
+        shared::{
+            fake_claims::FakeClaims, identity::Identity, request_processing::RequestMetadata,
+        },
+    };
+    use fractic_server_error::ServerError;
+    use lambda_runtime::Context;
+
+    /// Requires a "token" query parameter.
+    struct RequireToken;
+
+    impl ValidatorSpec<()> for RequireToken {
+        fn validate(
+            &self,
+            request: &ApiGatewayProxyRequest,
+            _data: &(),
+            _metadata: &RequestMetadata,
+        ) -> Result<(), ServerError> {
+            match request.query_string_parameters.first("token") {
+                Some(_) => Ok(()),
+                None => Err(InvalidRequestError::new("missing token")),
+            }
+        }
+    }
+
+    fn event(
+        route_key: &str,
+        connection_id: &str,
+        claims: Option<FakeClaims>,
+        token: Option<&str>,
+    ) -> LambdaEvent<ApiGatewayWebsocketProxyRequest> {
+        let mut event = ApiGatewayWebsocketProxyRequest::default();
+        event.request_context.route_key = Some(route_key.to_string());
+        event.request_context.connection_id = Some(connection_id.to_string());
+        event.request_context.authorizer = claims.map(|c| c.authorizer());
+        if let Some(token) = token {
+            event.query_string_parameters =
+                HashMap::from([("token".to_string(), token.to_string())]).into();
+        }
+        LambdaEvent::new(event, Context::default())
+    }
+
+    fn config(store: Arc<InMemoryConnectionStore>) -> WebSocketRoutingConfig {
+        let mut config = WebSocketRoutingConfig {
+            connections: Some(store),
+            ..Default::default()
+        };
+        config.routes.insert(
+            CONNECT_ROUTE,
+            NullaryWebSocketFunction::new(
+                Access::AnyUser,
+                Validation::Require(Box::new(RequireToken)),
+                |_| async { Ok(()) },
+            ),
+        );
+        config
+    }
+
+    #[tokio::test]
+    async fn test_connect() {
+        let store = InMemoryConnectionStore::new();
+        let config = config(store.clone());
+
+        let user = Some(FakeClaims::user("user-1"));
+        let response = config
+            .handle(event(CONNECT_ROUTE, "conn-1", user.clone(), Some("t")))
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 200);
+        let connection = store.get("conn-1").await.unwrap().unwrap();
+        assert_eq!(connection.user_sub.as_deref(), Some("user-1"));
+
+        let response = config
+            .handle(event(CONNECT_ROUTE, "conn-2", None, Some("t")))
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 401);
+        assert!(store.get("conn-2").await.unwrap().is_none());
+
+        let response = config
+            .handle(event(CONNECT_ROUTE, "conn-3", user, None))
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 400);
+        assert!(store.get("conn-3").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_disconnect() {
+        let store = InMemoryConnectionStore::new();
+        let config = config(store.clone());
+        let user = Some(FakeClaims::user("user-1"));
+        config
+            .handle(event(CONNECT_ROUTE, "conn-1", user.clone(), Some("t")))
+            .await
+            .unwrap();
+        assert!(store.get("conn-1").await.unwrap().is_some());
+
+        // Not routed, but still answered and removed from the store.
+        let response = config
+            .handle(event(DISCONNECT_ROUTE, "conn-1", user, None))
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 200);
+        assert!(store.get("conn-1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_with_failing_identity() {
+        struct FailingIdentity;
+
+        #[async_trait]
+        impl IdentityExtractor for FailingIdentity {
+            async fn extract(
+                &self,
+                _request: &ApiGatewayProxyRequest,
+            ) -> Result<Option<Identity>, ServerError> {
+                Err(UnauthorizedError::new())
+            }
+        }
+
+        let store = InMemoryConnectionStore::new();
+        let mut config = config(store.clone());
+        let user = Some(FakeClaims::user("user-1"));
+        config
+            .handle(event(CONNECT_ROUTE, "conn-1", user.clone(), Some("t")))
+            .await
+            .unwrap();
+        assert!(store.get("conn-1").await.unwrap().is_some());
+
+        // E.g. an expired token: the request is rejected, but the connection
+        // is still removed.
+        config.identity = Arc::new(FailingIdentity);
+        let response = config
+            .handle(event(DISCONNECT_ROUTE, "conn-1", user, None))
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 401);
+        assert!(store.get("conn-1").await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_stage_endpoint() {
+        let mut event = ApiGatewayWebsocketProxyRequest::default();
+        assert_eq!(stage_endpoint(&event), None);
+        event.request_context.domain_name = Some("abc.execute-api.aws.com".to_string());
+        event.request_context.stage = Some("prod".to_string());
+        assert_eq!(
+            stage_endpoint(&event).as_deref(),
+            Some("https://abc.execute-api.aws.com/prod")
+        );
+    }
+
+    #[test]
+    fn test_find_spec_falls_back_to_default() {
+        struct Noop;
+
+        #[async_trait]
+        impl WebSocketSpec for Noop {
+            async fn resolve(
+                &self,
+                _ctx: &WebSocketContext,
+                _options: &ResponseOptions,
+            ) -> Result<ApiGatewayProxyResponse, Error> {
+                Ok(empty_ok())
+            }
+        }
+
+        let mut config = WebSocketRoutingConfig::default();
+        assert!(config.find_spec(Some("sendMessage")).is_none());
+        config.routes.insert(DEFAULT_ROUTE, Box::new(Noop));
+        assert!(config.find_spec(Some("sendMessage")).is_some());
+        assert!(config.find_spec(Some(CONNECT_ROUTE)).is_none());
+        assert!(config.find_spec(None).is_none());
+    }
+}
@@ -12,18 +12,53 @@ mod handle_with_function {
     pub mod macros;
 }
 mod handle_with_router {
+    #[cfg(feature = "client")]
+    pub mod client;
+    pub mod handler;
+    #[cfg(feature = "local-server")]
+    pub mod local_server;
     pub mod macros;
+    pub mod middleware;
+    #[cfg(feature = "openapi")]
+    pub mod openapi;
+    pub mod path_templates;
+    pub mod route_table;
+    pub mod routes;
     pub mod routing_config;
+    #[cfg(any(feature = "openapi", feature = "typescript"))]
+    pub mod schema_registry;
     pub mod std {
         pub mod crud_specs;
+        pub mod extractors;
         pub mod function_specs;
         pub mod validators;
     }
+    #[cfg(any(test, feature = "test-utils"))]
+    pub mod test_client;
+    #[cfg(feature = "typescript")]
+    pub mod typescript;
+}
+mod handle_with_websocket {
+    pub mod connections;
+    pub mod function_specs;
+    pub mod macros;
+    pub mod routing_config;
 }
 mod shared {
+    pub mod api_events;
+    pub mod api_keys;
     pub mod auth_utils;
+    pub mod cors;
+    #[cfg(any(test, feature = "local-server", feature = "test-utils"))]
+    pub mod fake_claims;
+    pub mod identity;
+    #[cfg(feature = "jwt")]
+    pub mod jwt;
     pub mod request_processing;
     pub mod response_building;
+    #[cfg(any(test, feature = "client", feature = "test-utils"))]
+    pub mod response_decoding;
+    pub mod string_map_deserializer;
 }
 
 mod constants;
@@ -37,11 +72,42 @@ mod errors;
 // (Macros are automatically exported at crate root.)
 
 pub use errors::*;
+#[cfg(feature = "client")]
+pub use handle_with_router::client::*;
+pub use handle_with_router::handler::*;
+#[cfg(feature = "local-server")]
+pub use handle_with_router::local_server::*;
+pub use handle_with_router::middleware::*;
+#[cfg(feature = "openapi")]
+pub use handle_with_router::openapi::*;
+pub use handle_with_router::route_table::*;
+pub use handle_with_router::routes::*;
 pub use handle_with_router::routing_config::*;
+#[cfg(any(feature = "openapi", feature = "typescript"))]
+pub use handle_with_router::schema_registry::*;
 pub use handle_with_router::std::crud_specs::*;
+pub use handle_with_router::std::extractors::*;
 pub use handle_with_router::std::function_specs::*;
 pub use handle_with_router::std::validators::*;
+#[cfg(feature = "test-utils")]
+pub use handle_with_router::test_client::*;
+#[cfg(feature = "typescript")]
+pub use handle_with_router::typescript::*;
+pub use handle_with_websocket::connections::*;
+pub use handle_with_websocket::function_specs::*;
+pub use handle_with_websocket::routing_config::*;
+pub use shared::api_events::*;
+pub use shared::api_keys::*;
+pub use shared::auth_utils::RoleConfig;
+pub use shared::cors::*;
+#[cfg(any(feature = "local-server", feature = "test-utils"))]
+pub use shared::fake_claims::*;
+pub use shared::identity::*;
+#[cfg(feature = "jwt")]
+pub use shared::jwt::*;
 pub use shared::request_processing::*;
 pub use shared::response_building::*;
+#[cfg(any(feature = "client", feature = "test-utils"))]
+pub use shared::response_decoding::*;
 
 // ---------------------------------------------------------------------------
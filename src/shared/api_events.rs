@@ -0,0 +1,470 @@
+use std::collections::HashMap;
+
+use aws_lambda_events::{
+    alb::{AlbTargetGroupRequest, AlbTargetGroupResponse},
+    apigw::{
+        ApiGatewayProxyRequest, ApiGatewayProxyResponse, ApiGatewayRequestAuthorizer,
+        ApiGatewayV2httpRequest, ApiGatewayV2httpResponse,
+    },
+    encodings::Body,
+    http::{header::SET_COOKIE, HeaderMap, Method, StatusCode},
+    lambda_function_urls::{LambdaFunctionUrlRequest, LambdaFunctionUrlResponse},
+    query_map::QueryMap,
+};
+use base64::Engine as _;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+// Lambda event adapters.
+// --------------------------------------------------
+//
+// The router and request parsing utils work on REST API (payload format 1.0)
+// requests. Other event sources are normalized into that representation
+// before routing, and the response is converted back, so that the same
+// `FunctionSpec` / `CrudSpec` definitions can serve all of them.
+
+/// Lambda event types that can be handled by `RoutingConfig`.
+pub trait ApiEvent: DeserializeOwned + Send + 'static {
+    type Response: Serialize + Send + 'static;
+
+    fn into_proxy_request(self) -> ApiGatewayProxyRequest;
+
+    /// `request` is the normalized request the response was built for.
+    fn from_proxy_response(
+        response: ApiGatewayProxyResponse,
+        request: &ApiGatewayProxyRequest,
+    ) -> Self::Response;
+}
+
+/// REST API (payload format 1.0).
+impl ApiEvent for ApiGatewayProxyRequest {
+    type Response = ApiGatewayProxyResponse;
+
+    fn into_proxy_request(self) -> ApiGatewayProxyRequest {
+        self
+    }
+
+    fn from_proxy_response(
+        response: ApiGatewayProxyResponse,
+        _request: &ApiGatewayProxyRequest,
+    ) -> Self::Response {
+        response
+    }
+}
+
+/// HTTP API (payload format 2.0).
+///
+/// JWT authorizer claims are moved to where the REST API's Cognito authorizer
+/// puts them, Lambda authorizer context is flattened into the authorizer
+/// fields, and the IAM caller is copied into the request identity, so that
+/// `parse_request_metadata` and the `IdentityExtractor`s work unchanged.
+impl ApiEvent for ApiGatewayV2httpRequest {
+    type Response = ApiGatewayV2httpResponse;
+
+    fn into_proxy_request(self) -> ApiGatewayProxyRequest {
+        let mut request = ApiGatewayProxyRequest::default();
+        request.http_method = self.request_context.http.method.clone();
+        request.path_parameters = self.path_parameters;
+        if !request.path_parameters.contains_key("proxy") {
+            // Routes without a greedy path variable (e.g. "$default").
+            let path = strip_stage(
+                self.raw_path.as_deref().unwrap_or_default(),
+                self.request_context.stage.as_deref(),
+            );
+            request.path_parameters.insert(
+                "proxy".to_string(),
+                path.trim_start_matches('/').to_string(),
+            );
+        }
+        request.path = self.raw_path;
+        request.resource = self.route_key;
+        request.headers = with_cookie_header(self.headers, self.cookies);
+        request.query_string_parameters = self.query_string_parameters;
+        request.stage_variables = self.stage_variables;
+        request.body = self.body;
+        request.is_base64_encoded = self.is_base64_encoded;
+        request.request_context.request_id = self.request_context.request_id;
+        request.request_context.stage = self.request_context.stage;
+        let authorizer = self.request_context.authorizer;
+        if let Some(iam) = authorizer.as_ref().and_then(|a| a.iam.as_ref()) {
+            let identity = &mut request.request_context.identity;
+            identity.user_arn = iam.user_arn.clone();
+            identity.account_id = iam.account_id.clone();
+            identity.caller = iam.caller_id.clone();
+            identity.user = iam.user_id.clone();
+        }
+        request.request_context.authorizer =
+            authorizer.map(normalize_authorizer).unwrap_or_default();
+        request
+    }
+
+    fn from_proxy_response(
+        response: ApiGatewayProxyResponse,
+        _request: &ApiGatewayProxyRequest,
+    ) -> Self::Response {
+        let (headers, cookies) = split_cookies(response.headers);
+        let mut v2 = ApiGatewayV2httpResponse::default();
+        v2.status_code = response.status_code;
+        v2.headers = headers;
+        v2.cookies = cookies;
+        v2.body = response.body;
+        v2.is_base64_encoded = response.is_base64_encoded;
+        v2
+    }
+}
+
+/// Lambda Function URLs (payload format 2.0, without path parameters or
+/// authorizer claims).
+impl ApiEvent for LambdaFunctionUrlRequest {
+    type Response = LambdaFunctionUrlResponse;
+
+    fn into_proxy_request(self) -> ApiGatewayProxyRequest {
+        let mut request = ApiGatewayProxyRequest::default();
+        request.http_method = self
+            .request_context
+            .http
+            .method
+            .as_deref()
+            .and_then(|m| Method::from_bytes(m.as_bytes()).ok())
+            .unwrap_or_default();
+        let path = self.raw_path.unwrap_or_default();
+        request.path_parameters.insert(
+            "proxy".to_string(),
+            path.trim_start_matches('/').to_string(),
+        );
+        request.path = Some(path);
+        request.headers = with_cookie_header(self.headers, self.cookies);
+        request.query_string_parameters = self.query_string_parameters.into();
+        request.body = self.body;
+        request.is_base64_encoded = self.is_base64_encoded;
+        request.request_context.request_id = self.request_context.request_id;
+        request
+    }
+
+    fn from_proxy_response(
+        response: ApiGatewayProxyResponse,
+        _request: &ApiGatewayProxyRequest,
+    ) -> Self::Response {
+        let (headers, cookies) = split_cookies(response.headers);
+        let mut url = LambdaFunctionUrlResponse::default();
+        url.status_code = response.status_code;
+        url.headers = headers;
+        url.cookies = cookies;
+        match response.body {
+            Some(Body::Text(text)) => url.body = Some(text),
+            Some(Body::Binary(bytes)) => {
+                url.body = Some(base64::engine::general_purpose::STANDARD.encode(bytes));
+                url.is_base64_encoded = true;
+            }
+            _ => {}
+        }
+        url
+    }
+}
+
+/// Application Load Balancer target group.
+///
+/// ALB does not decode the path or query parameters, so they are
+/// percent-decoded here (the raw path is kept in `path`, as received). If
+/// multi-value headers are enabled on the target group, the request only
+/// contains multi-value headers and query parameters, and the response must
+/// use multi-value headers too.
+impl ApiEvent for AlbTargetGroupRequest {
+    type Response = AlbTargetGroupResponse;
+
+    fn into_proxy_request(self) -> ApiGatewayProxyRequest {
+        let mut request = ApiGatewayProxyRequest::default();
+        request.http_method = self.http_method;
+        let path = self.path.unwrap_or_default();
+        request.path_parameters.insert(
+            "proxy".to_string(),
+            percent_decode_path(path.trim_start_matches('/')),
+        );
+        request.path = Some(path);
+        if self.multi_value_headers.is_empty() {
+            request.headers = self.headers;
+            request.query_string_parameters = decode_query(self.query_string_parameters);
+        } else {
+            request.headers = self.multi_value_headers.clone();
+            request.multi_value_headers = self.multi_value_headers;
+            let query = decode_query(self.multi_value_query_string_parameters);
+            request.query_string_parameters = query.clone();
+            request.multi_value_query_string_parameters = query;
+        }
+        request.body = self.body;
+        request.is_base64_encoded = self.is_base64_encoded;
+        request
+    }
+
+    fn from_proxy_response(
+        response: ApiGatewayProxyResponse,
+        request: &ApiGatewayProxyRequest,
+    ) -> Self::Response {
+        let mut alb = AlbTargetGroupResponse::default();
+        alb.status_code = response.status_code;
+        alb.status_description = Some(format!(
+            "{} {}",
+            response.status_code,
+            u16::try_from(response.status_code)
+                .ok()
+                .and_then(|s| StatusCode::from_u16(s).ok())
+                .and_then(|s| s.canonical_reason())
+                .unwrap_or_default()
+        ));
+        if request.multi_value_headers.is_empty() {
+            alb.headers = response.headers;
+        } else {
+            alb.multi_value_headers = response.headers;
+            alb.multi_value_headers.extend(response.multi_value_headers);
+        }
+        alb.body = response.body;
+        alb.is_base64_encoded = response.is_base64_encoded;
+        alb
+    }
+}
+
+// Helper functions.
+// --------------------------------------------------
+
+/// HTTP API JWT authorizers expose claims under `jwt.claims`, with array
+/// claims flattened into strings like "[admin editor]". REST API Cognito
+/// authorizers expose them under the "claims" field, with groups separated by
+/// commas.
+fn normalize_authorizer(
+    mut authorizer: ApiGatewayRequestAuthorizer,
+) -> ApiGatewayRequestAuthorizer {
+    if let Some(jwt) = authorizer.jwt.take() {
+        let claims = jwt.claims.into_iter().map(|(k, v)| {
+            let v = if k == "cognito:groups" {
+                v.trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(",")
+            } else {
+                v
+            };
+            (k, Value::String(v))
+        });
+        authorizer
+            .fields
+            .insert("claims".to_string(), Value::Object(claims.collect()));
+    }
+    if let Some(Value::Object(context)) = authorizer.fields.remove("lambda") {
+        authorizer.fields.extend(context);
+    }
+    authorizer
+}
+
+/// With non-default stages, HTTP API raw paths are prefixed by the stage name.
+fn strip_stage<'a>(path: &'a str, stage: Option<&str>) -> &'a str {
+    match stage {
+        Some(stage) if stage != "$default" => path
+            .strip_prefix('/')
+            .and_then(|p| p.strip_prefix(stage))
+            .filter(|p| p.is_empty() || p.starts_with('/'))
+            .unwrap_or(path),
+        _ => path,
+    }
+}
+
+fn decode_query(query: QueryMap) -> QueryMap {
+    let mut decoded: HashMap<String, Vec<String>> = HashMap::new();
+    for (key, value) in query.iter() {
+        decoded
+            .entry(percent_decode(key))
+            .or_default()
+            .push(percent_decode(value));
+    }
+    decoded.into()
+}
+
+/// Decodes "%XX" escapes and '+' (as used in query strings). Invalid escapes
+/// are kept as-is.
+pub(crate) fn percent_decode(s: &str) -> String {
+    decode_escapes(s, true)
+}
+
+/// Like `percent_decode`, but keeps '+', which is not an escape in paths.
+fn percent_decode_path(s: &str) -> String {
+    decode_escapes(s, false)
+}
+
+fn decode_escapes(s: &str, plus_as_space: bool) -> String {
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' if plus_as_space => out.push(b' '),
+            b'%' => match (
+                bytes.get(i + 1).and_then(|b| hex(*b)),
+                bytes.get(i + 2).and_then(|b| hex(*b)),
+            ) {
+                (Some(hi), Some(lo)) => {
+                    out.push(hi << 4 | lo);
+                    i += 2;
+                }
+                _ => out.push(b'%'),
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Payload format 2.0 passes cookies separately from the headers.
+fn with_cookie_header(mut headers: HeaderMap, cookies: Option<Vec<String>>) -> HeaderMap {
+    if let Some(cookies) = cookies.filter(|c| !c.is_empty()) {
+        if let Ok(v) = cookies.join("; ").parse() {
+            headers.insert("cookie", v);
+        }
+    }
+    headers
+}
+
+/// Payload format 2.0 expects response cookies in a separate field.
+fn split_cookies(mut headers: HeaderMap) -> (HeaderMap, Vec<String>) {
+    let cookies = headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok().map(str::to_owned))
+        .collect();
+    headers.remove(SET_COOKIE);
+    (headers, cookies)
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::{
+        identity::{IamIdentity, IdentityExtractor},
+        request_processing::parse_request_metadata,
+    };
+    use aws_lambda_events::apigw::{
+        ApiGatewayRequestAuthorizerIamDescription, ApiGatewayRequestAuthorizerJwtDescription,
+    };
+
+    #[test]
+    fn test_v2_request_claims() {
+        let mut jwt = ApiGatewayRequestAuthorizerJwtDescription::default();
+        jwt.claims = [
+            ("cognito:username", "FakeUsername"),
+            ("cognito:groups", "[admin editor]"),
+            ("sub", "FakeUserSub"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let mut authorizer = ApiGatewayRequestAuthorizer::default();
+        authorizer.jwt = Some(jwt);
+        let mut event = ApiGatewayV2httpRequest::default();
+        event.request_context.authorizer = Some(authorizer);
+
+        let metadata = parse_request_metadata(&event.into_proxy_request()).unwrap();
+        assert!(metadata.is_authenticated);
+        assert!(metadata.is_admin);
+        assert_eq!(metadata.user_sub.as_deref(), Some("FakeUserSub"));
+    }
+
+    #[tokio::test]
+    async fn test_v2_request_iam() {
+        let mut iam = ApiGatewayRequestAuthorizerIamDescription::default();
+        iam.user_arn = Some("arn:aws:iam::123456789012:user/ci".to_string());
+        iam.account_id = Some("123456789012".to_string());
+        let mut authorizer = ApiGatewayRequestAuthorizer::default();
+        authorizer.iam = Some(iam);
+        let mut event = ApiGatewayV2httpRequest::default();
+        event.request_context.authorizer = Some(authorizer);
+
+        let request = event.into_proxy_request();
+        assert_eq!(
+            request.request_context.identity.account_id.as_deref(),
+            Some("123456789012")
+        );
+        let identity = IamIdentity.extract(&request).await.unwrap().unwrap();
+        assert_eq!(identity.sub, "arn:aws:iam::123456789012:user/ci");
+    }
+
+    #[test]
+    fn test_v2_request_path() {
+        let mut event = ApiGatewayV2httpRequest::default();
+        event.raw_path = Some("/prod/users/123".to_string());
+        event.request_context.stage = Some("prod".to_string());
+        event.request_context.http.method = Method::GET;
+        let request = event.into_proxy_request();
+        assert_eq!(request.http_method, Method::GET);
+        assert_eq!(request.path_parameters["proxy"], "users/123");
+    }
+
+    #[test]
+    fn test_function_url_round_trip() {
+        let mut event = LambdaFunctionUrlRequest::default();
+        event.raw_path = Some("/users".to_string());
+        event.request_context.http.method = Some("PUT".to_string());
+        let request = event.into_proxy_request();
+        assert_eq!(request.http_method, Method::PUT);
+        assert_eq!(request.path_parameters["proxy"], "users");
+
+        let mut response = ApiGatewayProxyResponse::default();
+        response.status_code = 200;
+        response.body = Some(Body::Binary(vec![1, 2, 3]));
+        response.headers.insert(SET_COOKIE, "a=b".parse().unwrap());
+        let url = LambdaFunctionUrlRequest::from_proxy_response(response, &request);
+        assert_eq!(url.body.as_deref(), Some("AQID"));
+        assert!(url.is_base64_encoded);
+        assert_eq!(url.cookies, vec!["a=b".to_string()]);
+        assert!(url.headers.get(SET_COOKIE).is_none());
+    }
+
+    #[test]
+    fn test_alb_multi_value_round_trip() {
+        let mut event = AlbTargetGroupRequest::default();
+        event.http_method = Method::GET;
+        event.path = Some("/users/John%20Doe+Jr".to_string());
+        event
+            .multi_value_headers
+            .append("x-tag", "a".parse().unwrap());
+        event
+            .multi_value_headers
+            .append("x-tag", "b".parse().unwrap());
+        event.multi_value_query_string_parameters = HashMap::from([(
+            "name".to_string(),
+            vec!["John%20Doe".to_string(), "a+b".to_string()],
+        )])
+        .into();
+        let request = event.into_proxy_request();
+        assert_eq!(request.path_parameters["proxy"], "users/John Doe+Jr");
+        assert_eq!(request.headers.get_all("x-tag").iter().count(), 2);
+        assert_eq!(
+            request.query_string_parameters.all("name"),
+            Some(vec!["John Doe", "a b"])
+        );
+
+        let mut response = ApiGatewayProxyResponse::default();
+        response.status_code = 404;
+        response
+            .headers
+            .insert("content-type", "text/plain".parse().unwrap());
+        let alb = AlbTargetGroupRequest::from_proxy_response(response, &request);
+        assert_eq!(alb.status_description.as_deref(), Some("404 Not Found"));
+        assert!(alb.headers.is_empty());
+        assert_eq!(
+            alb.multi_value_headers.get("content-type").unwrap(),
+            "text/plain"
+        );
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%2Fb%3d"), "a/b=");
+        assert_eq!(percent_decode("%E2%9C%93"), "\u{2713}");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+}
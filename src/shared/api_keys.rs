@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use aws_lambda_events::apigw::ApiGatewayProxyRequest;
+use aws_sdk_dynamodb::{types::AttributeValue, Client};
+use fractic_server_error::ServerError;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::errors::{ApiKeyStoreError, UnauthorizedError};
+
+// API key authentication.
+// --------------------------------------------------
+//
+// Partners calling the API with static keys send them in the `X-Api-Key`
+// header. If an `ApiKeyStore` is configured on the `RoutingConfig`, the key
+// is looked up by its SHA-256 hash (keys themselves are never stored), and the
+// key's principal and permissions are exposed as `RequestMetadata::api_key`.
+// Routes accept API keys through `Access::ApiKey` or
+// `Access::ApiKeyPermission`.
+//
+// Requests with an unknown key are rejected, rather than treated as
+// unauthenticated.
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Caller authenticated by an API key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyPrincipal {
+    /// Who the key was issued to (e.g. "partner-acme").
+    pub principal: String,
+    /// Permissions granted to the key (e.g. "orders:read").
+    pub permissions: Vec<String>,
+}
+
+impl ApiKeyPrincipal {
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|p| p == permission)
+    }
+}
+
+/// API key as stored, identified by the hash of its value (see
+/// `hash_api_key`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredApiKey {
+    pub key_hash: String,
+    pub principal: String,
+    pub permissions: Vec<String>,
+}
+
+#[async_trait]
+pub trait ApiKeyStore: Send + Sync {
+    /// Looks up a key by its hash (see `hash_api_key`).
+    async fn find(&self, key_hash: &str) -> Result<Option<StoredApiKey>, ServerError>;
+}
+
+/// Hex-encoded SHA-256 hash of a key, as stored in an `ApiKeyStore`.
+pub fn hash_api_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Reads the `X-Api-Key` header, if any, and looks the key up in the store.
+pub(crate) async fn authenticate_api_key(
+    store: &dyn ApiKeyStore,
+    request: &ApiGatewayProxyRequest,
+) -> Result<Option<ApiKeyPrincipal>, ServerError> {
+    let Some(key) = request
+        .headers
+        .get(API_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return Ok(None);
+    };
+    let key_hash = hash_api_key(key);
+    match store.find(&key_hash).await? {
+        // Compared again in constant time, in case the store's lookup is
+        // looser than an exact match.
+        Some(stored) if hashes_match(&stored.key_hash, &key_hash) => Ok(Some(ApiKeyPrincipal {
+            principal: stored.principal,
+            permissions: stored.permissions,
+        })),
+        _ => Err(UnauthorizedError::with_debug(
+            &"unknown API key".to_string(),
+        )),
+    }
+}
+
+fn hashes_match(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+// Implementations.
+// --------------------------------------------------
+
+/// `ApiKeyStore` backed by a DynamoDB table, keyed by the "key_hash" string
+/// attribute. Items have a "principal" string attribute and an optional
+/// "permissions" string set attribute.
+pub struct DynamoApiKeyStore {
+    client: Client,
+    table: String,
+}
+
+impl DynamoApiKeyStore {
+    pub fn new(client: Client, table: &str) -> Self {
+        Self {
+            client,
+            table: table.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for DynamoApiKeyStore {
+    async fn find(&self, key_hash: &str) -> Result<Option<StoredApiKey>, ServerError> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.table)
+            .key("key_hash", AttributeValue::S(key_hash.to_string()))
+            .send()
+            .await
+            .map_err(|e| ApiKeyStoreError::with_debug(&self.table, &e))?;
+        let Some(item) = output.item() else {
+            return Ok(None);
+        };
+        let string = |name: &str| {
+            item.get(name)
+                .and_then(|v| v.as_s().ok())
+                .cloned()
+                .ok_or_else(|| {
+                    ApiKeyStoreError::with_debug(
+                        &self.table,
+                        &format!("item has no '{name}' string attribute"),
+                    )
+                })
+        };
+        Ok(Some(StoredApiKey {
+            key_hash: string("key_hash")?,
+            principal: string("principal")?,
+            permissions: item
+                .get("permissions")
+                .and_then(|v| v.as_ss().ok())
+                .cloned()
+                .unwrap_or_default(),
+        }))
+    }
+}
+
+/// In-memory `ApiKeyStore`, for tests and local development (or for a small,
+/// fixed set of keys configured at cold start).
+#[derive(Default)]
+pub struct InMemoryApiKeyStore {
+    keys: HashMap<String, StoredApiKey>,
+}
+
+impl InMemoryApiKeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a key by its hash (see `hash_api_key`).
+    pub fn with_key_hash(mut self, key_hash: &str, principal: &str, permissions: &[&str]) -> Self {
+        self.keys.insert(
+            key_hash.to_string(),
+            StoredApiKey {
+                key_hash: key_hash.to_string(),
+                principal: principal.to_string(),
+                permissions: permissions.iter().map(|p| p.to_string()).collect(),
+            },
+        );
+        self
+    }
+
+    /// Adds a key by its value. Prefer `with_key_hash` outside of tests, so
+    /// that keys are not kept in the source or configuration.
+    pub fn with_key(self, key: &str, principal: &str, permissions: &[&str]) -> Self {
+        self.with_key_hash(&hash_api_key(key), principal, permissions)
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for InMemoryApiKeyStore {
+    async fn find(&self, key_hash: &str) -> Result<Option<StoredApiKey>, ServerError> {
+        // All keys are compared, so that the time taken does not depend on
+        // which (or whether a) key matches.
+        let mut found = None;
+        for stored in self.keys.values() {
+            if hashes_match(&stored.key_hash, key_hash) {
+                found = Some(stored.clone());
+            }
+        }
+        Ok(found)
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_lambda_events::http::HeaderValue;
+
+    fn request(key: Option<&str>) -> ApiGatewayProxyRequest {
+        let mut request = ApiGatewayProxyRequest::default();
+        if let Some(key) = key {
+            request
+                .headers
+                .insert(API_KEY_HEADER, HeaderValue::from_str(key).unwrap());
+        }
+        request
+    }
+
+    #[test]
+    fn test_hash_api_key() {
+        assert_eq!(
+            hash_api_key("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_api_key() {
+        let store = InMemoryApiKeyStore::new()
+            .with_key("secret-1", "partner-1", &["orders:read"])
+            .with_key_hash(&hash_api_key("secret-2"), "partner-2", &[]);
+
+        let principal = authenticate_api_key(&store, &request(Some("secret-1")))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(principal.principal, "partner-1");
+        assert!(principal.has_permission("orders:read"));
+        assert!(!principal.has_permission("orders:write"));
+
+        let principal = authenticate_api_key(&store, &request(Some("secret-2")))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(principal.principal, "partner-2");
+
+        assert_eq!(
+            authenticate_api_key(&store, &request(None)).await.unwrap(),
+            None
+        );
+        assert!(authenticate_api_key(&store, &request(Some("wrong")))
+            .await
+            .is_err());
+    }
+}
@@ -1,35 +1,71 @@
 use aws_lambda_events::apigw::ApiGatewayProxyRequest;
 use fractic_server_error::{CriticalError, ServerError};
+use serde_json::Value;
 
 use crate::errors::UnauthorizedError;
 
 // API Gateway authentication utils.
 // --------------------------------------------------
 
+/// Tokens issued to users carry a username. Client-credentials tokens (issued
+/// to machine clients) are not user tokens (see `get_client_id`).
 pub fn is_authenticated(req: &ApiGatewayProxyRequest) -> bool {
     match req.request_context.authorizer.fields.get("claims") {
-        Some(claims) => match claims.get("cognito:username") {
-            Some(_) => true,
-            None => false,
-        },
+        Some(claims) => claims.get("cognito:username").is_some(),
         None => false,
     }
 }
 
-// This function assumes a UserPool group named "admin".
-pub fn is_admin(req: &ApiGatewayProxyRequest) -> bool {
-    match req.request_context.authorizer.fields.get("claims") {
-        Some(claims) => match claims.get("cognito:groups") {
-            Some(groups_val) => match groups_val.as_str() {
-                Some(groups_str) => groups_str.split(',').any(|g| g == "admin"),
-                None => false,
-            },
-            None => false,
-        },
-        None => false,
+/// Client ID of a client-credentials token, which carries a "client_id" claim
+/// but no username.
+pub fn get_client_id(req: &ApiGatewayProxyRequest) -> Option<String> {
+    if is_authenticated(req) {
+        return None;
+    }
+    req.request_context
+        .authorizer
+        .fields
+        .get("claims")
+        .and_then(|claims| claims.get("client_id"))
+        .and_then(Value::as_str)
+        .map(String::from)
+}
+
+/// Maps user pool groups to roles. Configured through `RoutingConfig::roles`.
+#[derive(Debug, Clone)]
+pub struct RoleConfig {
+    /// Members of any of these groups are admins (see `Access::Admin`).
+    /// Defaults to a single group named "admin".
+    pub admin_groups: Vec<String>,
+}
+
+impl Default for RoleConfig {
+    fn default() -> Self {
+        Self {
+            admin_groups: vec!["admin".to_string()],
+        }
+    }
+}
+
+impl RoleConfig {
+    pub fn is_admin(&self, groups: &[String]) -> bool {
+        groups.iter().any(|g| self.admin_groups.contains(g))
     }
 }
 
+/// User pool groups of the authenticated user, from the "cognito:groups"
+/// claim. REST API authorizers pass it as a comma-separated string, HTTP API
+/// authorizers as a bracketed, space-separated string (e.g. "[admin editor]").
+pub fn get_groups(req: &ApiGatewayProxyRequest) -> Vec<String> {
+    claim_list(req, "cognito:groups")
+}
+
+/// OAuth2 scopes of the access token (e.g. "orders/read"), from the
+/// space-separated "scope" claim.
+pub fn get_scopes(req: &ApiGatewayProxyRequest) -> Vec<String> {
+    claim_list(req, "scope")
+}
+
 pub fn get_sub_of_authenticated_user(req: &ApiGatewayProxyRequest) -> Result<String, ServerError> {
     match req.request_context.authorizer.fields.get("claims") {
         Some(claims) => match claims.get("sub") {
@@ -47,6 +83,38 @@ pub fn get_sub_of_authenticated_user(req: &ApiGatewayProxyRequest) -> Result<Str
     }
 }
 
+// Helper functions.
+// --------------------------------------------------
+
+fn claim_list(req: &ApiGatewayProxyRequest, name: &str) -> Vec<String> {
+    req.request_context
+        .authorizer
+        .fields
+        .get("claims")
+        .and_then(|claims| claims.get(name))
+        .map(parse_list)
+        .unwrap_or_default()
+}
+
+/// Parses a list passed either as a JSON array or as a (possibly bracketed)
+/// string separated by commas or spaces.
+pub(crate) fn parse_list(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(list) => list
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split([',', ' '])
+            .filter(|item| !item.is_empty())
+            .map(String::from)
+            .collect(),
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|item| item.as_str().map(String::from))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
 // Tests.
 // --------------------------------------------------
 
@@ -74,6 +142,21 @@ mod tests {
         request
     }
 
+    fn create_client_credentials_request() -> ApiGatewayProxyRequest {
+        let mut request = ApiGatewayProxyRequest::default();
+        request.request_context.authorizer.fields = [(
+            "claims".into(),
+            serde_json::json!({
+                "client_id": "FakeClientId",
+                "sub": "FakeClientId",
+                "token_use": "access",
+                "scope": "orders/read orders/write"
+            }),
+        )]
+        .into();
+        request
+    }
+
     fn create_unauthenticated_request() -> ApiGatewayProxyRequest {
         ApiGatewayProxyRequest::default()
     }
@@ -84,9 +167,54 @@ mod tests {
         let unauthenticated_request = create_unauthenticated_request();
 
         assert!(is_authenticated(&authenticated_request));
+        assert!(!is_authenticated(&create_client_credentials_request()));
         assert!(!is_authenticated(&unauthenticated_request));
     }
 
+    #[test]
+    fn test_get_client_id() {
+        assert_eq!(
+            get_client_id(&create_client_credentials_request()).as_deref(),
+            Some("FakeClientId")
+        );
+        assert_eq!(get_client_id(&create_authenticated_request()), None);
+        assert_eq!(get_client_id(&create_unauthenticated_request()), None);
+    }
+
+    #[test]
+    fn test_get_scopes() {
+        assert_eq!(
+            get_scopes(&create_client_credentials_request()),
+            vec!["orders/read", "orders/write"]
+        );
+        assert!(get_scopes(&create_authenticated_request()).is_empty());
+    }
+
+    #[test]
+    fn test_get_groups() {
+        let mut request = create_authenticated_request();
+        assert!(get_groups(&request).is_empty());
+
+        for groups in [
+            serde_json::json!("editor,admin"),
+            serde_json::json!("[editor admin]"),
+            serde_json::json!(["editor", "admin"]),
+        ] {
+            request.request_context.authorizer.fields.insert(
+                "claims".into(),
+                serde_json::json!({ "cognito:groups": groups }),
+            );
+            assert_eq!(get_groups(&request), vec!["editor", "admin"]);
+            assert!(RoleConfig::default().is_admin(&get_groups(&request)));
+        }
+
+        let roles = RoleConfig {
+            admin_groups: vec!["superusers".to_string()],
+        };
+        assert!(!roles.is_admin(&get_groups(&request)));
+        assert!(roles.is_admin(&["superusers".to_string()]));
+    }
+
     #[test]
     fn test_get_sub_of_authenticated_user() {
         let authenticated_request = create_authenticated_request();
@@ -0,0 +1,375 @@
+use std::{env, sync::OnceLock};
+
+use aws_lambda_events::{
+    apigw::ApiGatewayProxyResponse,
+    http::{
+        header::{
+            ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+            ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE,
+            VARY,
+        },
+        HeaderMap, HeaderValue,
+    },
+};
+use fractic_server_error::ServerError;
+use regex::Regex;
+
+use crate::errors::InvalidCorsConfig;
+
+// CORS utils.
+// --------------------------------------------------
+//
+// Most modern browsers will not allow a web client to read an API response
+// unless the relevant CORS headers are set, and will send a preflight OPTIONS
+// request before any non-simple request. The policy configured here is
+// applied to all responses, and preflight requests are answered directly by
+// the router.
+//
+// NOTE: The allowed origins do not prevent the API from responding to
+// requests from other origins; they only instruct browsers to block the
+// response from being read by front-end code.
+
+/// Origin allowed by a `CorsPolicy`.
+#[derive(Debug, Clone)]
+pub enum AllowedOrigin {
+    /// Any origin. Answered with "*" rather than by echoing the origin, so
+    /// it cannot be combined with `allow_credentials`.
+    Any,
+    /// Exact match (e.g. "https://fractic.io").
+    Exact(String),
+    /// Pattern where each '*' matches any sequence of characters (e.g.
+    /// "https://*.fractic.io").
+    Wildcard(String),
+    /// Regular expression matched against the origin (use '^' and '$' to
+    /// anchor it).
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+    pub allowed_origins: Vec<AllowedOrigin>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    /// How long (in seconds) browsers may cache preflight responses.
+    pub max_age: Option<u64>,
+    pub allow_credentials: bool,
+}
+
+impl Default for CorsPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![AllowedOrigin::Exact("https://fractic.io".into())],
+            allowed_methods: ["GET", "POST", "PUT", "DELETE"].map(String::from).to_vec(),
+            allowed_headers: [
+                "Content-Type",
+                "X-Amz-Date",
+                "Authorization",
+                "X-Api-Key",
+                "X-Amz-Security-Token",
+                "X-Amz-User-Agent",
+                "X-Payload-Format",
+                "X-Payload-Encoding",
+                "X-Envelope-Version",
+            ]
+            .map(String::from)
+            .to_vec(),
+            max_age: Some(600),
+            allow_credentials: true,
+        }
+    }
+}
+
+impl AllowedOrigin {
+    /// Parses "*" as `Any`, "regex:<expr>" as `Regex`, patterns containing '*'
+    /// as `Wildcard`, and anything else as `Exact`.
+    pub fn parse(s: &str) -> Result<Self, ServerError> {
+        let s = s.trim();
+        if s == "*" {
+            Ok(AllowedOrigin::Any)
+        } else if let Some(expr) = s.strip_prefix("regex:") {
+            Regex::new(expr)
+                .map(AllowedOrigin::Regex)
+                .map_err(|e| InvalidCorsConfig::with_debug(&format!("invalid regex '{expr}'"), &e))
+        } else if s.contains('*') {
+            Ok(AllowedOrigin::Wildcard(s.to_string()))
+        } else {
+            Ok(AllowedOrigin::Exact(s.to_string()))
+        }
+    }
+
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            AllowedOrigin::Any => true,
+            AllowedOrigin::Exact(o) => o == origin,
+            AllowedOrigin::Wildcard(pattern) => wildcard_match(pattern, origin),
+            AllowedOrigin::Regex(r) => r.is_match(origin),
+        }
+    }
+}
+
+impl CorsPolicy {
+    /// Builds a policy from the following environment variables, if
+    /// `CORS_ALLOWED_ORIGINS` is set. Unset variables fall back to the
+    /// defaults.
+    ///
+    /// - `CORS_ALLOWED_ORIGINS`: comma-separated (see `AllowedOrigin::parse`).
+    /// - `CORS_ALLOWED_METHODS`: comma-separated.
+    /// - `CORS_ALLOWED_HEADERS`: comma-separated.
+    /// - `CORS_MAX_AGE`: seconds.
+    /// - `CORS_ALLOW_CREDENTIALS`: "true" or "false". Must be "false" if any
+    ///   origin is allowed ("*").
+    pub fn from_env() -> Result<Option<Self>, ServerError> {
+        let origins = match env::var("CORS_ALLOWED_ORIGINS") {
+            Ok(v) => v,
+            Err(_) => return Ok(None),
+        };
+        let mut policy = Self {
+            allowed_origins: split_list(&origins)
+                .map(AllowedOrigin::parse)
+                .collect::<Result<_, _>>()?,
+            ..Default::default()
+        };
+        if let Ok(v) = env::var("CORS_ALLOWED_METHODS") {
+            policy.allowed_methods = split_list(&v).map(String::from).collect();
+        }
+        if let Ok(v) = env::var("CORS_ALLOWED_HEADERS") {
+            policy.allowed_headers = split_list(&v).map(String::from).collect();
+        }
+        if let Ok(v) = env::var("CORS_MAX_AGE") {
+            policy.max_age =
+                Some(v.trim().parse().map_err(|e| {
+                    InvalidCorsConfig::with_debug("CORS_MAX_AGE is not a number", &e)
+                })?);
+        }
+        if let Ok(v) = env::var("CORS_ALLOW_CREDENTIALS") {
+            policy.allow_credentials = v.trim().eq_ignore_ascii_case("true");
+        }
+        if policy.allow_credentials && policy.allows_any_origin() {
+            return Err(InvalidCorsConfig::new(
+                "'*' origin requires CORS_ALLOW_CREDENTIALS=false",
+            ));
+        }
+        Ok(Some(policy))
+    }
+
+    /// Policy used when none is configured on the `RoutingConfig`, and for
+    /// responses built outside of the router: read from the environment
+    /// (see `from_env`), falling back to the default policy.
+    pub fn global() -> &'static CorsPolicy {
+        static GLOBAL: OnceLock<CorsPolicy> = OnceLock::new();
+        GLOBAL.get_or_init(|| match Self::from_env() {
+            Ok(policy) => policy.unwrap_or_default(),
+            Err(e) => {
+                eprintln!("ERROR\n{}", e);
+                Self::default()
+            }
+        })
+    }
+
+    pub fn is_origin_allowed(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|o| o.matches(origin))
+    }
+
+    /// Sets the CORS response headers for a request with the given `Origin`
+    /// header, replacing any previously set. Allowed origins are echoed back,
+    /// except those only allowed through `AllowedOrigin::Any`, which get "*"
+    /// (or nothing, if credentials are allowed). Requests without an `Origin`
+    /// header (i.e. not from a browser) get the single configured origin, if
+    /// there is exactly one.
+    pub fn apply(&self, origin: Option<&str>, headers: &mut HeaderMap) {
+        headers.remove(ACCESS_CONTROL_ALLOW_ORIGIN);
+        headers.remove(ACCESS_CONTROL_ALLOW_CREDENTIALS);
+        headers.remove(ACCESS_CONTROL_ALLOW_HEADERS);
+        headers.remove(ACCESS_CONTROL_ALLOW_METHODS);
+        if origin.is_some() {
+            headers.append(VARY, HeaderValue::from_static("Origin"));
+        }
+        if let Some(allow_origin) = self.allow_origin_value(origin) {
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        }
+        if let Ok(v) = HeaderValue::from_str(&self.allowed_headers.join(",")) {
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, v);
+        }
+        if let Ok(v) = HeaderValue::from_str(&self.allowed_methods.join(", ")) {
+            headers.insert(ACCESS_CONTROL_ALLOW_METHODS, v);
+        }
+        if self.allow_credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+
+    /// Response to a preflight OPTIONS request.
+    pub fn preflight_response(&self, origin: Option<&str>) -> ApiGatewayProxyResponse {
+        let mut response = ApiGatewayProxyResponse::default();
+        response.status_code = 204;
+        self.apply(origin, &mut response.headers);
+        if let Some(max_age) = self.max_age {
+            response
+                .headers
+                .insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from(max_age));
+        }
+        response
+    }
+
+    fn allows_any_origin(&self) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|o| matches!(o, AllowedOrigin::Any))
+    }
+
+    fn allow_origin_value(&self, origin: Option<&str>) -> Option<HeaderValue> {
+        let any_origin = || (!self.allow_credentials).then(|| HeaderValue::from_static("*"));
+        match origin {
+            Some(o) => {
+                let listed = self
+                    .allowed_origins
+                    .iter()
+                    .any(|a| !matches!(a, AllowedOrigin::Any) && a.matches(o));
+                if listed {
+                    HeaderValue::from_str(o).ok()
+                } else if self.allows_any_origin() {
+                    // Echoing arbitrary origins together with
+                    // "Access-Control-Allow-Credentials" would let any site
+                    // make credentialed requests.
+                    any_origin()
+                } else {
+                    None
+                }
+            }
+            None => match self.allowed_origins.as_slice() {
+                [AllowedOrigin::Exact(o)] => HeaderValue::from_str(o).ok(),
+                [AllowedOrigin::Any] => any_origin(),
+                _ => None,
+            },
+        }
+    }
+}
+
+// Helper functions.
+// --------------------------------------------------
+
+fn split_list(s: &str) -> impl Iterator<Item = &str> {
+    s.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
+
+fn wildcard_match(pattern: &str, s: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // Pattern always contains at least one part.
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = s.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No '*' in pattern.
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(origins: &[&str]) -> CorsPolicy {
+        CorsPolicy {
+            allowed_origins: origins
+                .iter()
+                .map(|o| AllowedOrigin::parse(o).unwrap())
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_origin_matching() {
+        let p = policy(&[
+            "https://fractic.io",
+            "https://*.example.com",
+            "regex:^http://localhost:\\d+$",
+        ]);
+        assert!(p.is_origin_allowed("https://fractic.io"));
+        assert!(!p.is_origin_allowed("https://fractic.io.evil.com"));
+        assert!(p.is_origin_allowed("https://app.example.com"));
+        assert!(!p.is_origin_allowed("https://example.com"));
+        assert!(p.is_origin_allowed("http://localhost:3000"));
+        assert!(!p.is_origin_allowed("http://localhost:3000.evil.com"));
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("a*c", "abc"));
+        assert!(wildcard_match("a*c", "ac"));
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("a*b*c", "aXbYc"));
+        assert!(!wildcard_match("a*b*c", "aXcYb"));
+        assert!(!wildcard_match("a*c", "ab"));
+    }
+
+    #[test]
+    fn test_apply_echoes_allowed_origin() {
+        let p = policy(&["https://*.example.com"]);
+        let mut headers = HeaderMap::new();
+        p.apply(Some("https://app.example.com"), &mut headers);
+        assert_eq!(
+            headers.get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://app.example.com"
+        );
+        assert_eq!(headers.get(VARY).unwrap(), "Origin");
+
+        let mut headers = HeaderMap::new();
+        p.apply(Some("https://other.com"), &mut headers);
+        assert!(headers.get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[test]
+    fn test_apply_without_origin() {
+        let mut headers = HeaderMap::new();
+        CorsPolicy::default().apply(None, &mut headers);
+        assert_eq!(
+            headers.get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://fractic.io"
+        );
+    }
+
+    #[test]
+    fn test_preflight_response() {
+        let response = policy(&["*"]).preflight_response(Some("https://any.com"));
+        assert_eq!(response.status_code, 204);
+        assert!(response.headers.get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+        assert_eq!(response.headers.get(ACCESS_CONTROL_MAX_AGE).unwrap(), "600");
+
+        let without_credentials = CorsPolicy {
+            allow_credentials: false,
+            ..policy(&["*"])
+        };
+        let response = without_credentials.preflight_response(Some("https://any.com"));
+        assert_eq!(
+            response.headers.get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "*"
+        );
+        assert!(response
+            .headers
+            .get(ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            .is_none());
+
+        let response =
+            policy(&["*", "https://fractic.io"]).preflight_response(Some("https://fractic.io"));
+        assert_eq!(
+            response.headers.get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://fractic.io"
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use aws_lambda_events::apigw::ApiGatewayRequestAuthorizer;
+use serde_json::Value;
+
+// Fake identities for local development and tests.
+// --------------------------------------------------
+
+/// Cognito claims of the simulated user (or machine client).
+#[derive(Debug, Clone, Default)]
+pub struct FakeClaims {
+    pub sub: String,
+    /// Empty for machine clients, which are identified by their client ID
+    /// instead.
+    pub username: String,
+    pub groups: Vec<String>,
+    /// OAuth2 scopes of the access token.
+    pub scopes: Vec<String>,
+    /// Any other claims (e.g. "email").
+    pub extra: HashMap<String, String>,
+}
+
+impl FakeClaims {
+    pub fn user(sub: &str) -> Self {
+        Self {
+            sub: sub.to_string(),
+            username: sub.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn admin(sub: &str) -> Self {
+        Self {
+            groups: vec!["admin".to_string()],
+            ..Self::user(sub)
+        }
+    }
+
+    /// Machine client authenticated through the client-credentials flow.
+    pub fn client(client_id: &str, scopes: &[&str]) -> Self {
+        Self {
+            sub: client_id.to_string(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Authorizer as populated by a REST API Cognito authorizer.
+    pub(crate) fn authorizer(&self) -> ApiGatewayRequestAuthorizer {
+        let mut claims: HashMap<String, Value> = self
+            .extra
+            .iter()
+            .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+            .collect();
+        claims.insert("sub".to_string(), Value::String(self.sub.clone()));
+        if self.username.is_empty() {
+            claims.insert("client_id".to_string(), Value::String(self.sub.clone()));
+        } else {
+            claims.insert(
+                "cognito:username".to_string(),
+                Value::String(self.username.clone()),
+            );
+        }
+        if !self.groups.is_empty() {
+            claims.insert(
+                "cognito:groups".to_string(),
+                Value::String(self.groups.join(",")),
+            );
+        }
+        if !self.scopes.is_empty() {
+            claims.insert("scope".to_string(), Value::String(self.scopes.join(" ")));
+        }
+        let mut authorizer = ApiGatewayRequestAuthorizer::default();
+        authorizer.fields.insert(
+            "claims".to_string(),
+            Value::Object(claims.into_iter().collect()),
+        );
+        authorizer
+    }
+}
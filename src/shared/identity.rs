@@ -0,0 +1,332 @@
+use async_trait::async_trait;
+use aws_lambda_events::apigw::ApiGatewayProxyRequest;
+use fractic_server_error::ServerError;
+use serde_json::Value;
+
+use crate::shared::auth_utils::{
+    get_client_id, get_groups, get_scopes, get_sub_of_authenticated_user, is_authenticated,
+    parse_list,
+};
+
+// Caller identity.
+// --------------------------------------------------
+//
+// Where the caller's identity is found depends on how the API is secured. The
+// `IdentityExtractor` configured on the `RoutingConfig` reads it from the
+// request, and the router derives the `RequestMetadata` from it:
+//
+// - `CognitoIdentity` (default): Cognito user pool authorizer claims.
+// - `OidcIdentity`: JWT authorizer claims of other OIDC providers (e.g. Auth0
+//   or Okta).
+// - `LambdaAuthorizerIdentity`: context returned by a Lambda authorizer.
+// - `IamIdentity`: IAM-authorized (SigV4 signed) requests.
+//
+// Claims, authorizer context and IAM callers of HTTP API events are
+// normalized to the REST API layout beforehand (see `ApiEvent`), so all
+// extractors work with both.
+
+/// Identity of an authenticated caller.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Identity {
+    /// Unique ID of the caller (user sub, client ID, principal ID or ARN).
+    pub sub: String,
+    /// Groups (or roles) of the caller, checked by `Access::AnyOf` and
+    /// `Access::AllOf`, and mapped to admin access by `RoleConfig`.
+    pub groups: Vec<String>,
+    /// OAuth2 scopes of the caller's token.
+    pub scopes: Vec<String>,
+    /// Set for machine clients authenticated through the client-credentials
+    /// flow, which are not users (see `RequestMetadata::client_id`).
+    pub client_id: Option<String>,
+}
+
+#[async_trait]
+pub trait IdentityExtractor: Send + Sync {
+    /// Returns `None` for unauthenticated requests.
+    async fn extract(
+        &self,
+        request: &ApiGatewayProxyRequest,
+    ) -> Result<Option<Identity>, ServerError>;
+}
+
+/// Reads the claims set by a Cognito user pool authorizer ("sub",
+/// "cognito:groups" and "scope").
+pub struct CognitoIdentity;
+
+#[async_trait]
+impl IdentityExtractor for CognitoIdentity {
+    async fn extract(
+        &self,
+        request: &ApiGatewayProxyRequest,
+    ) -> Result<Option<Identity>, ServerError> {
+        cognito_identity(request)
+    }
+}
+
+/// Reads the claims set by a JWT authorizer for a generic OIDC provider.
+/// Requests are authenticated if the token has a "sub" claim.
+pub struct OidcIdentity {
+    /// Claim listing the caller's groups (or roles).
+    pub groups_claim: String,
+    /// Claim listing the token's scopes.
+    pub scopes_claim: String,
+}
+
+impl Default for OidcIdentity {
+    fn default() -> Self {
+        Self {
+            groups_claim: "groups".to_string(),
+            scopes_claim: "scope".to_string(),
+        }
+    }
+}
+
+impl OidcIdentity {
+    /// Okta puts groups in "groups" (if configured on the authorization
+    /// server) and scopes in "scp".
+    pub fn okta() -> Self {
+        Self {
+            groups_claim: "groups".to_string(),
+            scopes_claim: "scp".to_string(),
+        }
+    }
+
+    /// Auth0 only adds roles to tokens through a namespaced custom claim
+    /// (e.g. "https://example.com/roles"), set by a login action.
+    pub fn auth0(roles_claim: &str) -> Self {
+        Self {
+            groups_claim: roles_claim.to_string(),
+            scopes_claim: "scope".to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl IdentityExtractor for OidcIdentity {
+    async fn extract(
+        &self,
+        request: &ApiGatewayProxyRequest,
+    ) -> Result<Option<Identity>, ServerError> {
+        let Some(claims) = request.request_context.authorizer.fields.get("claims") else {
+            return Ok(None);
+        };
+        let Some(sub) = claims.get("sub").and_then(Value::as_str) else {
+            return Ok(None);
+        };
+        Ok(Some(Identity {
+            sub: sub.to_string(),
+            groups: claims
+                .get(&self.groups_claim)
+                .map(parse_list)
+                .unwrap_or_default(),
+            scopes: claims
+                .get(&self.scopes_claim)
+                .map(parse_list)
+                .unwrap_or_default(),
+            ..Default::default()
+        }))
+    }
+}
+
+/// Reads the context returned by a Lambda authorizer. Context values can only
+/// be strings, numbers or booleans, so lists are expected as comma-separated
+/// strings. Requests are authenticated if the context contains `sub_key`.
+pub struct LambdaAuthorizerIdentity {
+    /// Defaults to "principalId", which REST API authorizers always return.
+    /// HTTP API authorizers with simple responses have no principal ID, so
+    /// they must add one to their context.
+    pub sub_key: String,
+    /// Defaults to "groups".
+    pub groups_key: String,
+    /// Defaults to "scopes".
+    pub scopes_key: String,
+}
+
+impl Default for LambdaAuthorizerIdentity {
+    fn default() -> Self {
+        Self {
+            sub_key: "principalId".to_string(),
+            groups_key: "groups".to_string(),
+            scopes_key: "scopes".to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl IdentityExtractor for LambdaAuthorizerIdentity {
+    async fn extract(
+        &self,
+        request: &ApiGatewayProxyRequest,
+    ) -> Result<Option<Identity>, ServerError> {
+        let fields = &request.request_context.authorizer.fields;
+        let Some(sub) = fields.get(&self.sub_key).and_then(Value::as_str) else {
+            return Ok(None);
+        };
+        Ok(Some(Identity {
+            sub: sub.to_string(),
+            groups: fields
+                .get(&self.groups_key)
+                .map(parse_list)
+                .unwrap_or_default(),
+            scopes: fields
+                .get(&self.scopes_key)
+                .map(parse_list)
+                .unwrap_or_default(),
+            ..Default::default()
+        }))
+    }
+}
+
+/// Identifies IAM-authorized requests by the caller's ARN (e.g.
+/// "arn:aws:sts::123456789012:assumed-role/my-role/my-session"). IAM callers
+/// have no groups or scopes, so routes should restrict them through
+/// `Access::AnyUser` together with IAM policies, or through validators
+/// checking `RequestMetadata::user_sub`.
+pub struct IamIdentity;
+
+#[async_trait]
+impl IdentityExtractor for IamIdentity {
+    async fn extract(
+        &self,
+        request: &ApiGatewayProxyRequest,
+    ) -> Result<Option<Identity>, ServerError> {
+        Ok(request
+            .request_context
+            .identity
+            .user_arn
+            .as_ref()
+            .map(|arn| Identity {
+                sub: arn.clone(),
+                ..Default::default()
+            }))
+    }
+}
+
+pub(crate) fn cognito_identity(
+    request: &ApiGatewayProxyRequest,
+) -> Result<Option<Identity>, ServerError> {
+    if is_authenticated(request) {
+        return Ok(Some(Identity {
+            sub: get_sub_of_authenticated_user(request)?,
+            groups: get_groups(request),
+            scopes: get_scopes(request),
+            client_id: None,
+        }));
+    }
+    Ok(get_client_id(request).map(|client_id| Identity {
+        sub: client_id.clone(),
+        groups: Vec::new(),
+        scopes: get_scopes(request),
+        client_id: Some(client_id),
+    }))
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_with_fields(fields: Value) -> ApiGatewayProxyRequest {
+        let mut request = ApiGatewayProxyRequest::default();
+        request.request_context.authorizer.fields = fields
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        request
+    }
+
+    fn identity(sub: &str, groups: &[&str], scopes: &[&str]) -> Option<Identity> {
+        Some(Identity {
+            sub: sub.to_string(),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            client_id: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_cognito() {
+        let request = request_with_fields(serde_json::json!({
+            "claims": {
+                "cognito:username": "user",
+                "sub": "user-sub",
+                "cognito:groups": "editor,admin",
+            }
+        }));
+        assert_eq!(
+            CognitoIdentity.extract(&request).await.unwrap(),
+            identity("user-sub", &["editor", "admin"], &[])
+        );
+        let guest = ApiGatewayProxyRequest::default();
+        assert_eq!(CognitoIdentity.extract(&guest).await.unwrap(), None);
+
+        let client = request_with_fields(serde_json::json!({
+            "claims": {
+                "client_id": "svc",
+                "sub": "svc",
+                "scope": "orders/read",
+            }
+        }));
+        assert_eq!(
+            CognitoIdentity.extract(&client).await.unwrap(),
+            Some(Identity {
+                client_id: Some("svc".to_string()),
+                ..identity("svc", &[], &["orders/read"]).unwrap()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oidc() {
+        let request = request_with_fields(serde_json::json!({
+            "claims": {
+                "sub": "00u1abcd",
+                "groups": "[Editors Moderators]",
+                "scp": ["orders.read"],
+                "https://example.com/roles": ["editor"],
+                "scope": "openid orders.read",
+            }
+        }));
+        assert_eq!(
+            OidcIdentity::okta().extract(&request).await.unwrap(),
+            identity("00u1abcd", &["Editors", "Moderators"], &["orders.read"])
+        );
+        assert_eq!(
+            OidcIdentity::auth0("https://example.com/roles")
+                .extract(&request)
+                .await
+                .unwrap(),
+            identity("00u1abcd", &["editor"], &["openid", "orders.read"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lambda_authorizer() {
+        let request = request_with_fields(serde_json::json!({
+            "principalId": "partner-1",
+            "groups": "partners",
+            "integrationLatency": 12,
+        }));
+        let extractor = LambdaAuthorizerIdentity::default();
+        assert_eq!(
+            extractor.extract(&request).await.unwrap(),
+            identity("partner-1", &["partners"], &[])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_iam() {
+        let mut request = ApiGatewayProxyRequest::default();
+        assert_eq!(IamIdentity.extract(&request).await.unwrap(), None);
+        request.request_context.identity.user_arn =
+            Some("arn:aws:iam::123456789012:user/ci".to_string());
+        assert_eq!(
+            IamIdentity.extract(&request).await.unwrap(),
+            identity("arn:aws:iam::123456789012:user/ci", &[], &[])
+        );
+    }
+}
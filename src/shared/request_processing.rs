@@ -1,16 +1,179 @@
-use aws_lambda_events::apigw::ApiGatewayProxyRequest;
+use std::{any::Any, collections::HashMap, sync::Arc};
+
+use aws_lambda_events::{
+    apigw::ApiGatewayProxyRequest,
+    http::{HeaderMap, Method},
+};
 use fractic_server_error::ServerError;
 
 use crate::{
     errors::InvalidRequestError,
-    shared::auth_utils::{get_sub_of_authenticated_user, is_admin, is_authenticated},
+    handle_with_router::routing_config::{AppState, RouteContext},
+    shared::{
+        api_keys::ApiKeyPrincipal,
+        auth_utils::RoleConfig,
+        identity::{cognito_identity, Identity},
+        string_map_deserializer::deserialize_string_map,
+    },
 };
 
 #[derive(Debug, Clone)]
 pub struct RequestMetadata {
     pub is_authenticated: bool,
+    /// Member of one of the admin groups (see `RoleConfig`).
     pub is_admin: bool,
     pub user_sub: Option<String>,
+    /// Groups (or roles) of the authenticated user.
+    pub groups: Vec<String>,
+    /// OAuth2 scopes of the access token (e.g. those granted to a machine
+    /// client through the client-credentials flow).
+    pub scopes: Vec<String>,
+    /// Set for machine clients authenticated through the client-credentials
+    /// flow. They are not users, so `is_authenticated` is false, but routes
+    /// accept them through their scopes (see `Access::Scope`).
+    pub client_id: Option<String>,
+    /// Set if the request carries a valid API key (see `ApiKeyStore`).
+    pub api_key: Option<ApiKeyPrincipal>,
+}
+
+impl RequestMetadata {
+    pub fn from_identity(identity: Option<Identity>, roles: &RoleConfig) -> Self {
+        match identity {
+            Some(Identity {
+                client_id: Some(client_id),
+                scopes,
+                ..
+            }) => Self {
+                client_id: Some(client_id),
+                scopes,
+                ..Self::from_identity(None, roles)
+            },
+            Some(identity) => Self {
+                is_authenticated: true,
+                is_admin: roles.is_admin(&identity.groups),
+                user_sub: Some(identity.sub),
+                groups: identity.groups,
+                scopes: identity.scopes,
+                client_id: None,
+                api_key: None,
+            },
+            None => Self {
+                is_authenticated: false,
+                is_admin: false,
+                user_sub: None,
+                groups: Vec::new(),
+                scopes: Vec::new(),
+                client_id: None,
+                api_key: None,
+            },
+        }
+    }
+
+    pub fn has_group(&self, group: &str) -> bool {
+        self.groups.iter().any(|g| g == group)
+    }
+
+    pub fn has_any_group(&self, groups: &[&str]) -> bool {
+        groups.iter().any(|g| self.has_group(g))
+    }
+
+    pub fn has_all_groups(&self, groups: &[&str]) -> bool {
+        groups.iter().all(|g| self.has_group(g))
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+
+    pub fn has_all_scopes(&self, scopes: &[&str]) -> bool {
+        scopes.iter().all(|s| self.has_scope(s))
+    }
+
+    /// Authenticated user or machine client, i.e. a caller with a token whose
+    /// scopes can be checked.
+    pub fn has_token(&self) -> bool {
+        self.is_authenticated || self.client_id.is_some()
+    }
+}
+
+/// Request information made available to handlers registered through the
+/// `with_context` constructors.
+#[derive(Clone)]
+pub struct RequestContext {
+    pub metadata: RequestMetadata,
+    /// The raw request. Path parameters include those captured by templated
+    /// route keys.
+    pub request: ApiGatewayProxyRequest,
+    state: Option<AppState>,
+}
+
+impl RequestContext {
+    pub(crate) fn new(
+        request: &ApiGatewayProxyRequest,
+        metadata: RequestMetadata,
+        route_ctx: &RouteContext,
+    ) -> Self {
+        Self {
+            metadata,
+            request: request.clone(),
+            state: route_ctx.state.clone(),
+        }
+    }
+
+    /// Application state, if configured and of type `S`.
+    pub fn state<S>(&self) -> Option<Arc<S>>
+    where
+        S: Any + Send + Sync,
+    {
+        self.state.clone().and_then(|s| s.downcast::<S>().ok())
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.request.headers
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.request.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    pub fn query_param(&self, key: &str) -> Option<&str> {
+        self.request.query_string_parameters.first(key)
+    }
+
+    pub fn path_param(&self, key: &str) -> Option<&str> {
+        self.request.path_parameters.get(key).map(String::as_str)
+    }
+
+    /// API Gateway request ID.
+    pub fn request_id(&self) -> Option<&str> {
+        self.request.request_context.request_id.as_deref()
+    }
+}
+
+/// Everything needed to build a `RequestContext`, passed to boxed handlers so
+/// that the request is only cloned for handlers that take the context.
+pub(crate) struct PendingContext<'a> {
+    request: &'a ApiGatewayProxyRequest,
+    metadata: RequestMetadata,
+    route_ctx: &'a RouteContext,
+}
+
+impl<'a> PendingContext<'a> {
+    pub(crate) fn new(
+        request: &'a ApiGatewayProxyRequest,
+        metadata: RequestMetadata,
+        route_ctx: &'a RouteContext,
+    ) -> Self {
+        Self {
+            request,
+            metadata,
+            route_ctx,
+        }
+    }
+
+    pub(crate) fn build(self) -> RequestContext {
+        RequestContext::new(self.request, self.metadata, self.route_ctx)
+    }
 }
 
 // API Gateway request utils.
@@ -29,19 +192,54 @@ where
     serde_json::from_str(body).map_err(|e| InvalidRequestError::with_debug("parsing error", &e))
 }
 
+/// Parses the request's path parameters (either captured by a templated route
+/// key, or populated by API Gateway for templated resources) into `T`. The
+/// catch-all `proxy` parameter is ignored.
+pub fn parse_path_params<T>(request: &ApiGatewayProxyRequest) -> Result<T, ServerError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    deserialize_string_map(&request.path_parameters, &["proxy"])
+        .map_err(|e| InvalidRequestError::with_debug("invalid path parameters", &e))
+}
+
+/// Parses the request's query string parameters into `T`. If a parameter is
+/// repeated, only its first value is used.
+pub fn parse_query_params<T>(request: &ApiGatewayProxyRequest) -> Result<T, ServerError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let mut params: HashMap<String, String> = HashMap::new();
+    for (k, v) in request.query_string_parameters.iter() {
+        params.entry(k.to_string()).or_insert_with(|| v.to_string());
+    }
+    deserialize_string_map(&params, &[])
+        .map_err(|e| InvalidRequestError::with_debug("invalid query parameters", &e))
+}
+
+/// Parses the input of a function route. GET requests carry no body, so their
+/// input is read from the query string instead.
+pub fn parse_request_input<T>(request: &ApiGatewayProxyRequest) -> Result<T, ServerError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    if request.http_method == Method::GET {
+        parse_query_params(request)
+    } else {
+        parse_request_data(request)
+    }
+}
+
+/// Reads Cognito authorizer claims, with the default `RoleConfig`. Routes use
+/// the `IdentityExtractor` and `RoleConfig` of their `RoutingConfig` instead
+/// (see `RouteContext::request_metadata`).
 pub fn parse_request_metadata(
     request: &ApiGatewayProxyRequest,
 ) -> Result<RequestMetadata, ServerError> {
-    let is_authenticated = is_authenticated(request);
-    Ok(RequestMetadata {
-        is_authenticated,
-        is_admin: is_admin(request),
-        user_sub: if is_authenticated {
-            Some(get_sub_of_authenticated_user(request)?)
-        } else {
-            None
-        },
-    })
+    Ok(RequestMetadata::from_identity(
+        cognito_identity(request)?,
+        &RoleConfig::default(),
+    ))
 }
 
 // Tests.
@@ -90,6 +288,75 @@ mod tests {
         assert!(format!("{:?}", result.unwrap_err()).contains("InvalidRequestError"));
     }
 
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct TestPathParams {
+        user_id: String,
+        order_id: u64,
+    }
+
+    fn request_with_path_params(params: &[(&str, &str)]) -> ApiGatewayProxyRequest {
+        let mut request = ApiGatewayProxyRequest::default();
+        request.path_parameters = params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        request
+    }
+
+    #[test]
+    fn test_parse_path_params() {
+        let request = request_with_path_params(&[
+            ("proxy", "users/abc/orders/42"),
+            ("user_id", "abc"),
+            ("order_id", "42"),
+        ]);
+        let result = parse_path_params::<TestPathParams>(&request);
+        assert_eq!(
+            result.unwrap(),
+            TestPathParams {
+                user_id: "abc".to_string(),
+                order_id: 42,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_path_params_wrong_type() {
+        let request = request_with_path_params(&[("user_id", "abc"), ("order_id", "abc")]);
+        let result = parse_path_params::<TestPathParams>(&request);
+        assert!(format!("{:?}", result.unwrap_err()).contains("InvalidRequestError"));
+    }
+
+    #[test]
+    fn test_parse_request_input_get_uses_query() {
+        let mut request = ApiGatewayProxyRequest::default();
+        request.http_method = Method::GET;
+        request.query_string_parameters =
+            HashMap::from([("key".to_string(), "value".to_string())]).into();
+        let result = parse_request_input::<TestData>(&request);
+        assert_eq!(
+            result.unwrap(),
+            TestData {
+                key: "value".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_request_input_post_uses_body() {
+        let mut request = request_with_body(Some("{\"key\":\"value\"}"));
+        request.http_method = Method::POST;
+        request.query_string_parameters =
+            HashMap::from([("key".to_string(), "other".to_string())]).into();
+        let result = parse_request_input::<TestData>(&request);
+        assert_eq!(
+            result.unwrap(),
+            TestData {
+                key: "value".to_string()
+            }
+        );
+    }
+
     #[test]
     fn test_parse_request_valid_json_wrong_type() {
         let request = request_with_body(Some("{\"different_key\":\"value\"}"));
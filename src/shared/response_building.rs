@@ -1,51 +1,316 @@
 use std::io::Write as _;
 
 use aws_lambda_events::{
-    apigw::ApiGatewayProxyResponse,
+    apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse},
     encodings::Body,
     http::{
-        header::{
-            ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
-            ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE,
-        },
-        HeaderMap, HeaderValue,
+        header::{ACCEPT, ACCEPT_ENCODING, CONTENT_TYPE, VARY},
+        HeaderMap, HeaderValue, StatusCode,
     },
 };
 use base64::Engine as _;
 use flate2::{write::GzEncoder, Compression};
-use fractic_server_error::ServerError;
+use fractic_server_error::{ServerError, ServerErrorBehaviour};
 use lambda_runtime::Error;
 use serde::Serialize;
 
 use crate::{
     constants::{INTERNAL_SERVER_ERROR_MSG, UNAUTHORIZED_ERROR_MSG},
     errors::EncodingError,
+    shared::{cors::CorsPolicy, request_processing::RequestMetadata},
 };
 
 // API Gateway response utils.
 // --------------------------------------------------
 
-/// Wrapper for all API responses.
+/// Wrapper for all API responses (envelope v1).
 #[derive(Debug, Serialize)]
-struct ResponseWrapper {
+struct ResponseWrapper<'a, T: Serialize> {
     ok: bool,
 
-    /// If OK, response data, encoded as JSON -> gzip -> base64.
-    ///
-    /// TODO:
-    ///     Currently, all responses are encoded in standard gzip+base64, but
-    ///     this library should eventually support various encoding options,
-    ///     specifyable by query parameter, and perhaps even versioning of some
-    ///     sort.
-    ///
+    /// If OK, response data, serialized and encoded according to the
+    /// negotiated `PayloadFormat` and `PayloadEncoding` (by default, JSON ->
+    /// gzip -> base64).
     #[serde(skip_serializing_if = "Option::is_none")]
-    data: Option<String>,
+    data: Option<ResponseData<'a, T>>,
 
     /// If not OK, error message safe to show to user.
     #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<String>,
+    error: Option<&'a str>,
+}
+
+/// Wrapper for all API responses (envelope v2). Unlike v1, the envelope is
+/// self-describing: it carries its own version and the payload's format and
+/// encoding, and errors are objects so that fields can be added later.
+#[derive(Debug, Serialize)]
+struct ResponseWrapperV2<'a, T: Serialize> {
+    version: u32,
+    ok: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<ResponseData<'a, T>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'static str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'static str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ResponseErrorV2<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ResponseErrorV2<'a> {
+    /// Error message safe to show to user.
+    message: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum ResponseData<'a, T: Serialize> {
+    /// Serialized, compressed and base64-encoded payload.
+    Encoded(String),
+    /// Payload embedded as plain JSON (JSON format with identity encoding).
+    Plain(&'a T),
+}
+
+/// Serialization format of the payload in the response's `data` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PayloadFormat {
+    #[default]
+    Json,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl PayloadFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "json" | "application/json" => Some(PayloadFormat::Json),
+            #[cfg(feature = "msgpack")]
+            "msgpack" | "messagepack" | "application/msgpack" | "application/x-msgpack" => {
+                Some(PayloadFormat::MessagePack)
+            }
+            #[cfg(feature = "cbor")]
+            "cbor" | "application/cbor" => Some(PayloadFormat::Cbor),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PayloadFormat::Json => "json",
+            #[cfg(feature = "msgpack")]
+            PayloadFormat::MessagePack => "msgpack",
+            #[cfg(feature = "cbor")]
+            PayloadFormat::Cbor => "cbor",
+        }
+    }
+
+    fn serialize<T: Serialize>(&self, data: &T) -> Result<Vec<u8>, ServerError> {
+        match self {
+            PayloadFormat::Json => {
+                serde_json::to_vec(data).map_err(|e| EncodingError::with_debug("json", &e))
+            }
+            #[cfg(feature = "msgpack")]
+            PayloadFormat::MessagePack => {
+                rmp_serde::to_vec_named(data).map_err(|e| EncodingError::with_debug("msgpack", &e))
+            }
+            #[cfg(feature = "cbor")]
+            PayloadFormat::Cbor => {
+                let mut out = Vec::new();
+                ciborium::into_writer(data, &mut out)
+                    .map_err(|e| EncodingError::with_debug("cbor", &e))?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+/// Encoding applied to the serialized payload in the response's `data` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PayloadEncoding {
+    /// No compression. JSON payloads are embedded directly in `data`, while
+    /// binary formats are only base64-encoded.
+    Identity,
+    /// gzip -> base64.
+    #[default]
+    Gzip,
+    /// brotli -> base64.
+    #[cfg(feature = "brotli")]
+    Brotli,
+    /// zstd -> base64.
+    #[cfg(feature = "zstd")]
+    Zstd,
 }
 
+impl PayloadEncoding {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "identity" | "json" => Some(PayloadEncoding::Identity),
+            "gzip" => Some(PayloadEncoding::Gzip),
+            #[cfg(feature = "brotli")]
+            "br" | "brotli" => Some(PayloadEncoding::Brotli),
+            #[cfg(feature = "zstd")]
+            "zstd" => Some(PayloadEncoding::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PayloadEncoding::Identity => "identity",
+            PayloadEncoding::Gzip => "gzip",
+            #[cfg(feature = "brotli")]
+            PayloadEncoding::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            PayloadEncoding::Zstd => "zstd",
+        }
+    }
+}
+
+/// Version of the response envelope (the JSON object wrapping the payload).
+/// Clients opt in to newer versions through the `X-Envelope-Version` header,
+/// so that older app builds keep receiving the shape they understand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum EnvelopeVersion {
+    /// `{ "ok": bool, "data"?: .., "error"?: string }`
+    #[default]
+    V1,
+    /// `{ "version": 2, "ok": bool, "data"?: .., "format"?: string,
+    /// "encoding"?: string, "error"?: { "message": string } }`
+    V2,
+}
+
+impl EnvelopeVersion {
+    pub const LATEST: EnvelopeVersion = EnvelopeVersion::V2;
+
+    /// Parses a requested version (e.g. "2" or "v2"). Versions newer than
+    /// the latest supported one resolve to `LATEST`, so that newer clients
+    /// degrade gracefully against older servers.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let n: u32 = s.strip_prefix(['v', 'V']).unwrap_or(s).parse().ok()?;
+        match n {
+            0 => None,
+            1 => Some(EnvelopeVersion::V1),
+            _ => Some(EnvelopeVersion::LATEST),
+        }
+    }
+
+    pub fn number(&self) -> u32 {
+        match self {
+            EnvelopeVersion::V1 => 1,
+            EnvelopeVersion::V2 => 2,
+        }
+    }
+}
+
+/// How errors are returned to the client.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum ErrorMode {
+    /// Client errors are wrapped in a 200 response with `ok == false`, so that
+    /// Amplify clients can handle them gracefully. Only internal and
+    /// authorization errors use a non-200 status code.
+    #[default]
+    Envelope,
+    /// Standard HTTP semantics: errors use 4xx / 5xx status codes and RFC 7807
+    /// `application/problem+json` bodies. Intended for third-party API
+    /// consumers.
+    Problem(ProblemMapping),
+}
+
+/// Status codes used by `ErrorMode::Problem`.
+///
+/// By default, errors forwarded to the client map to 400, authorization errors
+/// to 401 for callers without credentials and 403 for callers lacking the
+/// required access, internal errors to 500, and unknown routes to 404. Of the
+/// crate's own errors, `InvalidRouteError` maps to 404, and `InvalidRequestError`
+/// to 400 for missing input and 422 for invalid input. `status_of` can override
+/// the status of specific errors (e.g. 404, 409, 422 or 429 for an
+/// application's own error types). The response body never contains more than
+/// the message that would be shown in `ErrorMode::Envelope`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProblemMapping {
+    pub status_of: Option<fn(&ServerError) -> Option<u16>>,
+}
+
+/// Options controlling how a response is built, negotiated per request.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseOptions {
+    pub format: PayloadFormat,
+    pub encoding: PayloadEncoding,
+    pub envelope: EnvelopeVersion,
+    /// Not negotiated; set by the router (see `RoutingConfig::error_mode`).
+    pub error_mode: ErrorMode,
+}
+
+impl ResponseOptions {
+    /// The payload encoding is selected by (in order of precedence):
+    ///
+    /// 1. The `encoding` query parameter.
+    /// 2. The `X-Payload-Encoding` header.
+    /// 3. The `Accept-Encoding` header, but only if it does not accept gzip.
+    ///    Browsers and HTTP clients always accept gzip, so this keeps existing
+    ///    clients on the default while allowing e.g. `Accept-Encoding:
+    ///    identity` from curl.
+    ///
+    /// Unsupported values are ignored, falling back to gzip.
+    ///
+    /// Similarly, the payload format is selected by the `format` query
+    /// parameter, the `X-Payload-Format` header, or an `Accept` header
+    /// listing MessagePack or CBOR, falling back to JSON.
+    ///
+    /// The envelope version is selected by the `X-Envelope-Version` header,
+    /// falling back to v1.
+    pub fn negotiate(request: &ApiGatewayProxyRequest) -> Self {
+        let header = |name: &str| request.headers.get(name).and_then(|v| v.to_str().ok());
+        let encoding = request
+            .query_string_parameters
+            .first("encoding")
+            .and_then(PayloadEncoding::parse)
+            .or_else(|| header(PAYLOAD_ENCODING_HEADER).and_then(PayloadEncoding::parse))
+            .or_else(|| header(ACCEPT_ENCODING.as_str()).and_then(negotiate_accept_encoding))
+            .unwrap_or_default();
+        let format = request
+            .query_string_parameters
+            .first("format")
+            .and_then(PayloadFormat::parse)
+            .or_else(|| header(PAYLOAD_FORMAT_HEADER).and_then(PayloadFormat::parse))
+            .or_else(|| {
+                header(ACCEPT.as_str()).and_then(|accept| {
+                    accept
+                        .split(',')
+                        .filter_map(|item| item.split(';').next())
+                        .filter_map(PayloadFormat::parse)
+                        .find(|f| *f != PayloadFormat::Json)
+                })
+            })
+            .unwrap_or_default();
+        let envelope = header(ENVELOPE_VERSION_HEADER)
+            .and_then(EnvelopeVersion::parse)
+            .unwrap_or_default();
+        Self {
+            format,
+            encoding,
+            envelope,
+            error_mode: ErrorMode::default(),
+        }
+    }
+}
+
+/// Response header indicating the encoding of the payload in `data`.
+pub const PAYLOAD_ENCODING_HEADER: &str = "x-payload-encoding";
+
+/// Response header indicating the format of the payload in `data`.
+pub const PAYLOAD_FORMAT_HEADER: &str = "x-payload-format";
+
+/// Request header selecting the envelope version, echoed in the response.
+pub const ENVELOPE_VERSION_HEADER: &str = "x-envelope-version";
+
 fn build_response(
     status_code: i64,
     content_type: ContentType,
@@ -67,35 +332,118 @@ pub fn build_simple(data: impl Into<Body>) -> ApiGatewayProxyResponse {
 }
 
 pub fn build_result<T>(result: Result<T, ServerError>) -> Result<ApiGatewayProxyResponse, Error>
+where
+    T: serde::Serialize,
+{
+    build_result_with(result, &ResponseOptions::default())
+}
+
+pub fn build_result_with<T>(
+    result: Result<T, ServerError>,
+    options: &ResponseOptions,
+) -> Result<ApiGatewayProxyResponse, Error>
 where
     T: serde::Serialize,
 {
     match result {
-        Ok(data) => build_ok(data),
-        Err(error) => build_err(error),
+        Ok(data) => build_ok_with(data, options),
+        Err(error) => build_err_with(error, options),
     }
 }
 
-pub(crate) fn build_ok<T>(data: T) -> Result<ApiGatewayProxyResponse, Error>
+pub(crate) fn build_ok_with<T>(
+    data: T,
+    options: &ResponseOptions,
+) -> Result<ApiGatewayProxyResponse, Error>
 where
     T: serde::Serialize,
 {
-    let payload = gzip_base64(&serde_json::to_vec(&data)?).map_err(|e| e.to_string())?;
-    let wrapper = ResponseWrapper {
-        ok: true,
-        data: Some(payload),
-        error: None,
+    let payload = match (options.format, options.encoding) {
+        (PayloadFormat::Json, PayloadEncoding::Identity) => ResponseData::Plain(&data),
+        (format, encoding) => ResponseData::Encoded(
+            format
+                .serialize(&data)
+                .and_then(|bytes| encode_base64(&bytes, encoding))
+                .map_err(|e| e.to_string())?,
+        ),
     };
-    let resp = build_response(
-        200,
-        ContentType::Json,
-        serde_json::to_string(&wrapper)?.into(),
-        false,
+    let body = envelope_body(options, Some(payload), None)?;
+    let mut resp = build_response(200, ContentType::Json, body.into(), false);
+    resp.headers.insert(
+        ENVELOPE_VERSION_HEADER,
+        HeaderValue::from(options.envelope.number()),
+    );
+    resp.headers.insert(
+        PAYLOAD_FORMAT_HEADER,
+        HeaderValue::from_static(options.format.name()),
+    );
+    resp.headers.insert(
+        PAYLOAD_ENCODING_HEADER,
+        HeaderValue::from_static(options.encoding.name()),
     );
+    resp.headers
+        .append(VARY, HeaderValue::from_static(NEGOTIATED_HEADERS));
+    Ok(resp)
+}
+
+/// Like `build_err_with`, but uses the given status code in
+/// `ErrorMode::Problem` (unless overridden by `ProblemMapping::status_of`).
+pub(crate) fn build_err_with_status(
+    error: ServerError,
+    status: u16,
+    options: &ResponseOptions,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    let mut resp = match options.error_mode {
+        ErrorMode::Envelope => return build_err_with(error, options),
+        ErrorMode::Problem(mapping) => build_problem(error, Some(status), &mapping)?,
+    };
+    resp.headers
+        .append(VARY, HeaderValue::from_static(NEGOTIATED_HEADERS));
     Ok(resp)
 }
 
-pub(crate) fn build_err(error: ServerError) -> Result<ApiGatewayProxyResponse, Error> {
+/// Like `build_err_with`, for errors of a request made by the given caller.
+/// In `ErrorMode::Problem`, authorization errors are 401 for callers without
+/// credentials, and 403 for callers lacking the required access.
+pub(crate) fn build_err_for(
+    error: ServerError,
+    metadata: &RequestMetadata,
+    options: &ResponseOptions,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    let has_credentials = metadata.has_token() || metadata.api_key.is_some();
+    if has_credentials && matches!(error.behaviour(), ServerErrorBehaviour::ReturnUnauthorized) {
+        build_err_with_status(error, 403, options)
+    } else {
+        build_err_with(error, options)
+    }
+}
+
+pub(crate) fn build_err_with(
+    error: ServerError,
+    options: &ResponseOptions,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    let mut resp = match options.error_mode {
+        ErrorMode::Envelope => build_envelope_err(error, options)?,
+        ErrorMode::Problem(mapping) => build_problem(error, None, &mapping)?,
+    };
+    resp.headers
+        .append(VARY, HeaderValue::from_static(NEGOTIATED_HEADERS));
+    Ok(resp)
+}
+
+// Helper functions.
+// --------------------------------------------------
+
+/// Request headers read by `ResponseOptions::negotiate`, listed in the `Vary`
+/// header of responses built with negotiated options so that caches keep
+/// the variants apart.
+const NEGOTIATED_HEADERS: &str =
+    "Accept, Accept-Encoding, X-Payload-Format, X-Payload-Encoding, X-Envelope-Version";
+
+fn build_envelope_err(
+    error: ServerError,
+    options: &ResponseOptions,
+) -> Result<ApiGatewayProxyResponse, Error> {
     enum LoggingLevel {
         Error,
         Warning,
@@ -113,20 +461,16 @@ pub(crate) fn build_err(error: ServerError) -> Result<ApiGatewayProxyResponse, E
             LoggingLevel::Info => println!("INFO\n{}", error),
         }
         println!("NOTE: Forwarding to client. Returning 200 response.");
-        let wrapper = ResponseWrapper {
-            ok: false,
-            data: None,
-            error: Some(public_msg.into()),
-        };
+        let body = envelope_body::<()>(options, None, Some(public_msg))?;
         // Outer status code should still be 200 for client-errors, otherwise Amplify
         // will treat it as a server error. The client will know there is a client
         // error because ok == false.
-        Ok::<_, Error>(build_response(
-            200,
-            ContentType::Json,
-            serde_json::to_string(&wrapper)?.into(),
-            false,
-        ))
+        let mut resp = build_response(200, ContentType::Json, body.into(), false);
+        resp.headers.insert(
+            ENVELOPE_VERSION_HEADER,
+            HeaderValue::from(options.envelope.number()),
+        );
+        Ok::<_, Error>(resp)
     };
 
     // 2) Return an error response, triggerring alerting, affecting lambda
@@ -167,21 +511,177 @@ pub(crate) fn build_err(error: ServerError) -> Result<ApiGatewayProxyResponse, E
     }
 }
 
-// Helper functions.
-// --------------------------------------------------
+/// Serializes the envelope for the negotiated version. Exactly one of `data`
+/// and `error` is expected to be set.
+fn envelope_body<T: Serialize>(
+    options: &ResponseOptions,
+    data: Option<ResponseData<'_, T>>,
+    error: Option<&str>,
+) -> Result<String, Error> {
+    Ok(match options.envelope {
+        EnvelopeVersion::V1 => serde_json::to_string(&ResponseWrapper {
+            ok: error.is_none(),
+            data,
+            error,
+        }),
+        EnvelopeVersion::V2 => serde_json::to_string(&ResponseWrapperV2 {
+            version: EnvelopeVersion::V2.number(),
+            ok: error.is_none(),
+            format: data.as_ref().map(|_| options.format.name()),
+            encoding: data.as_ref().map(|_| options.encoding.name()),
+            data,
+            error: error.map(|message| ResponseErrorV2 { message }),
+        }),
+    }?)
+}
+
+fn encode_base64(input: &[u8], encoding: PayloadEncoding) -> Result<String, ServerError> {
+    let compressed = match encoding {
+        PayloadEncoding::Identity => input.to_vec(),
+        PayloadEncoding::Gzip => gzip(input)?,
+        #[cfg(feature = "brotli")]
+        PayloadEncoding::Brotli => {
+            let mut out = Vec::new();
+            brotli::BrotliCompress(
+                &mut &input[..],
+                &mut out,
+                &brotli::enc::BrotliEncoderParams::default(),
+            )
+            .map_err(|e| EncodingError::with_debug("brotli", &e))?;
+            out
+        }
+        #[cfg(feature = "zstd")]
+        PayloadEncoding::Zstd => {
+            zstd::encode_all(input, 0).map_err(|e| EncodingError::with_debug("zstd", &e))?
+        }
+    };
+    Ok(base64::engine::general_purpose::STANDARD.encode(compressed))
+}
 
-fn gzip_base64(input: &[u8]) -> Result<String, ServerError> {
+fn gzip(input: &[u8]) -> Result<Vec<u8>, ServerError> {
     let mut e = GzEncoder::new(Vec::new(), Compression::default());
     e.write_all(input)
         .map_err(|e| EncodingError::with_debug("gzip write", &e))?;
-    let gz = e
-        .finish()
-        .map_err(|e| EncodingError::with_debug("gzip finish", &e))?;
-    Ok(base64::engine::general_purpose::STANDARD.encode(gz))
+    e.finish()
+        .map_err(|e| EncodingError::with_debug("gzip finish", &e))
+}
+
+/// Picks the first supported encoding listed in an `Accept-Encoding` header,
+/// unless it accepts gzip (see `ResponseOptions::negotiate`).
+fn negotiate_accept_encoding(accept_encoding: &str) -> Option<PayloadEncoding> {
+    let accepted: Vec<&str> = accept_encoding
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let name = parts.next()?;
+            let rejected = parts.any(|p| {
+                p.strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.0)
+            });
+            (!name.is_empty() && !rejected).then_some(name)
+        })
+        .collect();
+    if accepted
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case("gzip") || *name == "*")
+    {
+        return None;
+    }
+    accepted.into_iter().find_map(PayloadEncoding::parse)
+}
+
+/// RFC 7807 problem details.
+#[derive(Debug, Serialize)]
+struct Problem<'a> {
+    #[serde(rename = "type")]
+    problem_type: &'a str,
+    title: &'a str,
+    status: u16,
+    detail: &'a str,
+}
+
+fn build_problem(
+    error: ServerError,
+    status: Option<u16>,
+    mapping: &ProblemMapping,
+) -> Result<ApiGatewayProxyResponse, Error> {
+    use fractic_server_error::ServerErrorBehaviour as B;
+
+    // Logging follows the same rules as `ErrorMode::Envelope`.
+    let (default_status, detail) = match error.behaviour() {
+        B::ForwardToClient => {
+            println!("INFO\n{}", error);
+            (400, error.message().to_string())
+        }
+        B::LogWarningForwardToClient => {
+            println!("WARNING\n{}", error);
+            (400, error.message().to_string())
+        }
+        B::LogErrorForwardToClient => {
+            eprintln!("ERROR\n{}", error);
+            (400, error.message().to_string())
+        }
+        B::LogWarningSendFixedMsgToClient(fixed_msg) => {
+            println!("WARNING\n{}", error);
+            (400, fixed_msg.to_string())
+        }
+        B::LogErrorSendFixedMsgToClient(fixed_msg) => {
+            eprintln!("ERROR\n{}", error);
+            (400, fixed_msg.to_string())
+        }
+        B::ReturnInternalServerError => {
+            eprintln!("ERROR\n{}", error);
+            (500, INTERNAL_SERVER_ERROR_MSG.to_string())
+        }
+        B::ReturnUnauthorized => {
+            eprintln!("ERROR\n{}", error);
+            (401, UNAUTHORIZED_ERROR_MSG.to_string())
+        }
+    };
+    let status = mapping
+        .status_of
+        .and_then(|status_of| status_of(&error))
+        .or(status)
+        .or_else(|| status_of_crate_error(&error))
+        .unwrap_or(default_status);
+    let problem = Problem {
+        problem_type: "about:blank",
+        title: StatusCode::from_u16(status)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or("Error"),
+        status,
+        detail: &detail,
+    };
+    Ok(build_response(
+        status.into(),
+        ContentType::Problem,
+        serde_json::to_string(&problem)?.into(),
+        false,
+    ))
+}
+
+/// Default status of the crate's own client errors (see `ProblemMapping`),
+/// recognized by their message since `ServerError` does not expose its type.
+fn status_of_crate_error(error: &ServerError) -> Option<u16> {
+    let message = error.message();
+    if (message.starts_with("Route '") || message.starts_with("WebSocket route '"))
+        && message.ends_with("' does not exist.")
+    {
+        return Some(404);
+    }
+    let details = message.strip_prefix("Request is invalid: ")?;
+    Some(if details.starts_with("missing") {
+        400
+    } else {
+        422
+    })
 }
 
 enum ContentType {
     Json,
+    Problem,
     Text,
 }
 
@@ -191,52 +691,13 @@ fn build_headers(content_type: ContentType) -> HeaderMap {
         CONTENT_TYPE,
         match content_type {
             ContentType::Json => HeaderValue::from_static("application/json; charset=utf-8"),
+            ContentType::Problem => HeaderValue::from_static("application/problem+json"),
             ContentType::Text => HeaderValue::from_static("text/plain; charset=utf-8"),
         },
     );
-    //
-    // Build CORS headers to support web clients hosted on https://fractic.io
-    // accessing the API.
-    //
-    // Most modern browsers will not allow a web client to make a request to an
-    // API unless the relevant CORS headers are set.
-    //
-    // NOTE: In addition to requiring the proper response headers on the request
-    // itself, most modern browsers also make preflight OPTION requests before
-    // sending the actual API request. These preflight requests should be
-    // handled separately, and should also respond with the same CORS response
-    // headers as we do here (and no body). Those preflight handlers can be
-    // auto-generated by API Gateway by configuring the 'Cors' property on the
-    // AWS::Serverless::Api resource:
-    //
-    //   Cors:
-    //     AllowMethods: "'GET, POST, PUT, DELETE'"
-    //     AllowHeaders: "'Content-Type,X-Amz-Date,Authorization,X-Api-Key,X-Amz-Security-Token,X-Amz-User-Agent'"
-    //     AllowOrigin: "'https://example.com'"
-    //     MaxAge: "'600'"
-    //     AllowCredentials: true
-    //   Auth:
-    //     AddApiKeyRequiredToCorsPreflight: false
-    //     AddDefaultAuthorizerToCorsPreflight: false
-    //
-    headers.insert(
-        ACCESS_CONTROL_ALLOW_ORIGIN,
-        HeaderValue::from_static("https://fractic.io"),
-    );
-    headers.insert(
-        ACCESS_CONTROL_ALLOW_HEADERS,
-        HeaderValue::from_static(
-            "Content-Type,X-Amz-Date,Authorization,X-Api-Key,X-Amz-Security-Token,X-Amz-User-Agent",
-        ),
-    );
-    headers.insert(
-        ACCESS_CONTROL_ALLOW_METHODS,
-        HeaderValue::from_static("GET, POST, PUT, DELETE"),
-    );
-    headers.insert(
-        ACCESS_CONTROL_ALLOW_CREDENTIALS,
-        HeaderValue::from_static("true"),
-    );
+    // Responses built outside of the router (which applies its own policy
+    // based on the request's origin) use the global CORS policy.
+    CorsPolicy::global().apply(None, &mut headers);
     headers
 }
 
@@ -245,7 +706,10 @@ fn build_headers(content_type: ContentType) -> HeaderMap {
 
 #[cfg(test)]
 mod tests {
-    use crate::errors::UnauthorizedError;
+    use crate::{
+        errors::{InvalidRequestError, InvalidRouteError, UnauthorizedError},
+        shared::identity::Identity,
+    };
 
     use super::*;
     use aws_lambda_events::encodings::Body;
@@ -259,6 +723,30 @@ mod tests {
         key: String,
     }
 
+    fn build_ok<T: Serialize>(data: T) -> Result<ApiGatewayProxyResponse, Error> {
+        build_ok_with(data, &ResponseOptions::default())
+    }
+
+    fn build_err(error: ServerError) -> Result<ApiGatewayProxyResponse, Error> {
+        build_err_with(error, &ResponseOptions::default())
+    }
+
+    fn request_with(
+        query: &[(&str, &str)],
+        headers: &[(&'static str, &'static str)],
+    ) -> ApiGatewayProxyRequest {
+        let mut request = ApiGatewayProxyRequest::default();
+        request.query_string_parameters = query
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<std::collections::HashMap<_, _>>()
+            .into();
+        for (k, v) in headers {
+            request.headers.insert(*k, HeaderValue::from_static(*v));
+        }
+        request
+    }
+
     fn decode(payload: &str) -> String {
         use std::io::Read;
         let decoded = base64::engine::general_purpose::STANDARD
@@ -379,4 +867,339 @@ mod tests {
         assert_eq!(result.status_code, 401);
         assert!(!body.contains("internal authentication error message"));
     }
+
+    #[test]
+    fn test_negotiate_encoding() {
+        let negotiate = |query: &[(&str, &str)], headers: &[(&'static str, &'static str)]| {
+            ResponseOptions::negotiate(&request_with(query, headers))
+        };
+        assert_eq!(negotiate(&[], &[]).encoding, PayloadEncoding::Gzip);
+        assert_eq!(
+            negotiate(&[("encoding", "identity")], &[("x-payload-encoding", "br")]).encoding,
+            PayloadEncoding::Identity
+        );
+        assert_eq!(
+            negotiate(&[], &[("accept-encoding", "gzip, deflate, br")]).encoding,
+            PayloadEncoding::Gzip
+        );
+        assert_eq!(
+            negotiate(&[], &[("accept-encoding", "identity")]).encoding,
+            PayloadEncoding::Identity
+        );
+        assert_eq!(
+            negotiate(&[("encoding", "unknown")], &[]).encoding,
+            PayloadEncoding::Gzip
+        );
+    }
+
+    #[cfg(all(feature = "brotli", feature = "zstd"))]
+    #[test]
+    fn test_negotiate_brotli_and_zstd() {
+        let negotiate = |query: &[(&str, &str)], headers: &[(&'static str, &'static str)]| {
+            ResponseOptions::negotiate(&request_with(query, headers))
+        };
+        assert_eq!(
+            negotiate(&[], &[("x-payload-encoding", "zstd")]).encoding,
+            PayloadEncoding::Zstd
+        );
+        assert_eq!(
+            negotiate(&[], &[("accept-encoding", "br, gzip;q=0")]).encoding,
+            PayloadEncoding::Brotli
+        );
+    }
+
+    #[test]
+    fn test_build_result_identity() {
+        let data = MockResponseData {
+            key: "Test value.".to_string(),
+        };
+        let options = ResponseOptions {
+            encoding: PayloadEncoding::Identity,
+            ..Default::default()
+        };
+        let result = build_ok_with(data, &options).unwrap();
+        let body: Value = serde_json::from_str(match &result.body.unwrap() {
+            Body::Text(b) => b,
+            _ => panic!("Expected response body."),
+        })
+        .unwrap();
+
+        assert_eq!(
+            result.headers.get(PAYLOAD_ENCODING_HEADER).unwrap(),
+            "identity"
+        );
+        assert_eq!(body["ok"].as_bool().unwrap(), true);
+        assert_eq!(body["data"]["key"].as_str().unwrap(), "Test value.");
+    }
+
+    #[test]
+    fn test_vary_negotiated_headers() {
+        let ok = build_ok("data").unwrap();
+        let err = build_err(InvalidRequestError::new("bad")).unwrap();
+        for response in [ok, err] {
+            let vary = response.headers.get_all(VARY).iter().collect::<Vec<_>>();
+            assert!(vary.contains(&&HeaderValue::from_static(NEGOTIATED_HEADERS)));
+        }
+        for header in [
+            ACCEPT.as_str(),
+            ACCEPT_ENCODING.as_str(),
+            PAYLOAD_FORMAT_HEADER,
+            PAYLOAD_ENCODING_HEADER,
+            ENVELOPE_VERSION_HEADER,
+        ] {
+            assert!(NEGOTIATED_HEADERS
+                .split(", ")
+                .any(|name| name.eq_ignore_ascii_case(header)));
+        }
+    }
+
+    #[cfg(all(feature = "brotli", feature = "zstd"))]
+    #[test]
+    fn test_build_result_brotli_and_zstd() {
+        for encoding in [PayloadEncoding::Brotli, PayloadEncoding::Zstd] {
+            let options = ResponseOptions {
+                encoding,
+                ..Default::default()
+            };
+            let result = build_ok_with("Test string.", &options).unwrap();
+            let body: Value = serde_json::from_str(match &result.body.unwrap() {
+                Body::Text(b) => b,
+                _ => panic!("Expected response body."),
+            })
+            .unwrap();
+            let compressed = base64::engine::general_purpose::STANDARD
+                .decode(body["data"].as_str().unwrap())
+                .unwrap();
+            let decompressed = match encoding {
+                PayloadEncoding::Brotli => {
+                    let mut out = Vec::new();
+                    brotli::BrotliDecompress(&mut &compressed[..], &mut out).unwrap();
+                    out
+                }
+                _ => zstd::decode_all(&compressed[..]).unwrap(),
+            };
+            assert_eq!(String::from_utf8(decompressed).unwrap(), "\"Test string.\"");
+        }
+    }
+
+    #[test]
+    fn test_negotiate_format() {
+        let negotiate = |query: &[(&str, &str)], headers: &[(&'static str, &'static str)]| {
+            ResponseOptions::negotiate(&request_with(query, headers))
+        };
+        assert_eq!(negotiate(&[], &[]).format, PayloadFormat::Json);
+        assert_eq!(
+            negotiate(&[], &[("accept", "application/json, */*")]).format,
+            PayloadFormat::Json
+        );
+        assert_eq!(
+            negotiate(&[("format", "unknown")], &[]).format,
+            PayloadFormat::Json
+        );
+    }
+
+    #[cfg(all(feature = "msgpack", feature = "cbor"))]
+    #[test]
+    fn test_negotiate_msgpack_and_cbor() {
+        let negotiate = |query: &[(&str, &str)], headers: &[(&'static str, &'static str)]| {
+            ResponseOptions::negotiate(&request_with(query, headers))
+        };
+        assert_eq!(
+            negotiate(&[("format", "cbor")], &[]).format,
+            PayloadFormat::Cbor
+        );
+        assert_eq!(
+            negotiate(&[], &[("x-payload-format", "msgpack")]).format,
+            PayloadFormat::MessagePack
+        );
+        assert_eq!(
+            negotiate(&[], &[("accept", "application/msgpack;q=0.9")]).format,
+            PayloadFormat::MessagePack
+        );
+    }
+
+    #[cfg(all(feature = "msgpack", feature = "cbor"))]
+    #[test]
+    fn test_build_result_msgpack_and_cbor() {
+        for format in [PayloadFormat::MessagePack, PayloadFormat::Cbor] {
+            let options = ResponseOptions {
+                format,
+                encoding: PayloadEncoding::Identity,
+                ..Default::default()
+            };
+            let data = MockResponseData {
+                key: "Test value.".to_string(),
+            };
+            let result = build_ok_with(data, &options).unwrap();
+            let body: Value = serde_json::from_str(match &result.body.unwrap() {
+                Body::Text(b) => b,
+                _ => panic!("Expected response body."),
+            })
+            .unwrap();
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(body["data"].as_str().unwrap())
+                .unwrap();
+            let decoded: MockResponseData = match format {
+                PayloadFormat::MessagePack => rmp_serde::from_slice(&bytes).unwrap(),
+                _ => ciborium::from_reader(&bytes[..]).unwrap(),
+            };
+            assert_eq!(decoded.key, "Test value.");
+            assert_eq!(
+                result.headers.get(PAYLOAD_FORMAT_HEADER).unwrap(),
+                format.name()
+            );
+        }
+    }
+
+    #[test]
+    fn test_negotiate_envelope_version() {
+        let negotiate = |headers: &[(&'static str, &'static str)]| {
+            ResponseOptions::negotiate(&request_with(&[], headers)).envelope
+        };
+        assert_eq!(negotiate(&[]), EnvelopeVersion::V1);
+        assert_eq!(
+            negotiate(&[("x-envelope-version", "1")]),
+            EnvelopeVersion::V1
+        );
+        assert_eq!(
+            negotiate(&[("x-envelope-version", "v2")]),
+            EnvelopeVersion::V2
+        );
+        assert_eq!(
+            negotiate(&[("x-envelope-version", "99")]),
+            EnvelopeVersion::LATEST
+        );
+        assert_eq!(
+            negotiate(&[("x-envelope-version", "0")]),
+            EnvelopeVersion::V1
+        );
+        assert_eq!(
+            negotiate(&[("x-envelope-version", "abc")]),
+            EnvelopeVersion::V1
+        );
+    }
+
+    #[test]
+    fn test_envelope_v2() {
+        let options = ResponseOptions {
+            encoding: PayloadEncoding::Identity,
+            envelope: EnvelopeVersion::V2,
+            ..Default::default()
+        };
+        let body = |response: ApiGatewayProxyResponse| -> Value {
+            match response.body.unwrap() {
+                Body::Text(b) => serde_json::from_str(&b).unwrap(),
+                _ => panic!("Expected response body."),
+            }
+        };
+
+        let result = build_ok_with(
+            MockResponseData {
+                key: "Test value.".to_string(),
+            },
+            &options,
+        )
+        .unwrap();
+        assert_eq!(result.headers.get(ENVELOPE_VERSION_HEADER).unwrap(), "2");
+        assert_eq!(
+            body(result),
+            serde_json::json!({
+                "version": 2,
+                "ok": true,
+                "data": { "key": "Test value." },
+                "format": "json",
+                "encoding": "identity",
+            })
+        );
+
+        define_client_error!(TestClientError, "Client error.");
+        let result = build_err_with(TestClientError::new(), &options).unwrap();
+        assert_eq!(result.status_code, 200);
+        assert_eq!(
+            body(result),
+            serde_json::json!({
+                "version": 2,
+                "ok": false,
+                "error": { "message": "Client error." },
+            })
+        );
+    }
+
+    #[test]
+    fn test_problem_mode() {
+        define_client_error!(TestClientError, "Client error.");
+        define_client_error!(TestConflictError, "Already exists.");
+        let options = ResponseOptions {
+            error_mode: ErrorMode::Problem(ProblemMapping {
+                status_of: Some(|e| (e.message() == "Already exists.").then_some(409)),
+            }),
+            ..Default::default()
+        };
+        let problem = |response: &ApiGatewayProxyResponse| -> Value {
+            match response.body.as_ref().unwrap() {
+                Body::Text(b) => serde_json::from_str(b).unwrap(),
+                _ => panic!("Expected response body."),
+            }
+        };
+
+        let result = build_err_with(TestClientError::new(), &options).unwrap();
+        assert_eq!(result.status_code, 400);
+        assert_eq!(
+            result.headers.get(CONTENT_TYPE).unwrap(),
+            "application/problem+json"
+        );
+        assert_eq!(
+            problem(&result),
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Bad Request",
+                "status": 400,
+                "detail": "Client error.",
+            })
+        );
+
+        let result = build_err_with(TestConflictError::new(), &options).unwrap();
+        assert_eq!(result.status_code, 409);
+
+        let result = build_err_with(UnauthorizedError::new(), &options).unwrap();
+        assert_eq!(result.status_code, 401);
+        assert_eq!(problem(&result)["detail"], UNAUTHORIZED_ERROR_MSG);
+
+        // Callers with credentials lacking the required access are forbidden.
+        let guest = RequestMetadata::from_identity(None, &Default::default());
+        let result = build_err_for(UnauthorizedError::new(), &guest, &options).unwrap();
+        assert_eq!(result.status_code, 401);
+        let user = RequestMetadata::from_identity(
+            Some(Identity {
+                sub: "user-sub".to_string(),
+                ..Default::default()
+            }),
+            &Default::default(),
+        );
+        let result = build_err_for(UnauthorizedError::new(), &user, &options).unwrap();
+        assert_eq!(result.status_code, 403);
+        let result = build_err_for(TestClientError::new(), &user, &options).unwrap();
+        assert_eq!(result.status_code, 400);
+
+        let result = build_err_with(InvalidRouteError::new(Some("x".to_string())), &options);
+        assert_eq!(result.unwrap().status_code, 404);
+        let result = build_err_with(InvalidRequestError::new("missing request body"), &options);
+        assert_eq!(result.unwrap().status_code, 400);
+        let result = build_err_with(InvalidRequestError::new("parsing error"), &options);
+        assert_eq!(result.unwrap().status_code, 422);
+
+        let result = build_err_with(CriticalError::new("test"), &options).unwrap();
+        assert_eq!(result.status_code, 500);
+        assert_eq!(problem(&result)["detail"], INTERNAL_SERVER_ERROR_MSG);
+
+        let result = build_err_with_status(TestClientError::new(), 404, &options).unwrap();
+        assert_eq!(result.status_code, 404);
+        assert_eq!(problem(&result)["title"], "Not Found");
+
+        // Status hints are ignored in envelope mode.
+        let result =
+            build_err_with_status(TestClientError::new(), 404, &ResponseOptions::default())
+                .unwrap();
+        assert_eq!(result.status_code, 200);
+    }
 }
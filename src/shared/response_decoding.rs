@@ -0,0 +1,180 @@
+use std::{fmt, io::Read as _};
+
+use aws_lambda_events::http::{header::CONTENT_TYPE, HeaderMap};
+use base64::Engine as _;
+use flate2::read::GzDecoder;
+use fractic_server_error::ServerError;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+
+use crate::{
+    errors::DecodingError,
+    shared::response_building::{
+        PayloadEncoding, PayloadFormat, PAYLOAD_ENCODING_HEADER, PAYLOAD_FORMAT_HEADER,
+    },
+};
+
+// Client-side response decoding.
+// --------------------------------------------------
+//
+// Inverse of `build_ok` / `build_err`, shared by the clients of this library
+// (`TestClient` and `ApiClient`).
+
+/// Error as seen by the client of the API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientVisibleError {
+    /// 200 for errors forwarded in the response envelope.
+    pub status_code: i64,
+    /// The message shown to the user.
+    pub message: String,
+}
+
+impl fmt::Display for ClientVisibleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.status_code)
+    }
+}
+
+impl std::error::Error for ClientVisibleError {}
+
+/// Error returned by a client call.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The API returned an error (an envelope with `ok == false`, problem
+    /// details, or a non-200 response).
+    Api(ClientVisibleError),
+    /// The request could not be sent, or the response could not be decoded.
+    Request(ServerError),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Api(e) => write!(f, "API error: {e}"),
+            ClientError::Request(e) => write!(f, "Request error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<ServerError> for ClientError {
+    fn from(e: ServerError) -> Self {
+        ClientError::Request(e)
+    }
+}
+
+/// Decodes the payload of a response, in whichever format, encoding and
+/// envelope version it was returned.
+pub(crate) fn decode_response<T: DeserializeOwned>(
+    status_code: i64,
+    headers: &HeaderMap,
+    body: &str,
+) -> Result<T, ClientError> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let api_error = |message: String| {
+        ClientError::Api(ClientVisibleError {
+            status_code,
+            message,
+        })
+    };
+
+    let is_problem =
+        header(CONTENT_TYPE.as_str()).is_some_and(|c| c.starts_with("application/problem+json"));
+    if is_problem {
+        let problem: ProblemBody = serde_json::from_str(body)
+            .map_err(|e| DecodingError::with_debug("problem details", &e))?;
+        return Err(api_error(problem.detail));
+    }
+    if status_code != 200 {
+        return Err(api_error(body.to_string()));
+    }
+
+    let envelope: Value =
+        serde_json::from_str(body).map_err(|e| DecodingError::with_debug("envelope", &e))?;
+    if envelope["ok"] != Value::Bool(true) {
+        // v1 errors are strings, v2 errors are objects.
+        let error = &envelope["error"];
+        return match error.as_str().or_else(|| error["message"].as_str()) {
+            Some(message) => Err(api_error(message.to_string())),
+            None => Err(DecodingError::new("envelope error").into()),
+        };
+    }
+    let format = match header(PAYLOAD_FORMAT_HEADER) {
+        Some(f) => PayloadFormat::parse(f).ok_or_else(|| DecodingError::new("payload format"))?,
+        None => PayloadFormat::default(),
+    };
+    let encoding = match header(PAYLOAD_ENCODING_HEADER) {
+        Some(e) => {
+            PayloadEncoding::parse(e).ok_or_else(|| DecodingError::new("payload encoding"))?
+        }
+        None => PayloadEncoding::default(),
+    };
+    let data = &envelope["data"];
+    let decoded = match (format, encoding) {
+        (PayloadFormat::Json, PayloadEncoding::Identity) => {
+            deserialize(PayloadFormat::Json, data.to_string().as_bytes())?
+        }
+        _ => {
+            let encoded = data
+                .as_str()
+                .ok_or_else(|| DecodingError::new("encoded payload"))?;
+            deserialize(format, &decode_base64(encoded, encoding)?)?
+        }
+    };
+    Ok(decoded)
+}
+
+#[derive(Deserialize)]
+struct ProblemBody {
+    detail: String,
+}
+
+// Helper functions.
+// --------------------------------------------------
+
+/// Reverses `PayloadFormat::serialize`.
+fn deserialize<T: DeserializeOwned>(format: PayloadFormat, bytes: &[u8]) -> Result<T, ServerError> {
+    match format {
+        PayloadFormat::Json => std::str::from_utf8(bytes)
+            .map_err(|e| DecodingError::with_debug("utf-8", &e))
+            .and_then(|s| {
+                serde_json::from_str(s).map_err(|e| DecodingError::with_debug("json", &e))
+            }),
+        #[cfg(feature = "msgpack")]
+        PayloadFormat::MessagePack => {
+            rmp_serde::from_slice(bytes).map_err(|e| DecodingError::with_debug("msgpack", &e))
+        }
+        #[cfg(feature = "cbor")]
+        PayloadFormat::Cbor => {
+            ciborium::from_reader(bytes).map_err(|e| DecodingError::with_debug("cbor", &e))
+        }
+    }
+}
+
+/// Reverses `encode_base64` of `response_building`.
+fn decode_base64(input: &str, encoding: PayloadEncoding) -> Result<Vec<u8>, ServerError> {
+    let compressed = base64::engine::general_purpose::STANDARD
+        .decode(input)
+        .map_err(|e| DecodingError::with_debug("base64", &e))?;
+    let mut out = Vec::new();
+    match encoding {
+        PayloadEncoding::Identity => out = compressed,
+        PayloadEncoding::Gzip => {
+            GzDecoder::new(&compressed[..])
+                .read_to_end(&mut out)
+                .map_err(|e| DecodingError::with_debug("gzip", &e))?;
+        }
+        #[cfg(feature = "brotli")]
+        PayloadEncoding::Brotli => {
+            brotli::BrotliDecompress(&mut &compressed[..], &mut out)
+                .map_err(|e| DecodingError::with_debug("brotli", &e))?;
+        }
+        #[cfg(feature = "zstd")]
+        PayloadEncoding::Zstd => {
+            out = zstd::decode_all(&compressed[..])
+                .map_err(|e| DecodingError::with_debug("zstd", &e))?;
+        }
+    }
+    Ok(out)
+}
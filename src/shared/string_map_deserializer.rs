@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use serde::de::{
+    self,
+    value::{Error as DeError, MapDeserializer, StrDeserializer},
+    Error as _, IntoDeserializer, Visitor,
+};
+
+// String map deserialization.
+// --------------------------------------------------
+//
+// Path and query string parameters always arrive as strings, so a plain
+// string deserializer would reject numeric or boolean fields. Each value is
+// instead parsed on demand into whichever primitive type the target field
+// expects.
+
+pub(crate) fn deserialize_string_map<P>(
+    map: &HashMap<String, String>,
+    ignored: &[&str],
+) -> Result<P, DeError>
+where
+    P: de::DeserializeOwned,
+{
+    let entries = map
+        .iter()
+        .filter(|(k, _)| !ignored.contains(&k.as_str()))
+        .map(|(k, v)| (k.as_str(), StringValueDeserializer(v.as_str())));
+    P::deserialize(MapDeserializer::new(entries))
+}
+
+struct StringValueDeserializer<'a>(&'a str);
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+                visitor.$visit(self.0.parse().map_err(DeError::custom)?)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for StringValueDeserializer<'a> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_str(self.0)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        let variant: StrDeserializer<'a, DeError> = self.0.into_deserializer();
+        visitor.visit_enum(variant)
+    }
+
+    serde::forward_to_deserialize_any! {
+        char str string bytes byte_buf unit unit_struct seq tuple tuple_struct
+        map struct identifier ignored_any
+    }
+}
+
+impl<'de, 'a> IntoDeserializer<'de, DeError> for StringValueDeserializer<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}